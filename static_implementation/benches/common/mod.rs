@@ -0,0 +1,51 @@
+use criterion::measurement::WallTime;
+use criterion::{black_box, BenchmarkGroup, BenchmarkId};
+use z_fast_trie_static_sux::prelude::*;
+
+// one benchmark per operation, so that a regression points to the operation that caused it
+pub fn bench_build<T: Trie>(group: &mut BenchmarkGroup<WallTime>, ds: &mut T, v: &[Str], param: &str) {
+    // a build takes far longer than a query: fewer samples, then back to the default
+    group.sample_size(10);
+    group.bench_with_input(BenchmarkId::new("build", param), v, |b, v| b.iter(|| ds.build(v)));
+    group.sample_size(100);
+    ds.build(v);
+}
+
+pub fn bench_ops<T: Trie>(
+    group: &mut BenchmarkGroup<WallTime>,
+    ds: &T,
+    queries: &[Str],
+    ranges: &[(Str, Str)],
+    param: &str
+) {
+    group.bench_with_input(BenchmarkId::new("pred_queries", param), queries, |b, queries| b.iter(|| {
+        for i in queries {
+            black_box(ds.pred_query(i));
+        }
+    }));
+    group.bench_with_input(BenchmarkId::new("succ_queries", param), queries, |b, queries| b.iter(|| {
+        for i in queries {
+            black_box(ds.succ_query(i));
+        }
+    }));
+    group.bench_with_input(BenchmarkId::new("ex_pref_queries", param), queries, |b, queries| b.iter(|| {
+        for i in queries {
+            black_box(ds.ex_pref_query(i));
+        }
+    }));
+    group.bench_with_input(BenchmarkId::new("rank_queries", param), queries, |b, queries| b.iter(|| {
+        for i in queries {
+            black_box(ds.rank_query(i));
+        }
+    }));
+    group.bench_with_input(BenchmarkId::new("ex_range_queries", param), ranges, |b, ranges| b.iter(|| {
+        for (i, j) in ranges {
+            black_box(ds.ex_range_query(i, j));
+        }
+    }));
+    group.bench_with_input(BenchmarkId::new("count_range_queries", param), ranges, |b, ranges| b.iter(|| {
+        for (i, j) in ranges {
+            black_box(ds.count_range_query(i, j));
+        }
+    }));
+}
@@ -0,0 +1,66 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::prelude::*;
+use std::cmp::Ordering::*;
+use std::env;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::gen_query_key;
+
+mod common;
+use common::*;
+
+type Ds0 = SortedVecTrie;
+type Ds1 = BTreeSetTrie;
+type Ds2 = CompactTrie;
+type Ds3 = ZFastTrie<RollingHash>;
+type Ds4 = ZFastTrieSux<RollingHash>;
+type Ds5 = EliasFanoTrie;
+
+// the datasets in $ZFT_DATASETS (benches/datasets by default, see load_dir for the
+// formats), or synthetic ones if there is none; at most $ZFT_DATASET_KEYS keys each
+fn datasets() -> Vec<Dataset> {
+    let dir = env::var("ZFT_DATASETS").unwrap_or(concat!(env!("CARGO_MANIFEST_DIR"), "/benches/datasets").to_string());
+    let n = env::var("ZFT_DATASET_KEYS").map_or(100000, |x| x.parse().expect("invalid ZFT_DATASET_KEYS"));
+
+    let mut res = load_dir(&dir).expect("unreadable dataset");
+    if res.is_empty() {
+        res = synthetic_datasets(0, n);
+    }
+    res.iter().map(|d| d.sample(n)).collect()
+}
+
+pub fn dataset_bench_trie<T: Trie>(c: &mut Criterion, d: &Dataset, name: &str, ds: &mut T) {
+    let m = 1000;
+    let bits = d.keys.iter().map(|x| x.len()).max().unwrap_or(0);
+    let mut rng = SmallRng::seed_from_u64(0);
+    let queries: Vec<Str> = (0..m).map(|_| gen_query_key(&mut rng, &d.keys, bits)).collect();
+    let ranges: Vec<(Str, Str)> = (0..m)
+        .map(|_| {
+            let x = gen_query_key(&mut rng, &d.keys, bits);
+            let y = gen_query_key(&mut rng, &d.keys, bits);
+            if cmp(&x, &y) == Greater { (y, x) } else { (x, y) }
+        })
+        .collect();
+
+    let mut group = c.benchmark_group(format!("dataset_bench_{}_{}", d.name, name));
+    bench_build(&mut group, ds, &d.keys, &d.keys.len().to_string());
+    bench_ops(&mut group, ds, &queries, &ranges, &d.keys.len().to_string());
+    group.finish();
+}
+
+pub fn dataset_bench(c: &mut Criterion) {
+    for d in datasets() {
+        println!("{}: {} keys, {} bits", d.name, d.keys.len(), d.total_bits());
+        dataset_bench_trie(c, &d, "sorted_vec", &mut Ds0::new());
+        dataset_bench_trie(c, &d, "btree_set", &mut Ds1::new());
+        dataset_bench_trie(c, &d, "compact", &mut Ds2::new());
+        dataset_bench_trie(c, &d, "z_fast", &mut Ds3::with_seed(0));
+        dataset_bench_trie(c, &d, "z_fast_sux", &mut Ds4::with_seed(0));
+        // the elias-fano trie only takes keys of the same length, up to a word
+        if d.fixed_width().is_some_and(|w| w <= WORD_SIZE) {
+            dataset_bench_trie(c, &d, "elias_fano", &mut Ds5::new());
+        }
+    }
+}
+
+criterion_group!(benches, dataset_bench);
+criterion_main!(benches);
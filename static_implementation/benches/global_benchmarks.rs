@@ -1,14 +1,86 @@
-use criterion::{BenchmarkId, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use rand::prelude::*;
-use std::cmp::min;
 use std::cmp::Ordering::*;
 use std::mem::swap;
 use z_fast_trie_static_sux::prelude::*;
 
+mod common;
+use common::*;
+
+type Ds0 = SortedVecTrie;
+type Ds1 = BTreeSetTrie;
 type Ds2 = CompactTrie;
 type Ds3 = ZFastTrie<RollingHash>;
 type Ds4 = ZFastTrieSux<RollingHash>;
 
+pub fn global_bench_sorted_vec_fixed(c: &mut Criterion) {
+    let t = 1;
+    let bits = 10000;
+    let n = 5000;
+    let m = 10;
+    let deb = false;
+    let variablelen = false;
+    let fixed_seed = true;
+
+    let mut x = 200;
+    while x <= n { 
+        let mut ds: Ds0 = Ds0::new();
+        global_bench(t, bits, x, m, deb, variablelen, fixed_seed, &mut ds, c, &("global_bench_sorted_vec_fixed_".to_owned()+&x.to_string()));
+        x += 200;
+    }
+}
+
+pub fn global_bench_sorted_vec_fixed_small(c: &mut Criterion) {
+    let t = 1;
+    let bits = 63;
+    let n = 1000000;
+    let m = 10;
+    let deb = false;
+    let variablelen = false;
+    let fixed_seed = true;
+
+    let mut x = 40000;
+    while x <= n { 
+        let mut ds: Ds0 = Ds0::new();
+        global_bench(t, bits, x, m, deb, variablelen, fixed_seed, &mut ds, c, &("global_bench_sorted_vec_fixed_small_".to_owned()+&x.to_string()));
+        x += 40000;
+    }
+}
+
+pub fn global_bench_btree_set_fixed(c: &mut Criterion) {
+    let t = 1;
+    let bits = 10000;
+    let n = 5000;
+    let m = 10;
+    let deb = false;
+    let variablelen = false;
+    let fixed_seed = true;
+
+    let mut x = 200;
+    while x <= n { 
+        let mut ds: Ds1 = Ds1::new();
+        global_bench(t, bits, x, m, deb, variablelen, fixed_seed, &mut ds, c, &("global_bench_btree_set_fixed_".to_owned()+&x.to_string()));
+        x += 200;
+    }
+}
+
+pub fn global_bench_btree_set_fixed_small(c: &mut Criterion) {
+    let t = 1;
+    let bits = 63;
+    let n = 1000000;
+    let m = 10;
+    let deb = false;
+    let variablelen = false;
+    let fixed_seed = true;
+
+    let mut x = 40000;
+    while x <= n { 
+        let mut ds: Ds1 = Ds1::new();
+        global_bench(t, bits, x, m, deb, variablelen, fixed_seed, &mut ds, c, &("global_bench_btree_set_fixed_small_".to_owned()+&x.to_string()));
+        x += 40000;
+    }
+}
+
 pub fn global_bench_compact_fixed(c: &mut Criterion) {
     let t = 1;
     let bits = 10000;
@@ -131,14 +203,6 @@ fn global_bench_z_fast_sux_fixed_small(c: &mut Criterion) {
 }
 
 
-fn gen_bin_str(rng: &mut SmallRng, n: u32) -> Str {
-    let mut s = Str::new(0);
-    for _ in 0..n {
-        s.push(rng.next_u32() % 2 == 0);
-    }
-    s
-}
-
 pub fn global_bench<T: Trie>(
     t: u32,
     bits: u32,
@@ -155,28 +219,12 @@ pub fn global_bench<T: Trie>(
         if fixed_seed { SmallRng::seed_from_u64(0) } else { SmallRng::seed_from_u64(0) }
     };
 
+    let lengths = {
+        if variablelen { LengthDist::spread(bits as usize) } else { LengthDist::Fixed(bits as usize) }
+    };
+
     for _ in 0..t {
-        let mut v: Vec<Str> = vec![];
-        for _ in 0..n {
-            let len = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
-            let s = gen_bin_str(&mut rng, len);
-            let mut flag = true;
-
-            if variablelen {
-                //da ottimizzare
-                for i in &v {
-                    if get_substr(i,0,min(i.len(), s.len())) == *i || get_substr(i,0,min(i.len(), s.len())) == s {
-                        flag = false;
-                        continue;
-                    }
-                }
-            }
-            if flag {
-                v.push(s);
-            }
-        }
+        let v = KeyGen::new(n as usize, bits as usize).set_lengths(lengths).generate(&mut rng);
 
         if deb {
             print!("genero:\n");
@@ -186,19 +234,13 @@ pub fn global_bench<T: Trie>(
             print!("testo:\n");
         }
         
-        ds.build(&v);
-        
         let mut queries = vec![];
         let mut queries2 = vec![];
         let mut group = c.benchmark_group(name);
         for _i in 0..m {
-            let len = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
+            let len = lengths.sample(&mut rng) as u32;
             let mut s1 = gen_bin_str(&mut rng, len);
-            let len2 = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
+            let len2 = lengths.sample(&mut rng) as u32;
             let mut s2 = gen_bin_str(&mut rng, len2);
             if cmp(&s1,&s2) == Greater {
                 swap(&mut s1, &mut s2);
@@ -209,26 +251,17 @@ pub fn global_bench<T: Trie>(
             queries.push(s1.clone());
             queries2.push((s1.clone(),s2.clone()));
         }
-        group.bench_with_input(BenchmarkId::from_parameter("pred_queries"), &queries, |b, queries| b.iter(|| {
-            for i in queries {
-                ds.pred_query(&i);
-            }
-        }));
-        group.bench_with_input(BenchmarkId::from_parameter("succ_queries"), &queries, |b, queries| b.iter(|| {
-            for i in queries {
-                ds.succ_query(&i);
-            }
-        }));
-        group.bench_with_input(BenchmarkId::from_parameter("ex_range_queries"), &queries2, |b, queries2| b.iter(|| {
-            for (i,j) in queries2 {
-                ds.ex_range_query(&i,&j);
-            }
-        }));
+        bench_build(&mut group, ds, &v, &n.to_string());
+        bench_ops(&mut group, ds, &queries, &queries2, &n.to_string());
         group.finish();
     }
 }
 
 criterion_group!(benches,
+global_bench_sorted_vec_fixed,
+global_bench_sorted_vec_fixed_small,
+global_bench_btree_set_fixed,
+global_bench_btree_set_fixed_small,
 global_bench_compact_fixed,
 global_bench_z_fast_fixed,
 global_bench_z_fast_variable,
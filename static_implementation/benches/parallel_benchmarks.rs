@@ -0,0 +1,47 @@
+use criterion::{BenchmarkId, criterion_group, criterion_main, Criterion};
+use rand::prelude::*;
+use std::thread;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::gen_query_key;
+
+type Ds2 = CompactTrie;
+type Ds4 = ZFastTrieSux<RollingHash>;
+
+fn par_bench<T: Trie + Sync>(c: &mut Criterion, ds: &mut T, name: &str) {
+    let n = 100000;
+    let bits = 256;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let v = KeyGen::new(n, bits).generate(&mut rng);
+    let xs: Vec<Str> = (0..1000000).map(|_| gen_query_key(&mut rng, &v, bits)).collect();
+    ds.build(&v);
+    let ds = &*ds;
+
+    // one thread, a few, and one per core
+    let mut counts = vec![1, 2, 4, thread::available_parallelism().map_or(1, |n| n.get())];
+    counts.sort();
+    counts.dedup();
+
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+    for threads in counts {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, &threads| b.iter(|| {
+            par_query_batch(ds, &xs, threads, |ds, xs| ds.pred_batch(xs))
+        }));
+    }
+    group.finish();
+}
+
+pub fn par_bench_compact(c: &mut Criterion) {
+    par_bench(c, &mut Ds2::new(), "par_bench_compact");
+}
+
+pub fn par_bench_z_fast_sux(c: &mut Criterion) {
+    par_bench(c, &mut Ds4::with_seed(0), "par_bench_z_fast_sux");
+}
+
+criterion_group!(benches,
+par_bench_compact,
+par_bench_z_fast_sux
+);
+criterion_main!(benches);
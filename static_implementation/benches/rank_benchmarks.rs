@@ -0,0 +1,39 @@
+use criterion::{BenchmarkId, criterion_group, criterion_main, Criterion};
+use rand::prelude::*;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::rank_structures::*;
+
+fn gen_bits(rng: &mut SmallRng, n: usize) -> Str {
+    str_from_fn(n, |_| rng.next_u32().is_multiple_of(3))
+}
+
+fn rank_bench<T: RankStructure>(c: &mut Criterion, name: &str) {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut group = c.benchmark_group(name);
+    for n in [1000, 100000, 10000000] {
+        let v0 = gen_bits(&mut rng, n);
+        let v1 = gen_bits(&mut rng, n);
+        let mut ds = T::new();
+        ds.build(&v0, &v1);
+
+        let queries: Vec<usize> = (0..1000).map(|_| (rng.next_u64() as usize) % n).collect();
+        group.bench_with_input(BenchmarkId::from_parameter(n), &queries, |b, queries| b.iter(|| {
+            queries.iter().map(|i| ds.rank(*i, &v0, &v1)).sum::<usize>()
+        }));
+    }
+    group.finish();
+}
+
+pub fn rank_bench_jacobson(c: &mut Criterion) {
+    rank_bench::<JacobsonRank>(c, "rank_bench_jacobson");
+}
+
+pub fn rank_bench_rank9(c: &mut Criterion) {
+    rank_bench::<Rank9>(c, "rank_bench_rank9");
+}
+
+criterion_group!(benches,
+rank_bench_jacobson,
+rank_bench_rank9
+);
+criterion_main!(benches);
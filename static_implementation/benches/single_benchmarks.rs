@@ -4,11 +4,45 @@ use std::cmp::min;
 use std::cmp::Ordering::*;
 use std::mem::swap;
 use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::gen_query_key;
 
+mod common;
+use common::*;
+
+type Ds0 = SortedVecTrie;
+type Ds1 = BTreeSetTrie;
 type Ds2 = CompactTrie;
 type Ds3 = ZFastTrie<RollingHash>;
 type Ds4 = ZFastTrieSux<RollingHash>;
 
+pub fn single_bench_sorted_vec_fixed(c: &mut Criterion) {
+    let t = 1;
+    let bits = 10000;
+    let n = 5000;
+    let m = 10;
+    let deb = false;
+    let variablelen = false;
+    let fixed_seed = true;
+
+    let mut ds: Ds0 = Ds0::new();
+
+    single_bench(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds, c, "single_bench_sorted_vec_fixed");
+}
+
+pub fn single_bench_btree_set_fixed(c: &mut Criterion) {
+    let t = 1;
+    let bits = 10000;
+    let n = 5000;
+    let m = 10;
+    let deb = false;
+    let variablelen = false;
+    let fixed_seed = true;
+
+    let mut ds: Ds1 = Ds1::new();
+
+    single_bench(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds, c, "single_bench_btree_set_fixed");
+}
+
 pub fn single_bench_compact_fixed(c: &mut Criterion) {
     let t = 1;
     let bits = 10000;
@@ -107,13 +141,87 @@ fn single_bench_z_fast_sux_fixed_small(c: &mut Criterion) {
     single_bench(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds, c, &"single_bench_z_fast_sux_fixed_small");
 }
 
+pub fn single_bench_prefix_hash(c: &mut Criterion) {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let s = gen_bin_str(&mut rng, 10000);
+    let hash = RollingHash::with_seed(0);
+    let state = hash.compute_state(s.as_bit_slice());
 
-fn gen_bin_str(rng: &mut SmallRng, n: u32) -> Str {
-    let mut s = Str::new(0);
-    for _ in 0..n {
-        s.push(rng.next_u32() % 2 == 0);
+    let mut group = c.benchmark_group("single_bench_prefix_hash");
+    for ind in [640, 641, 672, 703] {
+        group.bench_with_input(BenchmarkId::new("fast", ind), &ind, |b, ind| b.iter(|| hash.fast_prefix_hash(s.as_bit_slice(), &state, *ind)));
+        group.bench_with_input(BenchmarkId::new("slow", ind), &ind, |b, ind| b.iter(|| hash.slow_prefix_hash(s.as_bit_slice(), *ind)));
     }
-    s
+    group.finish();
+}
+
+pub fn single_bench_locate_exit(c: &mut Criterion) {
+    let n = 5000;
+    let bits = 10000;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let v: Vec<Str> = (0..n).map(|_| gen_bin_str(&mut rng, bits)).collect();
+    let mut ds3: Ds3 = Ds3::with_seed(0);
+    let mut ds4: Ds4 = Ds4::with_seed(0);
+    ds3.build(&v);
+    ds4.build(&v);
+
+    // prefixes ending right before a word boundary are the worst case for bit-by-bit tails
+    let mut group = c.benchmark_group("single_bench_locate_exit");
+    for len in [640, 703, 9600, 9663] {
+        let x = get_substr(&v[0], 0, len);
+        group.bench_with_input(BenchmarkId::new("z_fast", len), &x, |b, x| b.iter(|| ds3.ex_pref_query(x)));
+        group.bench_with_input(BenchmarkId::new("z_fast_sux", len), &x, |b, x| b.iter(|| ds4.ex_pref_query(x)));
+    }
+    group.finish();
+}
+
+pub fn single_bench_batch(c: &mut Criterion) {
+    let n = 5000;
+    let bits = 4000;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    // the batch hashes only what every query adds to the previous one in sorted order
+    let v = KeyGen::new(n, bits).set_shared_prefix(bits * 3 / 4).generate(&mut rng);
+    let xs: Vec<Str> = (0..1000).map(|_| gen_query_key(&mut rng, &v, bits)).collect();
+    let mut ds3: Ds3 = Ds3::with_seed(0);
+    let mut ds4: Ds4 = Ds4::with_seed(0);
+    ds3.build(&v);
+    ds4.build(&v);
+
+    let mut group = c.benchmark_group("single_bench_batch");
+    group.bench_function("z_fast/loop", |b| b.iter(|| xs.iter().map(|x| ds3.pred_query(x)).collect::<Vec<_>>()));
+    group.bench_function("z_fast/batch", |b| b.iter(|| ds3.pred_batch(&xs)));
+    group.bench_function("z_fast_sux/loop", |b| b.iter(|| xs.iter().map(|x| ds4.pred_query(x)).collect::<Vec<_>>()));
+    group.bench_function("z_fast_sux/batch", |b| b.iter(|| ds4.pred_batch(&xs)));
+    group.finish();
+}
+
+pub fn single_bench_compact_build(c: &mut Criterion) {
+    let n = 1000;
+    let bits = 10000;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let random = KeyGen::new(n, bits).generate(&mut rng);
+    // long shared prefixes make every internal node carry a long skip
+    let shared = KeyGen::new(n, bits).set_shared_prefix(bits / 2).generate(&mut rng);
+
+    let mut group = c.benchmark_group("single_bench_compact_build");
+    group.sample_size(10);
+    group.bench_with_input(BenchmarkId::from_parameter("random"), &random, |b, v| b.iter(|| Ds2::new().build(v)));
+    group.bench_with_input(BenchmarkId::from_parameter("shared_prefix"), &shared, |b, v| b.iter(|| Ds2::new().build(v)));
+    group.finish();
+}
+
+pub fn single_bench_get_substr(c: &mut Criterion) {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let s = gen_bin_str(&mut rng, 10000);
+
+    let mut group = c.benchmark_group("single_bench_get_substr");
+    for start in [0, 1, 63] {
+        group.bench_with_input(BenchmarkId::from_parameter(start), &start, |b, start| b.iter(|| get_substr(&s, *start, *start + 5000)));
+    }
+    group.finish();
 }
 
 pub fn single_bench<T: Trie>(
@@ -132,28 +240,12 @@ pub fn single_bench<T: Trie>(
         if fixed_seed { SmallRng::seed_from_u64(0) } else { SmallRng::seed_from_u64(0) }
     };
 
+    let lengths = {
+        if variablelen { LengthDist::spread(bits as usize) } else { LengthDist::Fixed(bits as usize) }
+    };
+
     for _ in 0..t {
-        let mut v: Vec<Str> = vec![];
-        for _ in 0..n {
-            let len = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
-            let s = gen_bin_str(&mut rng, len);
-            let mut flag = true;
-
-            if variablelen {
-                //da ottimizzare
-                for i in &v {
-                    if get_substr(i,0,min(i.len(), s.len())) == *i || get_substr(i,0,min(i.len(), s.len())) == s {
-                        flag = false;
-                        continue;
-                    }
-                }
-            }
-            if flag {
-                v.push(s);
-            }
-        }
+        let v = KeyGen::new(n as usize, bits as usize).set_lengths(lengths).generate(&mut rng);
 
         if deb {
             print!("genero:\n");
@@ -163,18 +255,12 @@ pub fn single_bench<T: Trie>(
             print!("testo:\n");
         }
         
-        ds.build(&v);
-        
-
         let mut group = c.benchmark_group(name);
+        bench_build(&mut group, ds, &v, &n.to_string());
         for _i in 0..m {
-            let len = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
+            let len = lengths.sample(&mut rng) as u32;
             let mut s1 = gen_bin_str(&mut rng, len);
-            let len2 = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
+            let len2 = lengths.sample(&mut rng) as u32;
             let mut s2 = gen_bin_str(&mut rng, len2);
             if cmp(&s1,&s2) == Greater {
                 swap(&mut s1, &mut s2);
@@ -183,45 +269,28 @@ pub fn single_bench<T: Trie>(
                 print!("query: {} & {}\n", s1, s2);
             }
 
-            group.bench_with_input(BenchmarkId::from_parameter(&get_substr(&s1,0,min(20,s1.len()))), &s1, |b, s1| b.iter(|| ds.pred_query(&s1)));
-            //c.bench_function(name,|b| b.iter(|| ds.pred_query(&s1)));
-            
-            /*let pred = ds.pred_query(&s1);
-            if deb {
-                if let Some(t) = pred {
-                    print!("pred: {}\n", t);
-                } else {
-                    print!("non ha predecessori\n");
-                }
-            }
-
-            let succ = ds.succ_query(&s1);
-            if deb {
-                if let Some(t) = succ {
-                    print!("succ: {}\n", t);
-                } else {
-                    print!("non ha successori\n");
-                }
-            }
-
-            let flag = ds.ex_range_query(&s1, &s2);
-            if deb {
-                print!("range query: {}\n", flag);
-                print!("------------\n");
-            }*/
+            let param = format!("{}", get_substr(&s1, 0, min(20, s1.len())));
+            bench_ops(&mut group, ds, &[s1.clone()], &[(s1, s2)], &param);
         }
         group.finish();
     }
 }
 
 criterion_group!(benches,
+single_bench_sorted_vec_fixed,
+single_bench_btree_set_fixed,
 single_bench_compact_fixed,
 single_bench_z_fast_fixed,
 single_bench_z_fast_variable,
 single_bench_z_fast_sux_fixed,
 single_bench_z_fast_sux_variable,
 single_bench_z_fast_fixed_small,
-single_bench_z_fast_sux_fixed_small
+single_bench_z_fast_sux_fixed_small,
+single_bench_prefix_hash,
+single_bench_locate_exit,
+single_bench_batch,
+single_bench_compact_build,
+single_bench_get_substr
 );
 criterion_main!(benches);
 
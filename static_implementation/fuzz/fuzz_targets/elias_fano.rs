@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use z_fast_trie_static_sux::testing::run_fuzz_target;
+
+fuzz_target!(|data: &[u8]| run_fuzz_target("elias_fano", data));
@@ -0,0 +1,197 @@
+use rand::prelude::*;
+use std::env;
+use std::fs;
+use std::io::{ self, BufRead };
+use std::process::exit;
+use std::time::Instant;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::*;
+
+const USAGE: &str = "usage:
+  zft build --input FILE [--encoding bytes|bits|u64] [--impl NAME] [--seed N] [--threads N] --out INDEX
+  zft query INDEX pred|succ|prefix|rank [KEY...]
+  zft query INDEX range|count FROM TO
+  zft stats INDEX
+  zft verify INDEX [--queries N]
+
+an index is a snapshot of the keys and the seed, and every command rebuilds the trie;
+the keys of a query are read one per line from stdin when none is given;
+implementations: naive, compact, z_fast, z_fast_sux (or sux), elias_fano, sorted_vec, btree_set";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = match args.first().map(|s| s.as_str()) {
+        Some("build") => build(&args[1..]),
+        Some("query") => query(&args[1..]),
+        Some("stats") => stats(&args[1..]),
+        Some("verify") => verify(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(e) = res {
+        eprintln!("zft: {}", e);
+        exit(1);
+    }
+}
+
+type Options<'a> = Vec<(&'a str, &'a str)>;
+
+// the positional arguments and the value of every --option
+fn parse_args<'a>(args: &'a [String], options: &[&str]) -> Result<(Vec<&'a str>, Options<'a>), String> {
+    let (mut positional, mut values) = (vec![], vec![]);
+    let mut it = args.iter();
+    while let Some(a) = it.next() {
+        if let Some(name) = a.strip_prefix("--") {
+            if !options.contains(&name) {
+                return Err(format!("unknown option --{}\n{}", name, USAGE));
+            }
+            let value = it.next().ok_or_else(|| format!("missing value for --{}", name))?;
+            values.push((name, value.as_str()));
+        } else {
+            positional.push(a.as_str());
+        }
+    }
+    Ok((positional, values))
+}
+
+fn option<'a>(values: &[(&'a str, &'a str)], name: &str) -> Option<&'a str> {
+    values.iter().rev().find(|(n, _)| *n == name).map(|(_, v)| *v)
+}
+
+fn load(path: &str) -> Result<Index, String> {
+    Index::load(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn build(args: &[String]) -> Result<(), String> {
+    let (positional, values) = parse_args(args, &["input", "encoding", "impl", "seed", "threads", "out"])?;
+    if !positional.is_empty() {
+        return Err(format!("unexpected argument {:?}", positional[0]));
+    }
+    let input = option(&values, "input").ok_or("missing --input")?;
+    let out = option(&values, "out").ok_or("missing --out")?;
+    let encoding = option(&values, "encoding").unwrap_or("bytes");
+    let encoding = Encoding::from_name(encoding).ok_or_else(|| format!("unknown encoding {:?}", encoding))?;
+    let seed = match option(&values, "seed") {
+        Some(s) => s.parse().map_err(|_| format!("invalid seed {:?}", s))?,
+        None => thread_rng().next_u64(),
+    };
+    // 0 builds on every core
+    let threads = match option(&values, "threads") {
+        Some(s) => s.parse().map_err(|_| format!("invalid thread count {:?}", s))?,
+        None => 0,
+    };
+
+    let text = fs::read_to_string(input).map_err(|e| format!("{}: {}", input, e))?;
+    let keys = encoding.parse_keys(&text).map_err(|e| format!("{}: {}", input, e))?;
+    let read = keys.len();
+    let index = Index::new(option(&values, "impl").unwrap_or("z_fast_sux"), encoding, seed, keys)
+        .map_err(|e| e.to_string())?;
+
+    let start = Instant::now();
+    let ds = index.par_build(threads);
+    ds.dyn_validate().map_err(|e| format!("invalid structure: {}", e))?;
+    let elapsed = start.elapsed();
+    index.save(out).map_err(|e| format!("{}: {}", out, e))?;
+
+    println!(
+        "{}: {} keys ({} duplicates or prefixes of other keys dropped), built in {:.3?}",
+        out, index.keys.len(), read - index.keys.len(), elapsed
+    );
+    Ok(())
+}
+
+fn query(args: &[String]) -> Result<(), String> {
+    let (positional, _) = parse_args(args, &[])?;
+    let (path, op) = match positional[..] {
+        [path, op, ..] => (path, op),
+        _ => return Err(USAGE.to_string()),
+    };
+    let index = load(path)?;
+    let mut keys: Vec<String> = positional[2..].iter().map(|s| s.to_string()).collect();
+    if keys.is_empty() {
+        keys = io::stdin().lock().lines().collect::<io::Result<_>>().map_err(|e| e.to_string())?;
+    }
+    let keys: Vec<Str> = keys
+        .iter()
+        .map(|k| if op == "prefix" { index.encoding.parse_prefix(k) } else { index.encoding.parse_key(k) })
+        .collect::<io::Result<_>>()
+        .map_err(|e| e.to_string())?;
+
+    let queries: Vec<Query> = match op {
+        "pred" => keys.into_iter().map(Query::Pred).collect(),
+        "succ" => keys.into_iter().map(Query::Succ).collect(),
+        "prefix" => keys.into_iter().map(Query::ExPref).collect(),
+        "rank" => keys.into_iter().map(Query::Rank).collect(),
+        "range" | "count" => {
+            if !keys.len().is_multiple_of(2) {
+                return Err(format!("{} takes pairs of keys", op));
+            }
+            let pairs = keys.chunks(2).map(|p| (p[0].clone(), p[1].clone()));
+            if op == "range" {
+                pairs.map(|(x, y)| Query::ExRange(x, y)).collect()
+            } else {
+                pairs.map(|(x, y)| Query::CountRange(x, y)).collect()
+            }
+        }
+        _ => return Err(format!("unknown query {:?}\n{}", op, USAGE)),
+    };
+
+    let ds = index.build();
+    for q in &queries {
+        match ds.dyn_answer(q) {
+            Answer::Key(Some(k)) => println!("{}", index.encoding.format_key(&k)),
+            Answer::Key(None) => println!("none"),
+            a => println!("{}", a),
+        }
+    }
+    Ok(())
+}
+
+fn stats(args: &[String]) -> Result<(), String> {
+    let (positional, _) = parse_args(args, &[])?;
+    let path = *positional.first().ok_or(USAGE)?;
+    let index = load(path)?;
+    let bytes = fs::metadata(path).map_err(|e| e.to_string())?.len() as usize;
+
+    let start = Instant::now();
+    let ds = index.build();
+    let elapsed = start.elapsed();
+    let stats = ds.dyn_stats();
+
+    println!("implementation    {}", index.implementation);
+    println!("encoding          {}", index.encoding.name());
+    println!("seed              {}", index.seed);
+    println!("key bits          {}", index.keys.iter().map(|x| x.len()).sum::<usize>());
+    println!("{}", stats);
+    println!("snapshot bytes    {}", bytes);
+    println!("build time        {:.3?}", elapsed);
+    Ok(())
+}
+
+// rebuilds the trie, checks its invariants and compares it with a BTreeSet on every key
+// and on random queries around them
+fn verify(args: &[String]) -> Result<(), String> {
+    let (positional, values) = parse_args(args, &["queries"])?;
+    let path = *positional.first().ok_or(USAGE)?;
+    let m = match option(&values, "queries") {
+        Some(s) => s.parse().map_err(|_| format!("invalid number of queries {:?}", s))?,
+        None => 10000,
+    };
+    let index = load(path)?;
+    let mut ds = new_trie(index.implementation, index.seed).unwrap();
+
+    let mut queries: Vec<Query> = index.keys.iter().flat_map(|k| [Query::Succ(k.clone()), Query::Rank(k.clone())]).collect();
+    let bits = index.keys.iter().map(|x| x.len()).max().unwrap_or(0);
+    let mut rng = SmallRng::seed_from_u64(index.seed);
+    queries.extend(gen_queries(&mut rng, &index.keys, m, bits.max(1)));
+
+    if let Some(f) = Differential::new(index.seed).add(index.implementation, ds.as_mut()).check(&index.keys, &queries) {
+        let query = f.query.map_or(String::new(), |q| format!(" on {}", q));
+        return Err(format!("{}: {}{}", path, f.message, query));
+    }
+    println!("{}: ok, {} keys and {} queries checked", path, index.keys.len(), queries.len());
+    Ok(())
+}
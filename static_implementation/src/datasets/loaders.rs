@@ -0,0 +1,130 @@
+use crate::generators::*;
+use crate::utils::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// strings are stored byte by byte, the most significant bit first, so that the trie
+// order is the byte order; a terminating zero byte keeps "ab" and "abc" prefix free
+pub const TERMINATOR: u8 = 0;
+
+pub struct Dataset {
+    pub name: String,
+    pub keys: Vec<Str>,
+}
+
+impl Dataset {
+    // keys sorted and prefix free, whatever order and duplicates the source had
+    pub fn new(name: &str, keys: Vec<Str>) -> Dataset {
+        Dataset { name: name.to_string(), keys: make_prefix_free(keys) }
+    }
+
+    // at most n keys taken at regular intervals, so that the distribution is kept
+    pub fn sample(&self, n: usize) -> Dataset {
+        if self.keys.len() <= n {
+            return Dataset { name: self.name.clone(), keys: self.keys.clone() };
+        }
+        let keys = (0..n).map(|i| self.keys[i * self.keys.len() / n].clone()).collect();
+        Dataset { name: self.name.clone(), keys }
+    }
+
+    // the width of the keys, if they all have the same length
+    pub fn fixed_width(&self) -> Option<usize> {
+        let width = self.keys.first().map_or(0, |x| x.len());
+        if self.keys.iter().all(|x| x.len() == width) { Some(width) } else { None }
+    }
+
+    pub fn total_bits(&self) -> usize {
+        self.keys.iter().map(|x| x.len()).sum()
+    }
+}
+
+pub fn str_from_text(s: &str) -> Str {
+    let mut res = str_from_bytes(s.as_bytes(), BitOrder::MsbFirst);
+    push_int(&mut res, TERMINATOR as usize, 8, BitOrder::MsbFirst);
+    res
+}
+
+// inverse of str_from_text, for printing the keys of a text dataset
+pub fn str_to_text(x: impl AsBitSlice) -> String {
+    let x = x.as_bit_slice();
+    let bytes: Vec<u8> = (0..x.len() / 8)
+        .map(|i| str_to_int(x.slice(8 * i, 8 * i + 8), BitOrder::MsbFirst) as u8)
+        .take_while(|&b| b != TERMINATOR)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned())
+}
+
+// one key per line; empty lines are keys too
+pub fn parse_lines(text: &str) -> Vec<Str> {
+    text.lines().map(str_from_text).collect()
+}
+
+// the first field of every line, skipping blank lines and # comments
+pub fn parse_urls(text: &str) -> Vec<Str> {
+    text.lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|url| !url.starts_with('#'))
+        .map(str_from_text)
+        .collect()
+}
+
+// little endian u64, preceded by their count if `sosd` (the SOSD format); every key
+// has 64 bits, the most significant first, so the trie order is the integer order
+pub fn parse_u64s(bytes: &[u8], sosd: bool) -> io::Result<Vec<Str>> {
+    if !bytes.len().is_multiple_of(8) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "length not a multiple of 8 bytes"));
+    }
+    let mut values: Vec<u64> = bytes.chunks(8).map(|c| u64::from_le_bytes(c.try_into().unwrap())).collect();
+    if sosd {
+        if values.first().is_none_or(|&k| k as usize + 1 != values.len()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "SOSD count doesn't match the length"));
+        }
+        values.remove(0);
+    }
+    Ok(values.into_iter().map(|x| str_from_int(x as usize, WORD_SIZE, BitOrder::MsbFirst)).collect())
+}
+
+pub fn load_lines(path: impl AsRef<Path>) -> io::Result<Dataset> {
+    let path = path.as_ref();
+    Ok(Dataset::new(&file_name(path), parse_lines(&fs::read_to_string(path)?)))
+}
+
+pub fn load_urls(path: impl AsRef<Path>) -> io::Result<Dataset> {
+    let path = path.as_ref();
+    Ok(Dataset::new(&file_name(path), parse_urls(&fs::read_to_string(path)?)))
+}
+
+// the SOSD count is expected only in .sosd files
+pub fn load_u64s(path: impl AsRef<Path>) -> io::Result<Dataset> {
+    let path = path.as_ref();
+    let sosd = path.extension().is_some_and(|e| e == "sosd");
+    Ok(Dataset::new(&file_name(path), parse_u64s(&fs::read(path)?, sosd)?))
+}
+
+// every dataset in dir, by extension: .txt lines, .urls url lists, .u64 and .bin
+// binary u64, .sosd binary u64 after their count; the other files are ignored, and a missing directory is empty
+pub fn load_dir(dir: impl AsRef<Path>) -> io::Result<Vec<Dataset>> {
+    let dir = dir.as_ref();
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut paths: Vec<_> = fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<io::Result<_>>()?;
+    paths.sort();
+
+    let mut res = vec![];
+    for path in paths {
+        let ext = path.extension().map_or(String::new(), |e| e.to_string_lossy().into_owned());
+        match ext.as_str() {
+            "txt" => res.push(load_lines(&path)?),
+            "urls" => res.push(load_urls(&path)?),
+            "u64" | "bin" | "sosd" => res.push(load_u64s(&path)?),
+            _ => {}
+        }
+    }
+    Ok(res)
+}
@@ -0,0 +1,5 @@
+pub mod loaders;
+pub use loaders::*;
+
+pub mod synthetic;
+pub use synthetic::*;
@@ -0,0 +1,74 @@
+use crate::datasets::loaders::*;
+use crate::generators::*;
+use crate::utils::*;
+use rand::prelude::*;
+
+// lettere in ordine di frequenza in inglese
+const LETTERS: &[u8] = b"etaoinshrdlcumwfgypbvkjxqz";
+const TLDS: [&str; 6] = ["com", "org", "net", "it", "de", "io"];
+
+// index in 0..n, the small ones far more likely
+fn skewed_index<R: RngCore>(rng: &mut R, n: usize) -> usize {
+    let a = rng.next_u32() as usize % n;
+    let b = rng.next_u32() as usize % n;
+    a.min(b)
+}
+
+fn gen_word<R: RngCore>(rng: &mut R) -> String {
+    let len = LengthDist::Geometric { min: 2, max: 15, p: 0.75 }.sample(rng);
+    (0..len).map(|_| LETTERS[skewed_index(rng, LETTERS.len())] as char).collect()
+}
+
+// words with english-like letter frequencies, so that the keys share short prefixes
+pub fn synthetic_words<R: RngCore>(rng: &mut R, n: usize) -> Vec<Str> {
+    (0..n).map(|_| str_from_text(&gen_word(rng))).collect()
+}
+
+// urls on few hosts with a handful of path segments, so that the keys share long prefixes
+pub fn synthetic_urls<R: RngCore>(rng: &mut R, n: usize) -> Vec<Str> {
+    let hosts: Vec<String> = (0..(n / 20).max(1))
+        .map(|_| {
+            let www = if rng.next_u32().is_multiple_of(2) { "www." } else { "" };
+            let tld = TLDS[skewed_index(rng, TLDS.len())];
+            format!("https://{}{}.{}", www, gen_word(rng), tld)
+        })
+        .collect();
+    (0..n)
+        .map(|_| {
+            let mut url = hosts[skewed_index(rng, hosts.len())].clone();
+            for _ in 0..rng.next_u32() % 5 {
+                url.push('/');
+                url.push_str(&gen_word(rng));
+            }
+            str_from_text(&url)
+        })
+        .collect()
+}
+
+// sorted integers in clusters of close values, as ids and timestamps are, with
+// large jumps between the clusters
+pub fn synthetic_u64s<R: RngCore>(rng: &mut R, n: usize) -> Vec<Str> {
+    let mut res = Vec::with_capacity(n);
+    let mut x = rng.next_u64() >> 8;
+    let mut gap_bits = 1;
+    for _ in 0..n {
+        if rng.next_u32().is_multiple_of(1000) {
+            x = x.wrapping_add(rng.next_u64() >> 16);
+            gap_bits = 1 + rng.next_u32() % 20;
+        }
+        x = x.wrapping_add(1 + rng.next_u64() % (1 << gap_bits));
+        res.push(str_from_int(x as usize, WORD_SIZE, BitOrder::MsbFirst));
+    }
+    res
+}
+
+// the stand-ins for the real datasets, plus uniformly random keys for comparison
+pub fn synthetic_datasets(seed: u64, n: usize) -> Vec<Dataset> {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    vec![
+        Dataset::new("synthetic_words", synthetic_words(&mut rng, n)),
+        Dataset::new("synthetic_urls", synthetic_urls(&mut rng, n)),
+        Dataset::new("synthetic_u64", synthetic_u64s(&mut rng, n)),
+        Dataset::new("random_u64", KeyGen::new(n, WORD_SIZE).generate(&mut rng)),
+    ]
+}
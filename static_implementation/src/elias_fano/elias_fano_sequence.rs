@@ -0,0 +1,91 @@
+use crate::rank_structures::*;
+use crate::traits::*;
+use crate::utils::str::*;
+use std::cmp::max;
+use sux::prelude::*;
+
+pub struct EliasFano<R: RankSelect = SampledSelect<Rank9>> {
+    n: usize,
+    low_bits: usize,
+    lower: BitFieldVec<usize>,
+    upper: R,
+}
+
+impl<R: RankSelect> EliasFano<R> {
+    pub fn from_sorted<I: IntoIterator<Item = usize>>(iter: I) -> EliasFano<R> {
+        let v: Vec<usize> = iter.into_iter().collect();
+        assert!(v.windows(2).all(|w| w[0] <= w[1]), "Build error: sequence is not sorted");
+
+        let n = v.len();
+        let last = v.last().copied().unwrap_or(0);
+        let low_bits = {
+            if n == 0 || last < n { 0 } else { (last / n).ilog2() as usize }
+        };
+
+        let mut upper = Str::new(n + (last >> low_bits) + 1);
+        let mut lower = BitFieldVec::<usize>::with_capacity(max(1, low_bits), n);
+        for (i, x) in v.iter().enumerate() {
+            upper.set((x >> low_bits) + i, true);
+            lower.push(x & ((1 << low_bits) - 1));
+        }
+
+        EliasFano { n, low_bits, lower, upper: R::from_bits(upper) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    // bits of the rank and select directory of the upper bits
+    pub fn directory_bits(&self) -> usize {
+        self.upper.overhead_bits()
+    }
+
+    pub fn get(&self, i: usize) -> usize {
+        assert!(i < self.n);
+        let high = self.upper.select1(i).unwrap() - i;
+        (high << self.low_bits) | self.lower.get(i)
+    }
+
+    // number of elements strictly smaller than x
+    pub fn rank(&self, x: usize) -> usize {
+        let h = x >> self.low_bits;
+        let buckets = self.upper.bits().len() - self.n;
+        if h >= buckets {
+            return self.n;
+        }
+
+        // the elements with high part h are exactly those in [l, r)
+        let mut l = if h == 0 { 0 } else { self.upper.select0(h - 1).unwrap() - (h - 1) };
+        let mut r = self.upper.select0(h).unwrap() - h;
+        while l < r {
+            let mid = (l + r) / 2;
+            if self.get(mid) < x {
+                l = mid + 1;
+            } else {
+                r = mid;
+            }
+        }
+        l
+    }
+
+    // smallest element greater than or equal to x
+    pub fn successor(&self, x: usize) -> Option<usize> {
+        let i = self.rank(x);
+        if i < self.n { Some(self.get(i)) } else { None }
+    }
+
+    // largest element strictly smaller than x
+    pub fn predecessor(&self, x: usize) -> Option<usize> {
+        let i = self.rank(x);
+        if i > 0 { Some(self.get(i - 1)) } else { None }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.n).map(move |i| self.get(i))
+    }
+}
@@ -0,0 +1,2 @@
+mod elias_fano_sequence;
+pub use elias_fano_sequence::*;
@@ -0,0 +1,144 @@
+use crate::utils::*;
+use rand::RngCore;
+
+// how many times generate() tops the set up after dropping the keys that clash
+const MAX_ROUNDS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LengthDist {
+    Fixed(usize),
+    // every length in lo..=hi with the same probability
+    Uniform(usize, usize),
+    // min plus as many extra bits as the successes before the first failure, at most max
+    Geometric { min: usize, max: usize, p: f64 },
+}
+
+impl LengthDist {
+    // lengths between bits/4 and bits, the distribution the variable length tests always used
+    pub fn spread(bits: usize) -> LengthDist {
+        let lo = bits / 4;
+        LengthDist::Uniform(lo, lo + ((bits / 4) * 3).max(1) - 1)
+    }
+
+    pub fn sample<R: RngCore>(&self, rng: &mut R) -> usize {
+        match *self {
+            LengthDist::Fixed(len) => len,
+            LengthDist::Uniform(lo, hi) => {
+                assert!(lo <= hi, "LengthDist::Uniform: empty range");
+                lo + (rng.next_u64() % ((hi - lo) as u64 + 1)) as usize
+            }
+            LengthDist::Geometric { min, max, p } => {
+                let mut len = min;
+                while len < max && gen_bool(rng, p) {
+                    len += 1;
+                }
+                len
+            }
+        }
+    }
+}
+
+fn gen_bool<R: RngCore>(rng: &mut R, p: f64) -> bool {
+    ((rng.next_u64() >> 11) as f64) < p * (1u64 << 53) as f64
+}
+
+// n random bits, each one set with probability one_prob
+pub fn gen_skewed_str<R: RngCore>(rng: &mut R, n: usize, one_prob: f64) -> Str {
+    let mut res = Str::new(0);
+    if one_prob == 0.5 {
+        // una parola alla volta
+        let mut left = n;
+        while left > 0 {
+            let bits = left.min(WORD_SIZE);
+            push_bits(&mut res, rng.next_u64() as usize, bits);
+            left -= bits;
+        }
+    } else {
+        for _ in 0..n {
+            res.push(gen_bool(rng, one_prob));
+        }
+    }
+    res
+}
+
+// random key sets with no key prefix of another, in O(n log n) expected time:
+// the keys are generated independently, then sorted, and every key that has its
+// neighbour as prefix is dropped and generated again
+#[derive(Clone, Debug)]
+pub struct KeyGen {
+    pub n: usize,
+    pub lengths: LengthDist,
+    // number of leading bits that all the keys have in common
+    pub shared_prefix: usize,
+    // probability of a one in the bits after the shared prefix
+    pub one_prob: f64,
+}
+
+impl KeyGen {
+    pub fn new(n: usize, bits: usize) -> KeyGen {
+        KeyGen {
+            n,
+            lengths: LengthDist::Fixed(bits),
+            shared_prefix: 0,
+            one_prob: 0.5,
+        }
+    }
+
+    pub fn set_lengths(&mut self, lengths: LengthDist) -> &mut Self {
+        self.lengths = lengths;
+        self
+    }
+
+    pub fn set_shared_prefix(&mut self, depth: usize) -> &mut Self {
+        self.shared_prefix = depth;
+        self
+    }
+
+    pub fn set_skew(&mut self, one_prob: f64) -> &mut Self {
+        assert!((0.0..=1.0).contains(&one_prob), "KeyGen::set_skew: not a probability");
+        self.one_prob = one_prob;
+        self
+    }
+
+    // sorted and prefix free; fewer than n keys only when there aren't enough
+    // distinct ones (e.g. short fixed lengths) or they keep clashing
+    pub fn generate<R: RngCore>(&self, rng: &mut R) -> Vec<Str> {
+        let prefix = gen_skewed_str(rng, self.shared_prefix, 0.5);
+        let mut res: Vec<Str> = vec![];
+        for _ in 0..MAX_ROUNDS {
+            let before = res.len();
+            if before >= self.n {
+                break;
+            }
+            for _ in before..self.n {
+                let len = self.lengths.sample(rng).max(self.shared_prefix);
+                let mut s = prefix.clone();
+                append(&mut s, gen_skewed_str(rng, len - self.shared_prefix, self.one_prob));
+                res.push(s);
+            }
+            res = make_prefix_free(res);
+            if res.len() <= before {
+                break;
+            }
+        }
+        res
+    }
+}
+
+// sorts v and drops every key that has a previous one as prefix (duplicates included)
+pub fn make_prefix_free(mut v: Vec<Str>) -> Vec<Str> {
+    v.sort_by(cmp);
+    let mut res: Vec<Str> = Vec::with_capacity(v.len());
+    for s in v {
+        if !res.last().is_some_and(|p| is_prefix_of(p, &s)) {
+            res.push(s);
+        }
+    }
+    res
+}
+
+pub fn is_prefix_free(v: &[Str]) -> bool {
+    let mut v = v.to_vec();
+    v.sort_by(cmp);
+    v.windows(2).all(|w| !is_prefix_of(&w[0], &w[1]))
+}
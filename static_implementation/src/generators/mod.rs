@@ -0,0 +1,2 @@
+pub mod key_sets;
+pub use key_sets::*;
@@ -1,4 +1,5 @@
 use crate::traits::*;
+use crate::utils::bit_slice::*;
 use crate::utils::str::*;
 
 pub struct NaiveHash;
@@ -12,26 +13,27 @@ impl Hash for NaiveHash {
         NaiveHash {}
     }
 
+    fn with_seed(_seed: u64) -> Self {
+        NaiveHash {}
+    }
+
     fn hash(&self, s: &Self::DomainType) -> Self::HashType {
-        self.slow_prefix_hash(s, s.len())
+        self.slow_prefix_hash(s.as_bit_slice(), s.len())
     }
 
-    fn slow_prefix_hash(&self, s: &Self::DomainType, ind: usize) -> Self::HashType {
-        let mut res : Self::HashType = vec![];
-        for i in get_substr(s,0,ind).as_ref() {
-            res.push(*i);
+    fn slow_prefix_hash(&self, s: BitSlice, ind: usize) -> Self::HashType {
+        assert!(ind <= s.len());
+        let mut res: Self::HashType = (0..ind / WORD_SIZE).map(|q| s.get_word(q * WORD_SIZE)).collect();
+        if !ind.is_multiple_of(WORD_SIZE) {
+            res.push(get_tail_word(s, ind));
         }
+        res.push(ind);
         res
     }
 
-    fn compute_state(&self, _s: &Self::DomainType) {}
+    fn compute_state(&self, _s: BitSlice) {}
 
-    fn fast_prefix_hash(
-        &self,
-        s: &Self::DomainType,
-        _state: &Self::State,
-        ind: usize
-    ) -> Self::HashType {
+    fn fast_prefix_hash(&self, s: BitSlice, _state: &Self::State, ind: usize) -> Self::HashType {
         self.slow_prefix_hash(s, ind)
     }
 }
@@ -1,130 +1,169 @@
 use crate::traits::*;
+use crate::utils::bit_slice::*;
 use crate::utils::str::*;
 use rand::prelude::*;
 
 const DEFAULT_MODULO: usize = 1000000000 + 7;
-const DEFAULT_BASE: usize = 37;
+const MIXER_MULT: u64 = 0x9e3779b97f4a7c15;
 
 pub struct RollingHash {
     modulo: usize,
     base: usize,
+    seed: u64,
+    mixer: u64,
+}
+
+pub struct RollingHashState {
+    prefix_hashes: Vec<usize>,
+    pots: Vec<usize>,
 }
 
 impl Hash for RollingHash {
     type DomainType = Str;
     type HashType = usize;
-    type State = (Vec<usize>, Vec<usize>);
+    type State = RollingHashState;
 
     fn new() -> Self {
-        RollingHash { base: DEFAULT_BASE, modulo: DEFAULT_MODULO }
+        Self::with_seed(thread_rng().next_u64())
+    }
+
+    fn with_seed(seed: u64) -> Self {
+        // la base deve stare in [2, modulo - 1]: con base 0 o 1 l'hash degenera
+        let num = ((SmallRng::seed_from_u64(seed).next_u64() as usize) % (DEFAULT_MODULO - 2)) + 2;
+        RollingHash::from_parameters(num, DEFAULT_MODULO, seed)
     }
 
     fn hash(&self, s: &Self::DomainType) -> Self::HashType {
-        self.slow_prefix_hash(s, s.len())
+        self.slow_prefix_hash(s.as_bit_slice(), s.len())
     }
 
-    fn slow_prefix_hash(&self, s: &Self::DomainType, ind: usize) -> Self::HashType {
+    fn slow_prefix_hash(&self, s: BitSlice, ind: usize) -> Self::HashType {
         assert!(ind <= s.len());
         let mut res: Self::HashType = self.base;
         let mut pot: Self::HashType = (self.base * self.base) % self.modulo;
-        let mut currind: usize = 0;
-
-        for x in s.as_ref() {
-            if currind + WORD_SIZE > ind {
-                break;
-            }
-            currind += WORD_SIZE;
-
-            res += (((x + 1) % self.modulo) * pot) % self.modulo;
-            res %= self.modulo;
-
-            pot *= self.base;
-            pot %= self.modulo;
-        }
-	
-        while currind < ind {
-            res += pot;
-            res %= self.modulo;
-            if s[currind] {
-                res += pot;
-                res %= self.modulo;
-            }
-
-            pot *= self.base;
-            pot %= self.modulo;
 
-            currind += 1;
+        for q in 0..ind / WORD_SIZE {
+            res = self.fold_word(res, pot, s.get_word(q * WORD_SIZE));
+            pot = (pot * self.base) % self.modulo;
         }
 
-        res
+        self.fold_tail(res, pot, get_tail_word(s, ind), ind % WORD_SIZE)
     }
 
-    fn compute_state(&self, s: &Self::DomainType) -> Self::State {
+    fn compute_state(&self, s: BitSlice) -> Self::State {
         let mut res: Self::HashType = self.base;
         let mut pot: Self::HashType = (self.base * self.base) % self.modulo;
 
-        let mut v = vec![res];
-        let mut pots = vec![pot];
+        let words = s.len() / WORD_SIZE + 1;
+        let mut state = RollingHashState { prefix_hashes: Vec::with_capacity(words), pots: Vec::with_capacity(words) };
+        state.prefix_hashes.push(res);
+        state.pots.push(pot);
 
-        for x in s.as_ref() {
-            res += (((x + 1) % self.modulo) * pot) % self.modulo;
-            res %= self.modulo;
-            v.push(res);
+        for q in 0..s.len() / WORD_SIZE {
+            res = self.fold_word(res, pot, s.get_word(q * WORD_SIZE));
+            state.prefix_hashes.push(res);
 
-            pot *= self.base;
-            pot %= self.modulo;
-            pots.push(pot);
+            pot = (pot * self.base) % self.modulo;
+            state.pots.push(pot);
         }
 
-        (v, pots)
+        state
     }
 
-    fn fast_prefix_hash(
-        &self,
-        s: &Self::DomainType,
-        state: &Self::State,
-        ind: usize
-    ) -> Self::HashType {
-        let mut res: Self::HashType = state.0[ind / WORD_SIZE];
-        let mut pot: Self::HashType = state.1[ind / WORD_SIZE];
+    // the hashes of the words in the first lcp bits are shared, and the vectors of the
+    // state are reused
+    fn update_state(&self, s: BitSlice, mut state: Self::State, lcp: usize) -> Self::State {
+        let q = lcp / WORD_SIZE;
+        state.prefix_hashes.truncate(q + 1);
+        state.pots.truncate(q + 1);
+        let (mut res, mut pot) = (state.prefix_hashes[q], state.pots[q]);
 
-        let mut currind = (ind / WORD_SIZE) * WORD_SIZE;
+        for q in q..s.len() / WORD_SIZE {
+            res = self.fold_word(res, pot, s.get_word(q * WORD_SIZE));
+            state.prefix_hashes.push(res);
 
-        while currind < ind {
-            res += pot;
-            if s[currind] {
-                res += pot;
-            }
-
-            pot *= self.base;
-            pot %= self.modulo;
-
-            currind += 1;
+            pot = (pot * self.base) % self.modulo;
+            state.pots.push(pot);
         }
 
-        res % self.modulo
+        state
+    }
+
+    fn fast_prefix_hash(&self, s: BitSlice, state: &Self::State, ind: usize) -> Self::HashType {
+        let q = ind / WORD_SIZE;
+        self.fold_tail(
+            state.prefix_hashes[q],
+            state.pots[q],
+            get_tail_word(s, ind),
+            ind % WORD_SIZE
+        )
     }
 }
 
 impl ParametricHash for RollingHash {
     fn new_parametric(domain_size: usize, seed: u64) -> Self {
+        assert!(domain_size > 2 && domain_size <= RollingHash::MAX_MODULO, "Invalid RollingHash domain size");
         let num = ((SmallRng::seed_from_u64(seed).next_u32() as usize) % (domain_size - 1)) + 1;
-        RollingHash {
-            base: RollingHash::get_coprime(num, domain_size),
-            modulo: domain_size,
-        }
+        // the coprime part of num may be 1, and domain_size - 1 is always coprime
+        let base = RollingHash::get_coprime(num, domain_size);
+        let base = if base < 2 { domain_size - 1 } else { base };
+        RollingHash::from_parameters(base, domain_size, seed)
     }
 
     fn new_random(domain_size: usize) -> Self {
-        let num = ((SmallRng::seed_from_u64(0).next_u32() as usize) % (domain_size - 1)) + 1;
-        RollingHash {
-            base: RollingHash::get_coprime(num, domain_size),
-            modulo: domain_size,
-        }
+        Self::new_parametric(domain_size, thread_rng().next_u64())
     }
 }
 
 impl RollingHash {
+    // products of two values below the modulo must fit in a word
+    pub const MAX_MODULO: usize = 1 << 32;
+
+    // base 0 makes every prefix hash the same, base 1 leaves only the sum of the words
+    pub fn from_parameters(base: usize, modulo: usize, seed: u64) -> Self {
+        assert!(
+            modulo > 2 && modulo <= RollingHash::MAX_MODULO && base >= 2 && base < modulo,
+            "Invalid RollingHash parameters"
+        );
+        RollingHash { base, modulo, seed, mixer: seed.wrapping_mul(MIXER_MULT) | 1 }
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn modulo(&self) -> usize {
+        self.modulo
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // seed-dependent scrambling of a word before its reduction modulo `modulo`: the
+    // product and the xor-shift are bijective on 64 bits but the reduction is not, so
+    // two words still collide with probability about 1 / modulo for a given seed; it
+    // only keeps words congruent modulo `modulo` from colliding for every seed at once
+    fn mix(&self, x: usize) -> usize {
+        let y = (x as u64).wrapping_mul(self.mixer);
+        ((y ^ (y >> 32)) as usize) % self.modulo
+    }
+
+    fn fold_word(&self, res: usize, pot: usize, x: usize) -> usize {
+        (res + ((self.mix(x) + 1) % self.modulo) * pot) % self.modulo
+    }
+
+    // folds the last `bits` bits of the prefix in one step; the length term keeps
+    // prefixes differing only by trailing zeros apart
+    fn fold_tail(&self, res: usize, pot: usize, x: usize, bits: usize) -> usize {
+        if bits == 0 {
+            res
+        } else {
+            let next_pot = (pot * self.base) % self.modulo;
+            (self.fold_word(res, pot, x) + (bits * next_pot) % self.modulo) % self.modulo
+        }
+    }
+
     fn get_coprime(num: usize, domain_size: usize) -> usize {
         let mut res = num;
         loop {
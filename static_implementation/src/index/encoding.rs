@@ -0,0 +1,71 @@
+use crate::datasets::*;
+use crate::utils::*;
+use std::io;
+
+// how the lines of a key file, and the keys given to zft query, become bit strings
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    // the bytes of the line and a terminating zero, as in the text datasets
+    Bytes,
+    // the line is a binary string
+    Bits,
+    // the line is a decimal u64, stored in 64 bits
+    U64,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Bytes, Encoding::Bits, Encoding::U64];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Bytes => "bytes",
+            Encoding::Bits => "bits",
+            Encoding::U64 => "u64",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        Encoding::ALL.into_iter().find(|e| e.name() == name)
+    }
+
+    pub fn parse_key(&self, s: &str) -> io::Result<Str> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        match self {
+            Encoding::Bytes => Ok(str_from_text(s)),
+            Encoding::Bits => str_from_bin(s).map_err(|e| invalid(format!("{:?}: {}", s, e))),
+            Encoding::U64 => {
+                let x: u64 = s.trim().parse().map_err(|e| invalid(format!("{:?}: {}", s, e)))?;
+                Ok(str_from_int(x as usize, WORD_SIZE, BitOrder::MsbFirst))
+            }
+        }
+    }
+
+    // the bits a key starting with s starts with: a text key without its terminator
+    pub fn parse_prefix(&self, s: &str) -> io::Result<Str> {
+        match self {
+            Encoding::Bytes => Ok(str_from_bytes(s.as_bytes(), BitOrder::MsbFirst)),
+            _ => self.parse_key(s),
+        }
+    }
+
+    // one key per line; blank lines are empty keys, except for u64 where they are skipped
+    pub fn parse_keys(&self, text: &str) -> io::Result<Vec<Str>> {
+        text.lines()
+            .filter(|line| *self != Encoding::U64 || !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                self.parse_key(line).map_err(|e| io::Error::new(e.kind(), format!("key {}: {}", i + 1, e)))
+            })
+            .collect()
+    }
+
+    // inverse of parse_key; keys that the encoding cannot produce are printed as bits
+    pub fn format_key(&self, x: impl AsBitSlice) -> String {
+        let x = x.as_bit_slice();
+        match self {
+            Encoding::Bytes if x.len().is_multiple_of(8) => str_to_text(x),
+            Encoding::U64 if x.len() == WORD_SIZE => str_to_int(x, BitOrder::MsbFirst).to_string(),
+            _ => format!("{}", x),
+        }
+    }
+}
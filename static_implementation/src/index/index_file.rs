@@ -0,0 +1,168 @@
+use crate::generators::*;
+use crate::hashes::*;
+use crate::index::encoding::*;
+use crate::testing::*;
+use crate::tries::*;
+use crate::utils::*;
+use std::fs;
+use std::io::{ self, BufReader, BufWriter, Read, Write };
+use std::path::Path;
+
+pub const MAGIC: &[u8; 4] = b"ZFT1";
+
+// the implementations zft can build, by the name stored in the index
+pub const TRIE_IMPLS: [&str; 7] = ["naive", "compact", "z_fast", "z_fast_sux", "elias_fano", "sorted_vec", "btree_set"];
+
+// the canonical name of an implementation, "sux" being short for z_fast_sux
+pub fn trie_impl(name: &str) -> Option<&'static str> {
+    let name = if name == "sux" { "z_fast_sux" } else { name };
+    TRIE_IMPLS.into_iter().find(|&i| i == name)
+}
+
+pub fn new_trie(name: &str, seed: u64) -> Option<Box<dyn DynTrie>> {
+    let res: Box<dyn DynTrie> = match trie_impl(name)? {
+        "naive" => Box::new(NaiveTrie::new()),
+        "compact" => Box::new(CompactTrie::new()),
+        "z_fast" => Box::new(ZFastTrie::<RollingHash>::with_seed(seed)),
+        "z_fast_sux" => Box::new(ZFastTrieSux::<RollingHash>::with_seed(seed)),
+        "elias_fano" => Box::new(EliasFanoTrie::new()),
+        "sorted_vec" => Box::new(SortedVecTrie::new()),
+        _ => Box::new(BTreeSetTrie::new()),
+    };
+    Some(res)
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+// the tries have no serialized form: the index is a snapshot of the keys and the seed,
+// and the trie is rebuilt whenever it is loaded, the same one since the build only
+// depends on them (the hashes tried after a collision are drawn from the seed too);
+// its size is the size of the keys, not of the trie
+pub struct Index {
+    pub implementation: &'static str,
+    pub encoding: Encoding,
+    pub seed: u64,
+    pub keys: Vec<Str>,
+}
+
+impl Index {
+    // the keys are sorted and made prefix free, as for the datasets
+    pub fn new(implementation: &str, encoding: Encoding, seed: u64, keys: Vec<Str>) -> io::Result<Index> {
+        let implementation = trie_impl(implementation)
+            .ok_or_else(|| invalid(format!("unknown implementation {:?}", implementation)))?;
+        let keys = make_prefix_free(keys);
+        if implementation == "elias_fano" {
+            let width = keys.first().map_or(0, |x| x.len());
+            if width > WORD_SIZE || keys.iter().any(|x| x.len() != width) {
+                return Err(invalid("elias_fano needs keys of the same length, at most 64 bits"));
+            }
+        }
+        Ok(Index { implementation, encoding, seed, keys })
+    }
+
+    pub fn build(&self) -> Box<dyn DynTrie> {
+        let mut res = new_trie(self.implementation, self.seed).unwrap();
+        res.dyn_build(&self.keys);
+        res
+    }
+
+    // the same trie, built on `threads` threads, 0 for one per core
+    // the z-fast tries build on threads only through their own par_build, which needs a
+    // hash that can be shared
+    pub fn par_build(&self, threads: usize) -> Box<dyn DynTrie> {
+        match self.implementation {
+            "z_fast" => {
+                let mut res = ZFastTrie::<RollingHash>::with_seed(self.seed);
+                res.par_build(&self.keys, threads);
+                Box::new(res)
+            }
+            "z_fast_sux" => {
+                let mut res = ZFastTrieSux::<RollingHash>::with_seed(self.seed);
+                res.par_build(&self.keys, threads);
+                Box::new(res)
+            }
+            _ => {
+                let mut res = new_trie(self.implementation, self.seed).unwrap();
+                res.dyn_par_build(&self.keys, threads);
+                res
+            }
+        }
+    }
+
+    // the magic, the implementation, the encoding and the seed, then every key as its
+    // length and its bits packed in bytes, the first one in the most significant bit
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[self.implementation.len() as u8])?;
+        w.write_all(self.implementation.as_bytes())?;
+        w.write_all(&[Encoding::ALL.iter().position(|&e| e == self.encoding).unwrap() as u8])?;
+        w.write_all(&self.seed.to_le_bytes())?;
+        w.write_all(&(self.keys.len() as u64).to_le_bytes())?;
+        for k in &self.keys {
+            w.write_all(&(k.len() as u64).to_le_bytes())?;
+            let k = k.as_bit_slice();
+            for i in (0..k.len()).step_by(8) {
+                let bits = (k.len() - i).min(8);
+                w.write_all(&[(str_to_int(k.slice(i, i + bits), BitOrder::MsbFirst) << (8 - bits)) as u8])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from(r: &mut impl Read) -> io::Result<Index> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a zft index"));
+        }
+        let mut name = vec![0u8; read_u8(r)? as usize];
+        r.read_exact(&mut name)?;
+        let name = String::from_utf8_lossy(&name).into_owned();
+        let encoding = *Encoding::ALL
+            .get(read_u8(r)? as usize)
+            .ok_or_else(|| invalid("unknown encoding"))?;
+        let seed = read_u64(r)?;
+
+        let n = read_u64(r)? as usize;
+        let mut keys = Vec::with_capacity(n.min(1 << 20));
+        for _ in 0..n {
+            // the length is not trusted: the bytes are read as far as the input goes
+            let len = read_u64(r)?;
+            let mut bytes = vec![];
+            r.by_ref().take(len.div_ceil(8)).read_to_end(&mut bytes)?;
+            if (bytes.len() as u64) < len.div_ceil(8) {
+                return Err(invalid("key longer than the index"));
+            }
+            let len = len as usize;
+            keys.push(get_substr(str_from_bytes(&bytes, BitOrder::MsbFirst), 0, len));
+        }
+        if !is_prefix_free(&keys) {
+            return Err(invalid("the keys are not sorted and prefix free"));
+        }
+        Index::new(&name, encoding, seed, keys)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(fs::File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Index> {
+        Index::read_from(&mut BufReader::new(fs::File::open(path)?))
+    }
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
@@ -0,0 +1,5 @@
+pub mod encoding;
+pub use encoding::*;
+
+pub mod index_file;
+pub use index_file::*;
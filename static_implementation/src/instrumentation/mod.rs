@@ -0,0 +1,2 @@
+pub mod query_stats;
+pub use query_stats::*;
@@ -0,0 +1,128 @@
+use crate::utils::histogram::*;
+use std::cell::RefCell;
+use std::fmt;
+
+// the counters are only updated with the instrumentation feature: without it record
+// is empty and inlined, and trace always returns zeros
+pub const ENABLED: bool = cfg!(feature = "instrumentation");
+
+// what the z-fast tries did to answer the queries of a trace
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct QueryStats {
+    // searches of an exit node
+    pub exit_searches: usize,
+    // z-map lookups of locate_exit_or_parex_prob, and the ones that found a node
+    pub z_map_probes: usize,
+    pub z_map_hits: usize,
+    // nodes found in the z-map whose handle did not match
+    pub false_hits: usize,
+    // searches that fell back to locate_parex, and its z-map lookups
+    pub parex_fallbacks: usize,
+    pub parex_probes: usize,
+    // jump pointers followed by is_nonempty
+    pub jump_hops: usize,
+}
+
+impl QueryStats {
+    pub fn add(&mut self, other: &QueryStats) {
+        self.exit_searches += other.exit_searches;
+        self.z_map_probes += other.z_map_probes;
+        self.z_map_hits += other.z_map_hits;
+        self.false_hits += other.false_hits;
+        self.parex_fallbacks += other.parex_fallbacks;
+        self.parex_probes += other.parex_probes;
+        self.jump_hops += other.jump_hops;
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<QueryStats> = RefCell::new(QueryStats::default());
+}
+
+#[inline(always)]
+pub fn record(f: impl FnOnce(&mut QueryStats)) {
+    #[cfg(feature = "instrumentation")]
+    CURRENT.with(|s| f(&mut s.borrow_mut()));
+    #[cfg(not(feature = "instrumentation"))]
+    let _ = f;
+}
+
+// runs f and returns what the queries it made on this thread recorded; an enclosing
+// trace sees them too
+pub fn trace<R>(f: impl FnOnce() -> R) -> (R, QueryStats) {
+    let outer = CURRENT.with(|s| s.replace(QueryStats::default()));
+    let res = f();
+    let stats = CURRENT.with(|s| {
+        let mut s = s.borrow_mut();
+        let stats = *s;
+        *s = outer;
+        s.add(&stats);
+        stats
+    });
+    (res, stats)
+}
+
+// the distribution of every counter of QueryStats over the queries of a workload
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WorkloadStats {
+    pub queries: usize,
+    pub exit_searches: Histogram,
+    pub z_map_probes: Histogram,
+    pub z_map_hits: Histogram,
+    pub false_hits: Histogram,
+    pub parex_fallbacks: Histogram,
+    pub parex_probes: Histogram,
+    pub jump_hops: Histogram,
+}
+
+impl WorkloadStats {
+    pub fn new() -> WorkloadStats {
+        WorkloadStats {
+            queries: 0,
+            exit_searches: Histogram::new(),
+            z_map_probes: Histogram::new(),
+            z_map_hits: Histogram::new(),
+            false_hits: Histogram::new(),
+            parex_fallbacks: Histogram::new(),
+            parex_probes: Histogram::new(),
+            jump_hops: Histogram::new(),
+        }
+    }
+
+    pub fn add(&mut self, s: &QueryStats) {
+        self.queries += 1;
+        self.exit_searches.add(s.exit_searches);
+        self.z_map_probes.add(s.z_map_probes);
+        self.z_map_hits.add(s.z_map_hits);
+        self.false_hits.add(s.false_hits);
+        self.parex_fallbacks.add(s.parex_fallbacks);
+        self.parex_probes.add(s.parex_probes);
+        self.jump_hops.add(s.jump_hops);
+    }
+
+    // traces f as one query of the workload
+    pub fn trace<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        let (res, stats) = trace(f);
+        self.add(&stats);
+        res
+    }
+}
+
+impl Default for WorkloadStats {
+    fn default() -> WorkloadStats {
+        WorkloadStats::new()
+    }
+}
+
+impl fmt::Display for WorkloadStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "queries          {}", self.queries)?;
+        writeln!(f, "exit searches    {}", self.exit_searches)?;
+        writeln!(f, "z-map probes     {}", self.z_map_probes)?;
+        writeln!(f, "z-map hits       {}", self.z_map_hits)?;
+        writeln!(f, "false hits       {}", self.false_hits)?;
+        writeln!(f, "parex fallbacks  {}", self.parex_fallbacks)?;
+        writeln!(f, "parex probes     {}", self.parex_probes)?;
+        write!(f, "jump hops        {}", self.jump_hops)
+    }
+}
@@ -1,22 +1,34 @@
+pub mod datasets;
+pub mod elias_fano;
+pub mod generators;
 pub mod hashes;
+pub mod index;
+pub mod instrumentation;
 pub mod rank_structures;
 pub mod static_dicts;
+pub mod testing;
 pub mod traits;
 pub mod tries;
 pub mod utils;
 
 pub mod prelude {
+    pub use crate::datasets::*;
+    pub use crate::elias_fano::*;
+    pub use crate::generators::*;
     pub use crate::hashes::*;
+    pub use crate::index::*;
+    pub use crate::instrumentation;
     pub use crate::rank_structures;
     pub use crate::static_dicts::*;
+    pub use crate::testing;
     pub use crate::traits::*;
     pub use crate::tries::*;
     pub use crate::utils::*;
+    pub use crate::bits;
 }
 
 use crate::prelude::*;
 use rand::prelude::*;
-use std::cmp::min;
 use std::cmp::Ordering::*;
 use std::mem::swap;
 
@@ -53,7 +65,7 @@ mod random_tests {
         let variablelen = false;
         let fixed_seed = true;
 
-        let mut ds: Ds3 = Ds3::new();
+        let mut ds: Ds3 = Ds3::with_seed(0);
 
         test(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds);
     }
@@ -68,7 +80,7 @@ mod random_tests {
         let variablelen = true;
         let fixed_seed = true;
 
-        let mut ds: Ds3 = Ds3::new();
+        let mut ds: Ds3 = Ds3::with_seed(0);
 
         test(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds);
     }
@@ -83,7 +95,7 @@ mod random_tests {
         let variablelen = false;
         let fixed_seed = true;
 
-        let mut ds: Ds4 = Ds4::new();
+        let mut ds: Ds4 = Ds4::with_seed(0);
 
         test(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds);
     }
@@ -98,7 +110,7 @@ mod random_tests {
         let variablelen = true;
         let fixed_seed = true;
 
-        let mut ds: Ds4 = Ds4::new();
+        let mut ds: Ds4 = Ds4::with_seed(0);
 
         test(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds);
     }
@@ -113,7 +125,7 @@ mod random_tests {
         let variablelen = false;
         let fixed_seed = true;
 
-        let mut ds: Ds3 = Ds3::new();
+        let mut ds: Ds3 = Ds3::with_seed(0);
 
         test(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds);
     }
@@ -128,20 +140,12 @@ mod random_tests {
         let variablelen = false;
         let fixed_seed = true;
 
-        let mut ds: Ds4 = Ds4::new();
+        let mut ds: Ds4 = Ds4::with_seed(0);
 
         test(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds);
     }
 }
 
-fn gen_bin_str(rng: &mut SmallRng, n: u32) -> Str {
-    let mut s = Str::new(0);
-    for _ in 0..n {
-        s.push(rng.next_u32() % 2 == 0);
-    }
-    s
-}
-
 pub fn test<T: Trie>(
     t: u32,
     bits: u32,
@@ -156,28 +160,12 @@ pub fn test<T: Trie>(
         if fixed_seed { SmallRng::seed_from_u64(0) } else { SmallRng::from_rng(thread_rng()) }
     };
 
+    let lengths = {
+        if variablelen { LengthDist::spread(bits as usize) } else { LengthDist::Fixed(bits as usize) }
+    };
+
     for _ in 0..t {
-        let mut v: Vec<Str> = vec![];
-        for _ in 0..n {
-            let len = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
-            let s = gen_bin_str(&mut rng, len);
-            let mut flag = true;
-
-            if variablelen {
-                //da ottimizzare
-                for i in &v {
-                    if get_substr(i,0,min(i.len(), s.len())) == *i || get_substr(i,0,min(i.len(), s.len())) == s {
-                        flag = false;
-                        continue;
-                    }
-                }
-            }
-            if flag {
-                v.push(s);
-            }
-        }
+        let v = KeyGen::new(n as usize, bits as usize).set_lengths(lengths).generate(&mut rng);
 
         if deb {
             print!("genero:\n");
@@ -188,15 +176,12 @@ pub fn test<T: Trie>(
         }
 
         ds.build(&v);
+        ds.validate().expect("invalid structure after build");
 
         for _ in 0..m {
-            let len = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
+            let len = lengths.sample(&mut rng) as u32;
             let mut s1 = gen_bin_str(&mut rng, len);
-            let len2 = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
+            let len2 = lengths.sample(&mut rng) as u32;
             let mut s2 = gen_bin_str(&mut rng, len2);
             if cmp(&s1,&s2) == Greater {
                 swap(&mut s1, &mut s2);
@@ -1,6 +1,6 @@
 use crate::traits::*;
 use crate::utils::str::*;
-use std::cmp::min;
+use std::cmp::{ max, min };
 use sux::prelude::*;
 
 pub struct JacobsonRank {
@@ -42,8 +42,11 @@ impl RankStructure for JacobsonRank {
         }
 
         self.n = sequence.len() + 1;
-        self.block_dim = (0.5 * (self.n as f64).log2()).ceil() as usize;
-        self.super_block_dim = ((self.n as f64).log2() * (self.n as f64).log2()).ceil() as usize;
+        self.block_dim = max(1, (0.5 * (self.n as f64).log2()).ceil() as usize);
+        self.super_block_dim = max(
+            self.block_dim,
+            ((self.n as f64).log2() * (self.n as f64).log2()).ceil() as usize
+        );
         while self.super_block_dim % self.block_dim != 0 {
             self.super_block_dim += 1;
         }
@@ -86,6 +89,7 @@ impl RankStructure for JacobsonRank {
                     self.super_block_ranks.get((i * self.block_dim) / self.super_block_dim)
             );
         }
+        self.partial_ranks.clear();
         for i in 0..1 << self.block_dim {
             self.partial_ranks.push(Self::compute_ranklist(i, self.block_dim, self.block_bits));
         }
@@ -103,6 +107,11 @@ impl RankStructure for JacobsonRank {
                 )
             ].get(i % self.block_dim)) as usize
     }
+
+    fn directory_bits(&self) -> usize {
+        let bits = |v: &BitFieldVec<usize>| v.len() * v.bit_width();
+        bits(&self.block_ranks) + bits(&self.super_block_ranks) + self.partial_ranks.iter().map(bits).sum::<usize>()
+    }
 }
 
 impl JacobsonRank {
@@ -3,3 +3,9 @@ pub use naive_rank::*;
 
 pub mod jacobson_rank;
 pub use jacobson_rank::*;
+
+pub mod sampled_select;
+pub use sampled_select::*;
+
+pub mod rank9;
+pub use rank9::*;
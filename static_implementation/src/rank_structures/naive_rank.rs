@@ -1,11 +1,13 @@
 use crate::traits::*;
 use crate::utils::str::*;
 
-pub struct NaiveRank;
+pub struct NaiveRank {
+    bits: Str,
+}
 
 impl RankStructure for NaiveRank {
     fn new() -> Self {
-        NaiveRank {}
+        NaiveRank { bits: Str::new(0) }
     }
 
     fn build(&mut self, _v0: &Str, _v1: &Str) {}
@@ -20,4 +22,63 @@ impl RankStructure for NaiveRank {
         }
         res
     }
+
+    fn directory_bits(&self) -> usize {
+        0
+    }
+}
+
+impl RankSelect for NaiveRank {
+    fn from_bits(v: Str) -> Self {
+        NaiveRank { bits: v }
+    }
+
+    fn bits(&self) -> &Str {
+        &self.bits
+    }
+
+    fn count_ones(&self) -> usize {
+        self.rank1(self.bits.len())
+    }
+
+    fn rank1(&self, ind: usize) -> usize {
+        assert!(ind <= self.bits.len());
+        let mut res = 0;
+        for i in 0..ind {
+            if self.bits[i] {
+                res += 1;
+            }
+        }
+        res
+    }
+
+    fn select1(&self, k: usize) -> Option<usize> {
+        let mut cont = 0;
+        for i in 0..self.bits.len() {
+            if self.bits[i] {
+                if cont == k {
+                    return Some(i);
+                }
+                cont += 1;
+            }
+        }
+        None
+    }
+
+    fn select0(&self, k: usize) -> Option<usize> {
+        let mut cont = 0;
+        for i in 0..self.bits.len() {
+            if !self.bits[i] {
+                if cont == k {
+                    return Some(i);
+                }
+                cont += 1;
+            }
+        }
+        None
+    }
+
+    fn overhead_bits(&self) -> usize {
+        0
+    }
 }
@@ -0,0 +1,64 @@
+use crate::traits::*;
+use crate::utils::str::*;
+
+const BLOCKS_PER_SUPER_BLOCK: usize = 8;
+const BLOCK_COUNT_BITS: usize = 9;
+
+// counts[2 * s] is the rank at the start of super block s, counts[2 * s + 1] packs
+// the ranks of its words 1..7 relative to it, 9 bits each
+pub struct Rank9 {
+    counts: Vec<usize>,
+}
+
+impl RankStructure for Rank9 {
+    fn new() -> Self {
+        Rank9 { counts: vec![] }
+    }
+
+    fn build(&mut self, v0: &Str, v1: &Str) {
+        assert!(v0.len() == v1.len());
+        let (refv0, refv1) = (v0.as_ref(), v1.as_ref());
+        let words = v0.len().div_ceil(WORD_SIZE);
+        let super_blocks = words / BLOCKS_PER_SUPER_BLOCK + 1;
+
+        self.counts = Vec::with_capacity(2 * super_blocks);
+        let mut tot = 0;
+        for s in 0..super_blocks {
+            let mut rel = 0;
+            let mut packed = 0;
+            for j in 0..BLOCKS_PER_SUPER_BLOCK {
+                if j > 0 {
+                    packed |= rel << (BLOCK_COUNT_BITS * (j - 1));
+                }
+                let w = s * BLOCKS_PER_SUPER_BLOCK + j;
+                if w < words {
+                    rel += (refv0[w] | refv1[w]).count_ones() as usize;
+                }
+            }
+            self.counts.push(tot);
+            self.counts.push(packed);
+            tot += rel;
+        }
+    }
+
+    fn rank(&self, ind: usize, v0: &Str, v1: &Str) -> usize {
+        let w = ind / WORD_SIZE;
+        let s = w / BLOCKS_PER_SUPER_BLOCK;
+        let j = w % BLOCKS_PER_SUPER_BLOCK;
+
+        let mut res = self.counts[2 * s];
+        if j > 0 {
+            res += (self.counts[2 * s + 1] >> (BLOCK_COUNT_BITS * (j - 1))) &
+                ((1 << BLOCK_COUNT_BITS) - 1);
+        }
+        let bits = ind % WORD_SIZE;
+        if bits > 0 {
+            res += ((v0.as_ref()[w] | v1.as_ref()[w]) & ((1 << bits) - 1)).count_ones() as usize;
+        }
+        res
+    }
+
+    fn directory_bits(&self) -> usize {
+        self.counts.len() * WORD_SIZE
+    }
+}
@@ -0,0 +1,94 @@
+use crate::traits::*;
+use crate::utils::str::*;
+use super::JacobsonRank;
+use std::cmp::max;
+
+pub struct SampledSelect<R: RankStructure = JacobsonRank> {
+    bits: Str,
+    ones: usize,
+    rank: R,
+    sample_rate: usize,
+    select1_samples: Vec<usize>,
+    select0_samples: Vec<usize>,
+}
+
+impl<R: RankStructure> RankSelect for SampledSelect<R> {
+    fn from_bits(v: Str) -> Self {
+        let n = v.len() + 1;
+        let sample_rate = max(1, ((n as f64).log2() * (n as f64).log2()).ceil() as usize);
+
+        let mut rank = R::new();
+        rank.build(&v, &v);
+
+        let mut select1_samples = vec![];
+        let mut select0_samples = vec![];
+        let (mut ones, mut zeros) = (0, 0);
+        for (i, x) in (&v).into_iter().enumerate() {
+            if x {
+                if ones % sample_rate == 0 {
+                    select1_samples.push(i);
+                }
+                ones += 1;
+            } else {
+                if zeros % sample_rate == 0 {
+                    select0_samples.push(i);
+                }
+                zeros += 1;
+            }
+        }
+
+        SampledSelect { bits: v, ones, rank, sample_rate, select1_samples, select0_samples }
+    }
+
+    fn bits(&self) -> &Str {
+        &self.bits
+    }
+
+    fn count_ones(&self) -> usize {
+        self.ones
+    }
+
+    fn rank1(&self, ind: usize) -> usize {
+        assert!(ind <= self.bits.len());
+        self.rank.rank(ind, &self.bits, &self.bits)
+    }
+
+    fn select1(&self, k: usize) -> Option<usize> {
+        if k >= self.ones {
+            None
+        } else {
+            Some(self.select(k, &self.select1_samples, |i| self.rank1(i)))
+        }
+    }
+
+    fn select0(&self, k: usize) -> Option<usize> {
+        if k >= self.bits.len() - self.ones {
+            None
+        } else {
+            Some(self.select(k, &self.select0_samples, |i| self.rank0(i)))
+        }
+    }
+
+    fn overhead_bits(&self) -> usize {
+        self.rank.directory_bits() + (self.select1_samples.len() + self.select0_samples.len()) * WORD_SIZE
+    }
+}
+
+impl<R: RankStructure> SampledSelect<R> {
+    // the k-th bit lies between two consecutive samples: binary search on the rank
+    fn select<F: Fn(usize) -> usize>(&self, k: usize, samples: &[usize], rank: F) -> usize {
+        let s = k / self.sample_rate;
+        let mut l = samples[s];
+        let mut r = if s + 1 < samples.len() { samples[s + 1] } else { self.bits.len() - 1 };
+
+        while l < r {
+            let mid = (l + r) / 2;
+            if rank(mid + 1) > k {
+                r = mid;
+            } else {
+                l = mid + 1;
+            }
+        }
+        l
+    }
+}
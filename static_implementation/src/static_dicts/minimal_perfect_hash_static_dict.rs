@@ -1,20 +1,35 @@
 use crate::traits::*;
 use crate::rank_structures::*;
+use crate::utils::bit_slice::*;
+use crate::utils::par_build::*;
+use rand::prelude::*;
 use std::cmp::max;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 use sux::prelude::*;
 
-type RankDS = JacobsonRank;
+// rank_benchmarks, 1000 ranks on 10^3 / 10^5 / 10^7 bits:
+// JacobsonRank 41 / 69 / 95 us, Rank9 6.0 / 4.6 / 8.0 us
+type RankDS = Rank9;
 const GAMMA: f64 = 1.23;
 
+// number of vertices of the hypergraph, and of cells of the table, for n keys
+pub fn mphf_table_size(n: usize) -> usize {
+    max((GAMMA * (n as f64)) as usize, 101)
+}
+
 pub struct MinimalPerfectHashStaticDict<K, V, H: ParametricHash<DomainType = K>> {
     size: usize,
+    seed: u64,
     h: [H; 3],
     table: Vec<V>,
     w0: BitVec<Vec<usize>>,
     w1: BitVec<Vec<usize>>,
     ds: RankDS,
+    // keys given to the last build
+    len: usize,
+    // hypergraphs tried by the last build
+    attempts: usize,
 }
 
 impl<K, V: Clone, H: ParametricHash<DomainType = K>> StaticDict<K, V, H>
@@ -22,33 +37,123 @@ for MinimalPerfectHashStaticDict<K, V, H> {
     type State = [H::State; 3];
 
     fn new() -> MinimalPerfectHashStaticDict<K, V, H> {
+        Self::with_seed(thread_rng().next_u64())
+    }
+
+    fn with_seed(seed: u64) -> MinimalPerfectHashStaticDict<K, V, H> {
         MinimalPerfectHashStaticDict::<K, V, H> {
             size: 0,
+            seed,
             h: [H::new(), H::new(), H::new()],
             table: vec![],
             w0: BitVec::new(0),
             w1: BitVec::new(0),
             ds: RankDS::new(),
+            len: 0,
+            attempts: 0,
         }
     }
 
     fn build(&mut self, keys: &Vec<K>, values: &Vec<V>) {
-        assert!(keys.len() == values.len());        
+        self.build_with(keys, values, |_| None);
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        if self.table.is_empty() {
+            return None;
+        }
+        let mut res = 0;
+        for i in &self.h {
+            res += self.get_w(i.hash(key));
+            if res >= 3 {
+                res -= 3;
+            }
+        }
+
+        let pos = self.ds.rank(self.h[res].hash(key), &self.w0, &self.w1);
+        Some(&self.table[pos])
+    }
+
+    fn compute_state(&self, key: BitSlice) -> Self::State {
+        [self.h[0].compute_state(key), self.h[1].compute_state(key), self.h[2].compute_state(key)]
+    }
+
+    fn update_state(&self, key: BitSlice, state: Self::State, lcp: usize) -> Self::State {
+        let [s0, s1, s2] = state;
+        [
+            self.h[0].update_state(key, s0, lcp),
+            self.h[1].update_state(key, s1, lcp),
+            self.h[2].update_state(key, s2, lcp),
+        ]
+    }
+
+    fn fast_prefix_get(&self, key: BitSlice, state: &Self::State, ind: usize) -> Option<&V> {
+        if self.table.is_empty() {
+            return None;
+        }
+        let mut res = 0;
+        for (h, s) in self.h.iter().zip(state.iter()) {
+            res += self.get_w(h.fast_prefix_hash(key, s, ind));
+            if res >= 3 {
+                res -= 3;
+            }
+        }
+
+        let pos = self.ds.rank(
+            self.h[res].fast_prefix_hash(key, &state[res], ind),
+            &self.w0,
+            &self.w1
+        );
+        Some(&self.table[pos])
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl<K: Sync, V: Clone, H: ParametricHash<DomainType = K> + Sync> MinimalPerfectHashStaticDict<K, V, H> {
+    // the same as build, the edges of the keys being hashed on `threads` threads
+    pub fn par_build(&mut self, keys: &[K], values: &[V], threads: usize) {
+        if thread_count(threads) == 1 {
+            return self.build_with(keys, values, |_| None);
+        }
+        self.build_with(keys, values, |h| Some(par_map(keys, threads, |_| 1, |x| get_edge(h, x))));
+    }
+}
+
+fn get_edge<K, H: ParametricHash<DomainType = K>>(h: &[H; 3], x: &K) -> [usize; 3] {
+    [h[0].hash(x), h[1].hash(x), h[2].hash(x)]
+}
+
+impl<K, V: Clone, H: ParametricHash<DomainType = K>> MinimalPerfectHashStaticDict<K, V, H> {
+    // the edges of the hypergraph are the three hashes of every key; par_edges gives them
+    // all for the hash functions of each attempt, or None to hash them one at a time,
+    // stopping at the first edge with a repeated vertex
+    fn build_with(&mut self, keys: &[K], values: &[V], par_edges: impl Fn(&[H; 3]) -> Option<Vec<[usize; 3]>>) {
+        assert!(keys.len() == values.len());
+        self.len = keys.len();
+        self.attempts = 0;
+        if keys.is_empty() {
+            self.size = 0;
+            self.table = vec![];
+            return;
+        }
         self.size = (GAMMA * (keys.len() as f64)) as usize;
-        let n = max(self.size, 101);
+        let n = mphf_table_size(keys.len());
 
-        let mut cc = 0;
+        let mut rng = SmallRng::seed_from_u64(self.seed);
         loop {
+            self.attempts += 1;
             for i in &mut self.h {
-                *i = H::new_parametric(n, cc);
-                cc += 2;
+                *i = H::new_parametric(n, rng.next_u64());
             }
 
+            let edges = par_edges(&self.h);
             let mut edgelists = vec![HashSet::<[usize;3]>::new();n];
-            let get_edge = |x: &K| { [self.h[0].hash(x), self.h[1].hash(x), self.h[2].hash(x)] };
             let mut ok = true;
-            for i in keys {
-                let edge = get_edge(i);
+            for i in 0..keys.len() {
+                let edge = edges.as_ref().map_or_else(|| get_edge(&self.h, &keys[i]), |e| e[i]);
                 if edge[0] == edge[1] || edge[0] == edge[2] || edge[1] == edge[2] {
                     ok = false;
                     break;
@@ -93,8 +198,9 @@ for MinimalPerfectHashStaticDict<K, V, H> {
                 continue;
             }
 
-            self.w0.resize(n, false);
-            self.w1.resize(n, false);
+            // from scratch, a previous build may have left its values
+            self.w0 = BitVec::new(n);
+            self.w1 = BitVec::new(n);
             for (i, j) in peeling_sequence.iter().rev() {
                 let mut sum = 0;
                 for k in j {
@@ -118,56 +224,42 @@ for MinimalPerfectHashStaticDict<K, V, H> {
             self.table.resize(n, values[0].clone());
             for i in 0..keys.len() {
                 let mut sum = 0;
-                let key = &keys[i];
-                for j in &self.h {
-                    sum += self.get_w(j.hash(key));
+                let edge = edges.as_ref().map_or_else(|| get_edge(&self.h, &keys[i]), |e| e[i]);
+                for j in edge {
+                    sum += self.get_w(j);
                     if sum >= 3 {
                         sum -= 3;
                     }
                 }
-                let ind = self.ds.rank(self.h[sum].hash(key), &self.w0, &self.w1);
+                let ind = self.ds.rank(edge[sum], &self.w0, &self.w1);
                 self.table[ind] = values[i].clone();
             }
             break;
         }
     }
+}
 
-    fn get(&self, key: &K) -> Option<&V> {
-        let mut res = 0;
-        for i in &self.h {
-            res += self.get_w(i.hash(key));
-            if res >= 3 {
-                res -= 3;
-            }
-        }
+impl<K, V, H: ParametricHash<DomainType = K>> MinimalPerfectHashStaticDict<K, V, H> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-        let pos = self.ds.rank(self.h[res].hash(key), &self.w0, &self.w1);
-        Some(&self.table[pos])
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
-    fn compute_state(&self, key: &K) -> Self::State {
-        [self.h[0].compute_state(key), self.h[1].compute_state(key), self.h[2].compute_state(key)]
+    pub fn table_size(&self) -> usize {
+        self.table.len()
     }
 
-    fn fast_prefix_get(&self, key: &K, state: &Self::State, ind: usize) -> Option<&V> {
-        let mut res = 0;
-        for i in 0..3 {
-            res += self.get_w(self.h[i].fast_prefix_hash(key, &state[i], ind));
-            if res >= 3 {
-                res -= 3;
-            }
-        }
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
 
-        let pos = self.ds.rank(
-            self.h[res].fast_prefix_hash(key, &state[res], ind),
-            &self.w0,
-            &self.w1
-        );
-        Some(&self.table[pos])
+    pub fn directory_bits(&self) -> usize {
+        self.ds.directory_bits()
     }
-}
 
-impl<K, V, H: ParametricHash<DomainType = K>> MinimalPerfectHashStaticDict<K, V, H> {
     fn get_w(&self, ind: usize) -> usize {
         let x = (self.w0[ind] as usize) + 2 * (self.w1[ind] as usize);
         if x == 0 {
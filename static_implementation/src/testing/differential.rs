@@ -0,0 +1,437 @@
+use crate::generators::*;
+use crate::traits::*;
+use crate::utils::*;
+use rand::prelude::*;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::panic::{ catch_unwind, AssertUnwindSafe };
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Query {
+    Pred(Str),
+    Succ(Str),
+    ExPref(Str),
+    ExRange(Str, Str),
+    Rank(Str),
+    CountRange(Str, Str),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Answer {
+    Key(Option<OrdStr>),
+    Flag(bool),
+    Count(usize),
+    Panic(String),
+}
+
+// object safe view of a Trie, so that different implementations can be checked together
+pub trait DynTrie {
+    fn dyn_build(&mut self, v: &[Str]);
+    fn dyn_par_build(&mut self, v: &[Str], threads: usize);
+    fn dyn_answer(&self, q: &Query) -> Answer;
+    fn dyn_validate(&self) -> Result<(), InvariantViolation>;
+    fn dyn_stats(&self) -> TrieStats;
+}
+
+impl<T: Trie> DynTrie for T {
+    fn dyn_build(&mut self, v: &[Str]) {
+        self.build(v);
+    }
+
+    fn dyn_par_build(&mut self, v: &[Str], threads: usize) {
+        self.par_build(v, threads);
+    }
+
+    fn dyn_answer(&self, q: &Query) -> Answer {
+        match q {
+            Query::Pred(x) => Answer::Key(self.pred_query(x).map(|k| k.into_owned())),
+            Query::Succ(x) => Answer::Key(self.succ_query(x).map(|k| k.into_owned())),
+            Query::ExPref(x) => Answer::Flag(self.ex_pref_query(x)),
+            Query::ExRange(x, y) => Answer::Flag(self.ex_range_query(x, y)),
+            Query::Rank(x) => Answer::Count(self.rank_query(x)),
+            Query::CountRange(x, y) => Answer::Count(self.count_range_query(x, y)),
+        }
+    }
+
+    fn dyn_validate(&self) -> Result<(), InvariantViolation> {
+        self.validate()
+    }
+
+    fn dyn_stats(&self) -> TrieStats {
+        self.stats()
+    }
+}
+
+fn oracle_answer(set: &BTreeSet<OrdStr>, q: &Query) -> Answer {
+    let succ = |x: &Str| set.range::<OrdStr, _>(OrdStr::from(x)..).next();
+    match q {
+        Query::Pred(x) => Answer::Key(set.range::<OrdStr, _>(..OrdStr::from(x)).next_back().cloned()),
+        Query::Succ(x) => Answer::Key(succ(x).cloned()),
+        Query::ExPref(x) => Answer::Flag(succ(x).is_some_and(|k| is_prefix_of(x, k))),
+        Query::ExRange(x, y) => Answer::Flag(succ(x).is_some_and(|k| cmp(k, y) == std::cmp::Ordering::Less)),
+        Query::Rank(x) => Answer::Count(set.range::<OrdStr, _>(..OrdStr::from(x)).count()),
+        Query::CountRange(x, y) => Answer::Count(set.range::<OrdStr, _>(OrdStr::from(x)..OrdStr::from(y)).count()),
+    }
+}
+
+// families of key sets where the tries are most likely to break
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeySet {
+    Random,
+    VariableLength,
+    SharedPrefix,
+    Skewed,
+    LastBit,
+    Short,
+    Single,
+    Empty,
+}
+
+impl KeySet {
+    pub const ALL: [KeySet; 8] = [
+        KeySet::Random,
+        KeySet::VariableLength,
+        KeySet::SharedPrefix,
+        KeySet::Skewed,
+        KeySet::LastBit,
+        KeySet::Short,
+        KeySet::Single,
+        KeySet::Empty,
+    ];
+
+    // the families whose keys all have the same length
+    pub const FIXED_WIDTH: [KeySet; 7] = [
+        KeySet::Random,
+        KeySet::SharedPrefix,
+        KeySet::Skewed,
+        KeySet::LastBit,
+        KeySet::Short,
+        KeySet::Single,
+        KeySet::Empty,
+    ];
+
+    // at most n sorted keys of at most `bits` bits, no one prefix of another
+    pub fn generate<R: RngCore>(&self, rng: &mut R, n: usize, bits: usize) -> Vec<Str> {
+        let mut keygen = KeyGen::new(n, bits);
+        match self {
+            KeySet::Random => keygen.generate(rng),
+            KeySet::VariableLength => keygen.set_lengths(LengthDist::spread(bits)).generate(rng),
+            // every key starts with the same long prefix, so the skips are long
+            KeySet::SharedPrefix => keygen.set_shared_prefix(bits - bits / 8).generate(rng),
+            // mostly ones, so the trie is deep and unbalanced
+            KeySet::Skewed => keygen.set_skew(0.9).generate(rng),
+            KeySet::LastBit => {
+                // pairs of keys that differ only in the last bit
+                let mut v = vec![];
+                for _ in 0..n / 2 {
+                    let s = gen_bin_str(rng, bits.max(1) as u32 - 1);
+                    for c in [false, true] {
+                        let mut t = s.clone();
+                        t.push(c);
+                        v.push(t);
+                    }
+                }
+                make_prefix_free(v)
+            }
+            KeySet::Short => {
+                let len = 1 + rng.next_u32() as usize % bits.clamp(1, 4);
+                keygen.set_lengths(LengthDist::Fixed(len)).generate(rng)
+            }
+            KeySet::Single => {
+                let len = rng.next_u32() as usize % (bits + 1);
+                vec![gen_bin_str(rng, len as u32)]
+            }
+            KeySet::Empty => vec![],
+        }
+    }
+}
+
+// keys, prefixes of keys, keys with a flipped bit or extended, and random strings
+pub fn gen_query_key<R: RngCore>(rng: &mut R, keys: &[Str], bits: usize) -> Str {
+    let r = rng.next_u32() as usize;
+    if keys.is_empty() || r.is_multiple_of(4) {
+        let len = rng.next_u32() % (bits as u32 + 3);
+        return gen_bin_str(rng, len);
+    }
+    let k = &keys[(r / 4) % keys.len()];
+    let pos = (rng.next_u32() as usize) % (k.len() + 1);
+    let extra = rng.next_u32() % 3;
+    match (r / 4 / keys.len()) % 4 {
+        0 => k.clone(),
+        1 => get_substr(k, 0, pos),
+        2 => {
+            let mut s = get_substr(k, 0, pos);
+            if pos < k.len() {
+                s.push(!k[pos]);
+            }
+            append(&mut s, gen_bin_str(rng, extra));
+            s
+        }
+        _ => {
+            let mut s = k.clone();
+            append(&mut s, gen_bin_str(rng, 1 + extra));
+            s
+        }
+    }
+}
+
+pub fn gen_queries<R: RngCore>(rng: &mut R, keys: &[Str], m: usize, bits: usize) -> Vec<Query> {
+    (0..m)
+        .map(|_| {
+            let x = gen_query_key(rng, keys, bits);
+            match rng.next_u32() % 6 {
+                0 => Query::Pred(x),
+                1 => Query::Succ(x),
+                2 => Query::ExPref(x),
+                3 => Query::Rank(x),
+                kind => {
+                    let y = gen_query_key(rng, keys, bits);
+                    let (x, y) = if cmp(&x, &y) == std::cmp::Ordering::Greater { (y, x) } else { (x, y) };
+                    if kind == 4 { Query::ExRange(x, y) } else { Query::CountRange(x, y) }
+                }
+            }
+        })
+        .collect()
+}
+
+pub struct Failure {
+    pub seed: u64,
+    pub trie: String,
+    pub keys: Vec<Str>,
+    // None when the build or the validation failed
+    pub query: Option<Query>,
+    pub message: String,
+}
+
+pub struct Differential<'a> {
+    tries: Vec<(&'a str, &'a mut dyn DynTrie)>,
+    kinds: Vec<KeySet>,
+    seed: u64,
+    rounds: usize,
+    n: usize,
+    bits: usize,
+    queries: usize,
+}
+
+impl<'a> Differential<'a> {
+    pub fn new(seed: u64) -> Differential<'a> {
+        Differential {
+            tries: vec![],
+            kinds: KeySet::ALL.to_vec(),
+            seed,
+            rounds: 1,
+            n: 100,
+            bits: 100,
+            queries: 1000,
+        }
+    }
+
+    pub fn add(&mut self, name: &'a str, ds: &'a mut dyn DynTrie) -> &mut Differential<'a> {
+        self.tries.push((name, ds));
+        self
+    }
+
+    pub fn set_kinds(&mut self, kinds: &[KeySet]) -> &mut Differential<'a> {
+        self.kinds = kinds.to_vec();
+        self
+    }
+
+    // every round builds `n` keys of about `bits` bits of each kind and asks `queries` queries
+    pub fn set_sizes(&mut self, rounds: usize, n: usize, bits: usize, queries: usize) -> &mut Differential<'a> {
+        (self.rounds, self.n, self.bits, self.queries) = (rounds, n, bits, queries);
+        self
+    }
+
+    pub fn run(&mut self) {
+        if let Some(f) = self.find_failure() {
+            panic!("{}", f);
+        }
+    }
+
+    // the first failing case, shrunk
+    pub fn find_failure(&mut self) -> Option<Failure> {
+        let mut rng = SmallRng::seed_from_u64(self.seed);
+        for _ in 0..self.rounds {
+            for kind in self.kinds.clone() {
+                let keys = kind.generate(&mut rng, self.n, self.bits);
+                let queries = gen_queries(&mut rng, &keys, self.queries, self.bits);
+                if let Some(f) = self.check(&keys, &queries) {
+                    return Some(self.shrink(f));
+                }
+            }
+        }
+        None
+    }
+
+    pub fn check(&mut self, keys: &[Str], queries: &[Query]) -> Option<Failure> {
+        let seed = self.seed;
+        let fail = |trie: &str, query: Option<&Query>, message: String| Failure {
+            seed,
+            trie: trie.to_string(),
+            keys: keys.to_vec(),
+            query: query.cloned(),
+            message,
+        };
+
+        for (name, ds) in self.tries.iter_mut() {
+            if let Err(e) = catch_unwind(AssertUnwindSafe(|| ds.dyn_build(keys))) {
+                return Some(fail(name, None, format!("build panicked: {}", panic_message(e))));
+            }
+            match catch_unwind(AssertUnwindSafe(|| ds.dyn_validate())) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    return Some(fail(name, None, format!("invalid structure: {}", e)));
+                }
+                Err(e) => {
+                    return Some(fail(name, None, format!("validate panicked: {}", panic_message(e))));
+                }
+            }
+        }
+
+        let oracle: BTreeSet<OrdStr> = keys.iter().map(OrdStr::from).collect();
+        for q in queries {
+            let expected = oracle_answer(&oracle, q);
+            for (name, ds) in self.tries.iter() {
+                let found = catch_unwind(AssertUnwindSafe(|| ds.dyn_answer(q)))
+                    .unwrap_or_else(|e| Answer::Panic(panic_message(e)));
+                if found != expected {
+                    return Some(fail(name, Some(q), format!("answered {} instead of {}", found, expected)));
+                }
+            }
+        }
+        None
+    }
+
+    // removes keys and bits while the same trie keeps failing
+    fn shrink(&mut self, f: Failure) -> Failure {
+        let trie = f.trie.clone();
+        let still_fails = |d: &mut Differential, keys: &[Str], query: &Option<Query>| {
+            let queries: Vec<Query> = query.iter().cloned().collect();
+            d.check(keys, &queries).filter(|g| g.trie == trie && g.query.is_some() == query.is_some())
+        };
+
+        let mut f = f;
+        let mut progress = true;
+        while progress {
+            progress = false;
+
+            // chunks of keys, from the largest
+            let mut chunk = f.keys.len().div_ceil(2);
+            while chunk > 0 {
+                let mut start = 0;
+                while start < f.keys.len() {
+                    let mut keys = f.keys.clone();
+                    keys.drain(start..(start + chunk).min(f.keys.len()));
+                    if let Some(g) = still_fails(self, &keys, &f.query) {
+                        (f, progress) = (g, true);
+                    } else {
+                        start += chunk;
+                    }
+                }
+                chunk /= 2;
+            }
+
+            // the last bit of a key: if the set stays prefix free, it also stays sorted
+            let mut i = 0;
+            while i < f.keys.len() {
+                let mut keys = f.keys.clone();
+                if let Some(s) = shorten(&keys[i]) {
+                    keys[i] = s;
+                    if is_prefix_free(&keys) {
+                        if let Some(g) = still_fails(self, &keys, &f.query) {
+                            (f, progress) = (g, true);
+                            continue;
+                        }
+                    }
+                }
+                i += 1;
+            }
+
+            // the last bit of the query strings
+            while let Some(q) = f.query.as_ref().and_then(shorten_query) {
+                match still_fails(self, &f.keys, &Some(q)) {
+                    Some(g) => (f, progress) = (g, true),
+                    None => break,
+                }
+            }
+        }
+        f
+    }
+}
+
+fn shorten(x: &Str) -> Option<Str> {
+    if x.len() == 0 { None } else { Some(get_substr(x, 0, x.len() - 1)) }
+}
+
+fn shorten_query(q: &Query) -> Option<Query> {
+    match q {
+        Query::Pred(x) => shorten(x).map(Query::Pred),
+        Query::Succ(x) => shorten(x).map(Query::Succ),
+        Query::ExPref(x) => shorten(x).map(Query::ExPref),
+        // a prefix of x is still not greater than y
+        Query::ExRange(x, y) => shorten(x).map(|x| Query::ExRange(x, y.clone())),
+        Query::Rank(x) => shorten(x).map(Query::Rank),
+        Query::CountRange(x, y) => shorten(x).map(|x| Query::CountRange(x, y.clone())),
+    }
+}
+
+pub fn panic_message(e: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = e.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = e.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Query::Pred(x) => write!(f, "pred_query({:?})", x.as_bit_slice()),
+            Query::Succ(x) => write!(f, "succ_query({:?})", x.as_bit_slice()),
+            Query::ExPref(x) => write!(f, "ex_pref_query({:?})", x.as_bit_slice()),
+            Query::ExRange(x, y) => {
+                write!(f, "ex_range_query({:?}, {:?})", x.as_bit_slice(), y.as_bit_slice())
+            }
+            Query::Rank(x) => write!(f, "rank_query({:?})", x.as_bit_slice()),
+            Query::CountRange(x, y) => {
+                write!(f, "count_range_query({:?}, {:?})", x.as_bit_slice(), y.as_bit_slice())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Answer::Key(Some(k)) => write!(f, "Some({:?})", k),
+            Answer::Key(None) => write!(f, "None"),
+            Answer::Flag(b) => write!(f, "{}", b),
+            Answer::Count(c) => write!(f, "{}", c),
+            Answer::Panic(s) => write!(f, "a panic ({})", s),
+        }
+    }
+}
+
+// printed as code that can be pasted into a test
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "trie `{}` failed (seed {}): {}", self.trie, self.seed, self.message)?;
+        writeln!(f, "let keys = vec![")?;
+        for k in &self.keys {
+            writeln!(f, "    {:?},", k.as_bit_slice())?;
+        }
+        write!(f, "];")?;
+        if let Some(q) = &self.query {
+            write!(f, "\n{}", q)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,204 @@
+use crate::generators::*;
+use crate::hashes::*;
+use crate::testing::differential::*;
+use crate::traits::*;
+use crate::tries::*;
+use crate::utils::*;
+
+// the targets in fuzz/fuzz_targets and the corpus in tests/fuzz_corpus, by name
+pub const FUZZ_TARGETS: [&str; 6] = [
+    "compact",
+    "z_fast",
+    "z_fast_verified",
+    "z_fast_sux",
+    "elias_fano",
+    "rolling_hash",
+];
+
+const MAX_KEYS: usize = 256;
+const MAX_QUERIES: usize = 1000;
+const MAX_HASHED_LEN: usize = 2048;
+
+// reads the fuzzer input a byte at a time, and zeros once it runs out
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> ByteReader<'a> {
+        ByteReader { data, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        let res = self.data.get(self.pos).copied().unwrap_or(0);
+        self.pos += 1;
+        res
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        for b in &mut bytes {
+            *b = self.u8();
+        }
+        u64::from_le_bytes(bytes)
+    }
+
+    // n bits, the first one from the most significant of the byte
+    pub fn bits(&mut self, n: usize) -> Str {
+        let mut res = Str::new(0);
+        while res.len() < n {
+            let bits = (n - res.len()).min(8);
+            push_int(&mut res, (self.u8() >> (8 - bits)) as usize, bits, BitOrder::MsbFirst);
+        }
+        res
+    }
+}
+
+// a key set: the number of keys and the maximum length, then every key, either
+// random or a prefix of the previous one followed by random bits
+pub fn decode_keys(r: &mut ByteReader, fixed_width: bool) -> Vec<Str> {
+    let n = (r.u8() as usize) % (MAX_KEYS + 1);
+    let max_len = if fixed_width { (r.u8() as usize) % (WORD_SIZE + 1) } else { r.u8() as usize };
+    let mut keys: Vec<Str> = vec![];
+    for _ in 0..n {
+        let len = if fixed_width { max_len } else { (r.u8() as usize) % (max_len + 1) };
+        let key = match (r.u8() % 2, keys.last()) {
+            (1, Some(prev)) => {
+                let shared = (r.u8() as usize) % (prev.len().min(len) + 1);
+                let mut s = get_substr(prev, 0, shared);
+                append(&mut s, r.bits(len - shared));
+                s
+            }
+            _ => r.bits(len),
+        };
+        keys.push(key);
+    }
+    make_prefix_free(keys)
+}
+
+// a key, a prefix of a key, a prefix with the next bit flipped, or a key with more bits
+fn decode_query_key(r: &mut ByteReader, keys: &[Str]) -> Str {
+    let mode = r.u8() % 4;
+    if keys.is_empty() || mode == 0 {
+        let len = r.u8() as usize;
+        return r.bits(len);
+    }
+    let k = &keys[(r.u16() as usize) % keys.len()];
+    let pos = (r.u8() as usize) % (k.len() + 1);
+    match mode {
+        1 => get_substr(k, 0, pos),
+        2 => {
+            let mut s = get_substr(k, 0, pos);
+            if pos < k.len() {
+                s.push(!k[pos]);
+            }
+            let extra = (r.u8() % 4) as usize;
+            append(&mut s, r.bits(extra));
+            s
+        }
+        _ => {
+            let mut s = k.clone();
+            let extra = 1 + (r.u8() % 4) as usize;
+            append(&mut s, r.bits(extra));
+            s
+        }
+    }
+}
+
+// the layout of the corpus has four kinds; rank and count_range, added later, ask
+// the same keys as pred and ex_range, so that the old inputs keep their meaning
+pub fn decode_queries(r: &mut ByteReader, keys: &[Str]) -> Vec<Query> {
+    let mut res = vec![];
+    while !r.is_empty() && res.len() < MAX_QUERIES {
+        let kind = r.u8() % 4;
+        let x = decode_query_key(r, keys);
+        match kind {
+            0 => {
+                res.push(Query::Rank(x.clone()));
+                res.push(Query::Pred(x));
+            }
+            1 => res.push(Query::Succ(x)),
+            2 => res.push(Query::ExPref(x)),
+            _ => {
+                let y = decode_query_key(r, keys);
+                let (x, y) = if cmp(&x, &y) == std::cmp::Ordering::Greater { (y, x) } else { (x, y) };
+                res.push(Query::CountRange(x.clone(), y.clone()));
+                res.push(Query::ExRange(x, y));
+            }
+        }
+    }
+    res
+}
+
+// builds the trie on the decoded keys, validates it and checks every decoded query
+// against a BTreeSet; panics with a reproducible failure
+pub fn fuzz_trie(name: &str, ds: &mut dyn DynTrie, data: &[u8], fixed_width: bool) {
+    let mut r = ByteReader::new(data);
+    let keys = decode_keys(&mut r, fixed_width);
+    let queries = decode_queries(&mut r, &keys);
+    if let Some(f) = Differential::new(0).add(name, ds).check(&keys, &queries) {
+        panic!("{}", f);
+    }
+}
+
+// a domain size and a seed, then a string: every prefix hash must agree with the
+// hash of the prefix and stay in the domain; out of 3..=MAX_MODULO, new_parametric
+// must refuse the domain size
+pub fn fuzz_rolling_hash(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    let domain_size = (r.u64() as usize) % (RollingHash::MAX_MODULO + 2);
+    let seed = r.u64();
+    if !(3..=RollingHash::MAX_MODULO).contains(&domain_size) {
+        let res = std::panic::catch_unwind(|| RollingHash::new_parametric(domain_size, seed));
+        let msg = res.err().map(panic_message);
+        assert!(
+            msg.as_deref() == Some("Invalid RollingHash domain size"),
+            "domain size {}: expected the invalid domain size panic, got {:?}", domain_size, msg
+        );
+        return;
+    }
+    let len = (r.u16() as usize) % (MAX_HASHED_LEN + 1);
+    let s = r.bits(len);
+
+    for h in [RollingHash::new_parametric(domain_size, seed), RollingHash::with_seed(seed)] {
+        let state = h.compute_state(s.as_bit_slice());
+        for i in 0..=s.len() {
+            let expected = h.hash(&get_substr(&s, 0, i));
+            let slow = h.slow_prefix_hash(s.as_bit_slice(), i);
+            let fast = h.fast_prefix_hash(s.as_bit_slice(), &state, i);
+            assert!(
+                slow == expected && fast == expected && expected < h.modulo(),
+                "prefix {} of {:?}: hash {}, slow {}, fast {} (modulo {}, seed {})",
+                i, s, expected, slow, fast, h.modulo(), seed
+            );
+        }
+    }
+}
+
+pub fn run_fuzz_target(target: &str, data: &[u8]) {
+    // il seed delle tries viene dall'input, così ogni caso è riproducibile
+    let seed = ByteReader::new(data).u64();
+    let data = data.get(8..).unwrap_or(&[]);
+    match target {
+        "compact" => fuzz_trie(target, &mut CompactTrie::new(), data, false),
+        "z_fast" => fuzz_trie(target, &mut ZFastTrie::<RollingHash>::with_seed(seed), data, false),
+        "z_fast_verified" => {
+            let mut ds = ZFastTrie::<RollingHash>::with_seed(seed);
+            ds.set_verify_handles(true);
+            fuzz_trie(target, &mut ds, data, false);
+        }
+        "z_fast_sux" => fuzz_trie(target, &mut ZFastTrieSux::<RollingHash>::with_seed(seed), data, false),
+        "elias_fano" => fuzz_trie(target, &mut EliasFanoTrie::new(), data, true),
+        "rolling_hash" => fuzz_rolling_hash(data),
+        _ => panic!("unknown fuzz target {:?}", target),
+    }
+}
@@ -0,0 +1,5 @@
+pub mod differential;
+pub use differential::*;
+
+pub mod fuzz;
+pub use fuzz::*;
@@ -1,3 +1,4 @@
+use crate::utils::bit_slice::*;
 use std::cmp::Eq;
 
 pub trait Hash {
@@ -6,15 +7,19 @@ pub trait Hash {
     type State;
 
     fn new() -> Self;
+    fn with_seed(seed: u64) -> Self;
     fn hash(&self, s: &Self::DomainType) -> Self::HashType;
-    fn slow_prefix_hash(&self, s: &Self::DomainType, ind: usize) -> Self::HashType;
-    fn compute_state(&self, s: &Self::DomainType) -> Self::State;
-    fn fast_prefix_hash(
-        &self,
-        s: &Self::DomainType,
-        state: &Self::State,
-        ind: usize
-    ) -> Self::HashType;
+    // the prefix hashes read the key through a view, so that queries on a
+    // substring of a larger buffer do not copy it
+    fn slow_prefix_hash(&self, s: BitSlice, ind: usize) -> Self::HashType;
+    fn compute_state(&self, s: BitSlice) -> Self::State;
+    fn fast_prefix_hash(&self, s: BitSlice, state: &Self::State, ind: usize) -> Self::HashType;
+
+    // the state of s from the state of a key sharing its first `lcp` bits, so that
+    // a batch of sorted keys only hashes what each key adds to the previous one
+    fn update_state(&self, s: BitSlice, _state: Self::State, _lcp: usize) -> Self::State {
+        self.compute_state(s)
+    }
 }
 
 pub trait ParametricHash: Hash<HashType = usize> {
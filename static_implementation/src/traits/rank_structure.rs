@@ -4,4 +4,21 @@ pub trait RankStructure {
     fn new() -> Self;
     fn build(&mut self, v0: &Str, v1: &Str);
     fn rank(&self, ind: usize, v0: &Str, v1: &Str) -> usize;
+    // bits of the counters, without the bit vectors they are built on
+    fn directory_bits(&self) -> usize;
+}
+
+pub trait RankSelect {
+    fn from_bits(v: Str) -> Self;
+    fn bits(&self) -> &Str;
+    fn count_ones(&self) -> usize;
+    fn rank1(&self, ind: usize) -> usize;
+    fn select1(&self, k: usize) -> Option<usize>;
+    fn select0(&self, k: usize) -> Option<usize>;
+    // bits used on top of the bit vector by the rank directory and the select samples
+    fn overhead_bits(&self) -> usize;
+
+    fn rank0(&self, ind: usize) -> usize {
+        ind - self.rank1(ind)
+    }
 }
@@ -1,11 +1,18 @@
 use super::hash::ParametricHash;
+use crate::utils::bit_slice::*;
 
 pub trait StaticDict<K, V, H: ParametricHash<DomainType = K>> {
     type State;
 
     fn new() -> Self;
+    fn with_seed(seed: u64) -> Self;
+    fn seed(&self) -> u64;
     fn build(&mut self, keys: &Vec<K>, values: &Vec<V>);
     fn get(&self, key: &K) -> Option<&V>;
-    fn compute_state(&self, key: &K) -> Self::State;
-    fn fast_prefix_get(&self, key: &K, state: &Self::State, ind: usize) -> Option<&V>;
+    fn compute_state(&self, key: BitSlice) -> Self::State;
+    fn fast_prefix_get(&self, key: BitSlice, state: &Self::State, ind: usize) -> Option<&V>;
+
+    fn update_state(&self, key: BitSlice, _state: Self::State, _lcp: usize) -> Self::State {
+        self.compute_state(key)
+    }
 }
@@ -1,9 +1,261 @@
+use crate::utils::bit_slice::*;
+use crate::utils::bitwise_operations::*;
+use crate::utils::histogram::*;
+use crate::utils::ord_str::*;
 use crate::utils::str::*;
+use std::borrow::Cow;
+use std::fmt;
+
+// keys returned by the queries: borrowed from the trie when it stores them, owned when
+// they have to be rebuilt, and viewed as a BitSlice through as_bit_slice either way.
+// They are not BitSlice views themselves because some tries have no key to point into:
+// CompactTrie keeps only the skip strings along the path and EliasFanoTrie splits the
+// keys into high and low bits, so both return owned keys; NaiveTrie, the z-fast tries
+// and the baselines return keys borrowed from their storage
+pub type Key<'a> = Cow<'a, OrdStr>;
+
+pub struct QueryAnswers<'a> {
+    pub pred: Option<Key<'a>>,
+    pub succ: Option<Key<'a>>,
+    pub ex_pref: bool,
+}
 
 pub trait Trie {
-    fn build(&mut self, v: &Vec<Str>);
-    fn pred_query(&self, x: &Str) -> Option<Str>;
-    fn succ_query(&self, x: &Str) -> Option<Str>;
-    fn ex_pref_query(&self, x: &Str) -> bool;
-    fn ex_range_query(&self, x: &Str, y: &Str) -> bool;
+    fn build(&mut self, v: &[Str]);
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>>;
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>>;
+    fn ex_pref_query(&self, x: impl AsBitSlice) -> bool;
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool;
+    // number of keys smaller than x
+    fn rank_query(&self, x: impl AsBitSlice) -> usize;
+    fn stats(&self) -> TrieStats;
+
+    // the same structure as build, with the work spread over `threads` threads, 0 for one
+    // per core; the tries without a parallel construction build on a single thread
+    fn par_build(&mut self, v: &[Str], _threads: usize) {
+        self.build(v);
+    }
+
+    // number of keys k with x <= k < y, the ones ex_range_query looks for
+    fn count_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> usize {
+        self.rank_query(y).saturating_sub(self.rank_query(x))
+    }
+
+    fn query_all(&self, x: impl AsBitSlice) -> QueryAnswers<'_> {
+        let x = x.as_bit_slice();
+        QueryAnswers {
+            pred: self.pred_query(x),
+            succ: self.succ_query(x),
+            ex_pref: self.ex_pref_query(x),
+        }
+    }
+
+    // answers for every key of the batch, in the order of the batch
+    fn pred_batch(&self, xs: &[Str]) -> Vec<Option<Key<'_>>> {
+        xs.iter().map(|x| self.pred_query(x)).collect()
+    }
+
+    fn succ_batch(&self, xs: &[Str]) -> Vec<Option<Key<'_>>> {
+        xs.iter().map(|x| self.succ_query(x)).collect()
+    }
+
+    fn ex_pref_batch(&self, xs: &[Str]) -> Vec<bool> {
+        xs.iter().map(|x| self.ex_pref_query(x)).collect()
+    }
+
+    fn ex_range_batch(&self, ranges: &[(Str, Str)]) -> Vec<bool> {
+        ranges.iter().map(|(x, y)| self.ex_range_query(x, y)).collect()
+    }
+
+    // checks the whole structure built by the last build
+    fn validate(&self) -> Result<(), InvariantViolation> {
+        Ok(())
+    }
+}
+
+// the shape of the trie built by the last build, and the size of its other parts;
+// the implementations without nodes describe the compacted trie on their keys
+#[derive(Clone, PartialEq, Debug)]
+pub struct TrieStats {
+    pub keys: usize,
+    pub internal_nodes: usize,
+    // internal nodes above every leaf
+    pub depths: Histogram,
+    // rind - lind of every node
+    pub skips: Histogram,
+    // sum of the extent lengths of all the nodes
+    pub extent_bits: usize,
+    pub z_map_entries: usize,
+    pub mphf: Option<MphfStats>,
+    // bits of the rank and select directories
+    pub rank_directory_bits: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct MphfStats {
+    pub table_size: usize,
+    // entries over table size
+    pub load_factor: f64,
+    // hypergraphs generated before one could be peeled
+    pub attempts: usize,
+}
+
+impl TrieStats {
+    pub fn new(keys: usize) -> TrieStats {
+        TrieStats {
+            keys,
+            internal_nodes: 0,
+            depths: Histogram::new(),
+            skips: Histogram::new(),
+            extent_bits: 0,
+            z_map_entries: 0,
+            mphf: None,
+            rank_directory_bits: 0,
+        }
+    }
+
+    // the compacted trie on keys, sorted and prefix free, visited as the tries build it
+    pub fn of_keys<K: AsBitSlice>(keys: &[K]) -> TrieStats {
+        let mut res = TrieStats::new(keys.len());
+        let mut stack = if keys.is_empty() { vec![] } else { vec![(0, keys.len(), 0, 0)] };
+        while let Some((l, r, lind, depth)) = stack.pop() {
+            if l + 1 == r {
+                res.add_node(lind, keys[l].as_bit_slice().len(), depth, true);
+            } else {
+                let rind = lcp_len(&keys[l], &keys[r - 1]);
+                let mid = l + keys[l..r].partition_point(|x| !x.as_bit_slice().get(rind));
+                res.add_node(lind, rind, depth, false);
+                stack.push((mid, r, rind + 1, depth + 1));
+                stack.push((l, mid, rind + 1, depth + 1));
+            }
+        }
+        res
+    }
+
+    // a node with skip interval [lind, rind] below `depth` internal nodes
+    pub fn add_node(&mut self, lind: usize, rind: usize, depth: usize, leaf: bool) {
+        self.skips.add(rind - lind);
+        self.extent_bits += rind;
+        if leaf {
+            self.depths.add(depth);
+        } else {
+            self.internal_nodes += 1;
+        }
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.skips.len()
+    }
+}
+
+impl fmt::Display for TrieStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "keys              {}", self.keys)?;
+        writeln!(f, "nodes             {} ({} internal)", self.nodes(), self.internal_nodes)?;
+        writeln!(f, "leaf depth        {}", self.depths)?;
+        writeln!(f, "skip length       {}", self.skips)?;
+        writeln!(f, "extent bits       {}", self.extent_bits)?;
+        writeln!(f, "z-map entries     {}", self.z_map_entries)?;
+        if let Some(m) = &self.mphf {
+            writeln!(
+                f,
+                "mphf              {} cells, load factor {:.3}, {} attempts",
+                m.table_size, m.load_factor, m.attempts
+            )?;
+        }
+        write!(f, "rank directories  {} bits", self.rank_directory_bits)
+    }
+}
+
+// nodes are identified by their skip interval [lind, rind], leaves by their rank
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InvariantViolation {
+    ChildCount { lind: usize },
+    KeyCount { leaves: usize, keys: usize },
+    UnsortedLeaves { leaf: usize },
+    LeafKey { leaf: usize, key: Option<usize> },
+    SkipInterval { lind: usize, rind: usize },
+    Extent { lind: usize, rind: usize },
+    Handle { lind: usize, rind: usize, handle: usize },
+    Jump { lind: usize, rind: usize, right: bool },
+    LeafLink { leaf: usize },
+    BackLink { lind: usize, rind: usize },
+    ZMap { lind: usize, rind: usize },
+    ZMapSize { nodes: usize, entries: usize },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantViolation::ChildCount { lind } => {
+                write!(f, "node at depth {} has a single child", lind)
+            }
+            InvariantViolation::KeyCount { leaves, keys } => {
+                write!(f, "{} leaves for {} keys", leaves, keys)
+            }
+            InvariantViolation::UnsortedLeaves { leaf } => {
+                write!(f, "leaf {} is not smaller than leaf {}", leaf, leaf + 1)
+            }
+            InvariantViolation::LeafKey { leaf, key } => {
+                write!(f, "leaf {} points to key {:?}", leaf, key)
+            }
+            InvariantViolation::SkipInterval { lind, rind } => {
+                write!(f, "wrong skip interval [{}, {}]", lind, rind)
+            }
+            InvariantViolation::Extent { lind, rind } => {
+                write!(f, "keys below node [{}, {}] do not branch at {}", lind, rind, rind)
+            }
+            InvariantViolation::Handle { lind, rind, handle } => {
+                write!(f, "handle {} is not the 2-fattest number of [{}, {}]", handle, lind, rind)
+            }
+            InvariantViolation::Jump { lind, rind, right } => {
+                let side = if *right { "right" } else { "left" };
+                write!(f, "wrong {} jump of node [{}, {}]", side, lind, rind)
+            }
+            InvariantViolation::LeafLink { leaf } => {
+                write!(f, "wrong links of leaf {} to its neighbours", leaf)
+            }
+            InvariantViolation::BackLink { lind, rind } => {
+                write!(f, "wrong to_leaf/to_internal link of node [{}, {}]", lind, rind)
+            }
+            InvariantViolation::ZMap { lind, rind } => {
+                write!(f, "the handle of node [{}, {}] is not mapped to it", lind, rind)
+            }
+            InvariantViolation::ZMapSize { nodes, entries } => {
+                write!(f, "{} z-map entries for {} internal nodes", entries, nodes)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
+
+impl InvariantViolation {
+    // the handle of the node [lind, rind] has to be the 2-fattest number of its skip interval
+    pub fn check_handle(lind: usize, rind: usize, handle: usize) -> Result<(), InvariantViolation> {
+        if is_fattest(handle, lind, rind) { Ok(()) } else { Err(InvariantViolation::Handle { lind, rind, handle }) }
+    }
+}
+
+pub struct QueryContext<'a, S> {
+    x: BitSlice<'a>,
+    state: S,
+}
+
+impl<'a, S> QueryContext<'a, S> {
+    pub fn new(x: BitSlice<'a>, state: S) -> QueryContext<'a, S> {
+        QueryContext { x, state }
+    }
+
+    pub fn key(&self) -> BitSlice<'a> {
+        self.x
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn into_state(self) -> S {
+        self.state
+    }
 }
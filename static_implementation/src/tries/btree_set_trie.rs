@@ -0,0 +1,67 @@
+use crate::traits::*;
+use crate::utils::bit_slice::*;
+use crate::utils::ord_str::*;
+use crate::utils::str::*;
+use std::collections::BTreeSet;
+use std::ops::Bound::*;
+
+// baseline for the benchmarks: every query is a range of the standard BTreeSet,
+// which needs an owned copy of x to compare with, and rank walks the whole range
+pub struct BTreeSetTrie {
+    set: BTreeSet<OrdStr>,
+}
+
+impl BTreeSetTrie {
+    pub fn new() -> BTreeSetTrie {
+        BTreeSetTrie { set: BTreeSet::new() }
+    }
+
+    fn succ(&self, x: BitSlice) -> Option<&OrdStr> {
+        self.set.range::<OrdStr, _>((Included(OrdStr::from(x)), Unbounded)).next()
+    }
+}
+
+impl Default for BTreeSetTrie {
+    fn default() -> BTreeSetTrie {
+        BTreeSetTrie::new()
+    }
+}
+
+impl Trie for BTreeSetTrie {
+    fn build(&mut self, v: &[Str]) {
+        self.set = v.iter().map(OrdStr::from).collect();
+    }
+
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        let x = OrdStr::from(x.as_bit_slice());
+        self.set.range::<OrdStr, _>((Unbounded, Excluded(x))).next_back().map(Key::Borrowed)
+    }
+
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.succ(x.as_bit_slice()).map(Key::Borrowed)
+    }
+
+    fn ex_pref_query(&self, x: impl AsBitSlice) -> bool {
+        let x = x.as_bit_slice();
+        self.succ(x).is_some_and(|k| is_prefix_of(x, k))
+    }
+
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool {
+        let y = y.as_bit_slice();
+        self.succ(x.as_bit_slice()).is_some_and(|k| cmp_prefix(k, y, y.len()) == std::cmp::Ordering::Less)
+    }
+
+    fn rank_query(&self, x: impl AsBitSlice) -> usize {
+        let x = OrdStr::from(x.as_bit_slice());
+        self.set.range::<OrdStr, _>((Unbounded, Excluded(x))).count()
+    }
+
+    fn count_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> usize {
+        let (x, y) = (OrdStr::from(x.as_bit_slice()), OrdStr::from(y.as_bit_slice()));
+        if x < y { self.set.range::<OrdStr, _>((Included(x), Excluded(y))).count() } else { 0 }
+    }
+
+    fn stats(&self) -> TrieStats {
+        TrieStats::of_keys(&self.set.iter().collect::<Vec<_>>())
+    }
+}
@@ -1,7 +1,14 @@
 use crate::traits::*;
+use crate::utils::bit_slice::*;
+use crate::utils::bitwise_operations::*;
+use crate::utils::dot::*;
+use crate::utils::ord_str::*;
+use crate::utils::par_build::*;
 use crate::utils::str::*;
 use std::cmp::min;
+use std::iter::successors;
 use std::cmp::Ordering::*;
+use std::io::{ self, Write };
 
 pub struct CompactTrie {
     root: Option<Box<TrieNode>>,
@@ -11,50 +18,172 @@ struct TrieNode {
     s: Str,
     left: Option<Box<TrieNode>>,
     right: Option<Box<TrieNode>>,
+    // number of leaves in the subtree, for rank_query
+    leaves: usize,
 }
 
 impl CompactTrie {
     pub fn new() -> CompactTrie {
         CompactTrie { root: None }
     }
+
+    // graphviz rendering of the trie, with the skip interval and the extent of every node
+    pub fn to_dot(&self, w: impl Write) -> io::Result<()> {
+        self.write_dot(w, None)
+    }
+
+    // the same, with the path of x from the root to the node where it leaves the trie
+    pub fn to_dot_with_query(&self, w: impl Write, x: impl AsBitSlice) -> io::Result<()> {
+        self.write_dot(w, Some(x.as_bit_slice()))
+    }
+
+    fn write_dot(&self, mut w: impl Write, x: Option<BitSlice>) -> io::Result<()> {
+        let (mut nodes, mut parents) = (vec![], vec![]);
+        if let Some(r) = &self.root {
+            r.collect(&Str::new(0), None, &mut nodes, &mut parents);
+        }
+
+        // in preorder the left child follows its parent, and the right one follows the
+        // 2 * leaves - 1 nodes of the left subtree
+        let mut exit = None;
+        if let (Some(x), false) = (x, nodes.is_empty()) {
+            let mut i = 0;
+            loop {
+                exit = Some(i);
+                let (node, extent) = &nodes[i];
+                match &node.left {
+                    Some(l) if extent.len() < x.len() && is_prefix_of(extent, x) => {
+                        i += if x[extent.len()] { 2 * l.leaves } else { 1 };
+                    }
+                    _ => break,
+                }
+            }
+        }
+        let marks = dot_path_marks(&parents, exit);
+
+        dot_begin(&mut w)?;
+        let mut leaf = 0;
+        for (i, (node, extent)) in nodes.iter().enumerate() {
+            let (lind, rind) = (extent.len() - node.s.len(), extent.len());
+            let mut label = vec![format!("[{}, {}]", lind, rind), dot_extent(extent, rind)];
+            if node.left.is_some() {
+                label.push(format!("handle {}", get_fattest(rind, lind)));
+            } else {
+                label.push(format!("key {}", leaf));
+                leaf += 1;
+            }
+            dot_node(&mut w, i, &label, node.left.is_none(), marks[i])?;
+            if let Some(p) = parents[i] {
+                let bit = extent[nodes[p].1.len()];
+                dot_child_edge(&mut w, p, i, bit)?;
+            }
+        }
+        dot_end(&mut w)
+    }
 }
 
 impl Trie for CompactTrie {
-    fn build(&mut self, v: &Vec<Str>) {
-        let mut x = v.to_vec();
-        x.sort_by(cmp);
+    fn build(&mut self, v: &[Str]) {
+        self.par_build(v, 1);
+    }
+
+    // the subtrees below the top of the trie are built concurrently
+    fn par_build(&mut self, v: &[Str], threads: usize) {
+        let x = par_sort(v, threads);
+        let top = trie_top(&x, threads);
+        let weight = |t: &TrieTop| if let TrieTop::Subtree { l, r, .. } = *t { r - l } else { 0 };
+        let subtrees = par_map(&top, threads, weight, |t| match *t {
+            TrieTop::Subtree { lind, l, r, .. } => TrieNode::new(&x, lind, l, r),
+            TrieTop::Internal { .. } => None,
+        });
 
-        self.root = TrieNode::new(&x, 0, 0, v.len());
+        self.root = TrieNode::assemble(&x, &mut top.iter().zip(subtrees));
     }
 
-    fn pred_query(&self, x: &Str) -> Option<Str> {
+    // the trie does not store whole keys, so the answers are rebuilt
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        let x = x.as_bit_slice();
         if let Some(r) = &self.root {
-            (*r).pred_query(x, &mut Str::new(0), &mut false)
+            r.pred_query(x, 0).map(|ans| Key::Owned(OrdStr(ans.to_key(x, false))))
         } else {
             None
         }
     }
 
-    fn succ_query(&self, x: &Str) -> Option<Str> {
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        let x = x.as_bit_slice();
         if let Some(r) = &self.root {
-            (*r).succ_query(x, &mut Str::new(0), &mut false)
+            r.succ_query(x, 0).map(|ans| Key::Owned(OrdStr(ans.to_key(x, true))))
         } else {
             None
         }
     }
 
-    fn ex_pref_query(&self, x: &Str) -> bool {
-        if let Some(i) = &self.succ_query(x) { get_substr(i,0,min(i.len(), x.len())) == *x } else { false }
+    fn ex_pref_query(&self, x: impl AsBitSlice) -> bool {
+        if let Some(r) = &self.root { r.ex_pref_query(x.as_bit_slice(), 0) } else { false }
     }
 
-    fn ex_range_query(&self, x: &Str, y: &Str) -> bool {
-        if let Some(i) = &self.succ_query(x) { cmp(i,y) == Less } else { false }
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool {
+        let (x, y) = (x.as_bit_slice(), y.as_bit_slice());
+        if let Some(r) = &self.root { r.ex_range_query(x, y, 0, true, true) } else { false }
+    }
+
+    fn rank_query(&self, x: impl AsBitSlice) -> usize {
+        if let Some(r) = &self.root { r.rank_query(x.as_bit_slice(), 0) } else { 0 }
+    }
+
+    fn stats(&self) -> TrieStats {
+        let mut res = TrieStats::new(self.root.as_ref().map_or(0, |r| r.leaves));
+        if let Some(r) = &self.root {
+            r.stats(0, 0, &mut res);
+        }
+        res
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation> {
+        let (mut last, mut leaves) = (None, 0);
+        if let Some(r) = &self.root {
+            r.validate(&Str::new(0), &mut last, &mut leaves)?;
+        }
+        Ok(())
+    }
+}
+
+// the answer of a query leaves the path of x at depth `depth`: the key is x[0..depth],
+// then `turn` if present, then node.s[offset..] and the leftmost or rightmost path below node
+struct Answer<'a> {
+    depth: usize,
+    turn: Option<bool>,
+    node: &'a TrieNode,
+    offset: usize,
+}
+
+impl Answer<'_> {
+    fn to_key(&self, x: BitSlice, leftmost: bool) -> Str {
+        // the length is known first, so that the key is allocated once
+        let path = || successors(Some(self.node), |node| {
+            node.left.as_deref().zip(node.right.as_deref()).map(|(l, r)| if leftmost { l } else { r })
+        }).skip(1);
+        let len = self.depth + self.turn.is_some() as usize + self.node.s.len() - self.offset +
+            path().map(|node| 1 + node.s.len()).sum::<usize>();
+
+        let mut res = Str::with_capacity(len);
+        append_substr(&mut res, x, 0, self.depth);
+        if let Some(c) = self.turn {
+            res.push(c);
+        }
+        append_substr(&mut res, &self.node.s, self.offset, self.node.s.len());
+        for node in path() {
+            res.push(!leftmost);
+            append(&mut res, &node.s);
+        }
+        res
     }
 }
 
 impl TrieNode {
-    fn new(v: &Vec<Str>, ind: usize, l: usize, r: usize) -> Option<Box<TrieNode>> {
-        assert!(ind < v[l].len() || l + 1 >= r, "Build error: v is not prefix free");
+    fn new(v: &[Str], ind: usize, l: usize, r: usize) -> Option<Box<TrieNode>> {
+        assert!(l + 1 >= r || ind < v[l].len(), "Build error: v is not prefix free");
         if l == r {
             None
         } else if l + 1 == r {
@@ -63,146 +192,262 @@ impl TrieNode {
                     s: get_substr(&v[l],ind,v[l].len()),
                     left: None,
                     right: None,
+                    leaves: 1,
                 })
             )
         } else {
-            let mut mid = l;
-            while mid < r && !v[mid][ind] {
-                mid += 1;
-            }
+            // v is sorted, so the common prefix of v[l..r] is the lcp of its first and last key
+            let end = lcp(&v[l], &v[r - 1]).len();
+            assert!(end < v[l].len(), "Build error: v is not prefix free");
+            let mid = l + v[l..r].partition_point(|x| !x[end]);
 
-            if mid != l && mid != r {
-                Some(
-                    Box::new(TrieNode {
-                        s: Str::new(0),
-                        left: TrieNode::new(v, ind + 1, l, mid),
-                        right: TrieNode::new(v, ind + 1, mid, r),
-                    })
-                )
-            } else {
-                let x = TrieNode::new(v, ind + 1, l, r);
-                assert!(x.is_some());
-                let mut res = x.unwrap();
-                push_front(&mut (*res).s, v[l][ind]);
-                Some(res)
-            }
+            Some(
+                Box::new(TrieNode {
+                    s: get_substr(&v[l], ind, end),
+                    left: TrieNode::new(v, end + 1, l, mid),
+                    right: TrieNode::new(v, end + 1, mid, r),
+                    leaves: r - l,
+                })
+            )
         }
     }
 
-    fn upd_curr_pred(x: &Str, curr: &mut Str, found: &mut bool, c: bool) -> bool {
-        if !*found {
-            if curr.len() == x.len() {
-                return false;
-            } else if c != x[curr.len()] {
-                if c {
-                    return false;
-                } else {
-                    *found = true;
-                }
-            }
+    // the trie from its top in preorder, the subtrees being already built
+    fn assemble<'a>(
+        v: &[Str],
+        parts: &mut impl Iterator<Item = (&'a TrieTop, Option<Box<TrieNode>>)>
+    ) -> Option<Box<TrieNode>> {
+        match parts.next()? {
+            (&TrieTop::Internal { lind, rind, l, r, .. }, _) => Some(
+                Box::new(TrieNode {
+                    s: get_substr(&v[l], lind, rind),
+                    left: TrieNode::assemble(v, parts),
+                    right: TrieNode::assemble(v, parts),
+                    leaves: r - l,
+                })
+            ),
+            (TrieTop::Subtree { .. }, node) => node,
         }
-        curr.push(c);
-        true
     }
 
-    fn pred_query(&self, x: &Str, curr: &mut Str, found: &mut bool) -> Option<Str> {
-        assert!(!(self.left.is_some() ^ self.right.is_some())); // invariante: ogni nodo ha 0 o 2 figli
-        for c in &self.s {
-            if !Self::upd_curr_pred(x, curr, found, c) {
-                return None;
+    fn stats(&self, lind: usize, depth: usize, res: &mut TrieStats) {
+        let rind = lind + self.s.len();
+        res.add_node(lind, rind, depth, self.left.is_none());
+        for child in [&self.left, &self.right].into_iter().flatten() {
+            child.stats(rind + 1, depth + 1, res);
+        }
+    }
+
+    // the nodes of the subtree in preorder with their extent, and the index of their parent;
+    // path is the string of the parent followed by the bit of the branch taken
+    fn collect<'a>(
+        &'a self,
+        path: &Str,
+        parent: Option<usize>,
+        nodes: &mut Vec<(&'a TrieNode, Str)>,
+        parents: &mut Vec<Option<usize>>
+    ) {
+        let i = nodes.len();
+        let extent = concat(path, &self.s);
+        nodes.push((self, extent.clone()));
+        parents.push(parent);
+        for (child, bit) in [(&self.left, false), (&self.right, true)] {
+            if let Some(c) = child {
+                let mut p = extent.clone();
+                p.push(bit);
+                c.collect(&p, Some(i), nodes, parents);
             }
         }
-        if *found {
-            if let Some(r) = &self.right {
-                assert!(Self::upd_curr_pred(x, curr, found, true));
-                r.pred_query(x, curr, found)
-            } else {
-                Some(curr.clone())
+    }
+
+    // path is the string of the parent followed by the bit of the branch taken;
+    // the leaves are visited in order and `last` is the key of the previous one
+    fn validate(&self, path: &Str, last: &mut Option<Str>, leaves: &mut usize) -> Result<(), InvariantViolation> {
+        let d = path.len();
+        let path = concat(path, &self.s);
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) => {
+                let (mut lpath, mut rpath) = (path.clone(), path);
+                lpath.push(false);
+                rpath.push(true);
+                left.validate(&lpath, last, leaves)?;
+                right.validate(&rpath, last, leaves)?;
+                if self.leaves != left.leaves + right.leaves {
+                    return Err(InvariantViolation::KeyCount { leaves: left.leaves + right.leaves, keys: self.leaves });
+                }
+                Ok(())
             }
-        } else {
-            if x.len() == curr.len() {
-                None
-            } else if !x[curr.len()] {
-                if let Some(l) = &self.left {
-                    assert!(Self::upd_curr_pred(x, curr, found, false));
-                    l.pred_query(x, curr, found)
-                } else {
-                    Some(curr.clone())
+            (None, None) => {
+                if self.leaves != 1 {
+                    return Err(InvariantViolation::KeyCount { leaves: 1, keys: self.leaves });
                 }
-            } else {
-                if let Some(r) = &self.right {
-                    let tmp = &mut curr.clone();
-                    assert!(Self::upd_curr_pred(x, tmp, found, true));
-                    if let Some(res) = r.pred_query(x, tmp, found) {
-                        Some(res)
-                    } else {
-                        assert!(self.left.is_some());
-                        let l = self.left.as_ref().unwrap();
-                        assert!(Self::upd_curr_pred(x, curr, found, false));
-                        l.pred_query(x, curr, found)
+                if let Some(p) = last {
+                    if cmp(p, &path) != Less {
+                        return Err(InvariantViolation::UnsortedLeaves { leaf: *leaves - 1 });
                     }
-                } else {
-                    Some(curr.clone())
                 }
+                *last = Some(path);
+                *leaves += 1;
+                Ok(())
             }
+            _ => Err(InvariantViolation::ChildCount { lind: d }),
         }
     }
 
-    fn upd_curr_succ(x: &Str, curr: &mut Str, found: &mut bool, c: bool) -> bool {
-        if !*found {
-            if c != x[curr.len()] {
-                if !c {
-                    return false;
-                } else {
-                    *found = true;
-                }
+    // the nodes below work on x[d..], where d is the depth of the node:
+    // l is the lcp of x[d..] and s, and m the length of the shortest of the two
+    fn lcp_with(&self, x: BitSlice, d: usize) -> (usize, usize) {
+        let m = min(x.len() - d, self.s.len());
+        (lcp_len_range(x, d, &self.s, 0, m), m)
+    }
+
+    fn pred_query(&self, x: BitSlice, d: usize) -> Option<Answer<'_>> {
+        assert!(!(self.left.is_some() ^ self.right.is_some())); // invariante: ogni nodo ha 0 o 2 figli
+        let (l, m) = self.lcp_with(x, d);
+
+        if l < m {
+            // x and s diverge: the whole subtree is on one side of x
+            if x[d + l] { Some(Answer { depth: d + l, turn: None, node: self, offset: l }) } else { None }
+        } else if d + l == x.len() {
+            // every key of the subtree has x as prefix
+            None
+        } else if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            let end = d + self.s.len();
+            if x[end] {
+                right
+                    .pred_query(x, end + 1)
+                    .or(Some(Answer { depth: end, turn: Some(false), node: left, offset: 0 }))
+            } else {
+                left.pred_query(x, end + 1)
             }
+        } else {
+            // the key of the leaf is a proper prefix of x
+            Some(Answer { depth: d + l, turn: None, node: self, offset: l })
         }
-        curr.push(c);
-        if curr.len() == x.len() {
-            *found = true;
+    }
+
+    // number of keys of the subtree smaller than x, following the same cases as pred_query
+    fn rank_query(&self, x: BitSlice, d: usize) -> usize {
+        let (l, m) = self.lcp_with(x, d);
+
+        if l < m {
+            if x[d + l] { self.leaves } else { 0 }
+        } else if d + l == x.len() {
+            0
+        } else if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            let end = d + self.s.len();
+            if x[end] { left.leaves + right.rank_query(x, end + 1) } else { left.rank_query(x, end + 1) }
+        } else {
+            1
         }
-        true
     }
 
-    fn succ_query(&self, x: &Str, curr: &mut Str, found: &mut bool) -> Option<Str> {
+    fn succ_query(&self, x: BitSlice, d: usize) -> Option<Answer<'_>> {
         assert!(!(self.left.is_some() ^ self.right.is_some())); // invariante: ogni nodo ha 0 o 2 figli
-        for c in &self.s {
-            if !Self::upd_curr_succ(x, curr, found, c) {
-                return None;
-            }
-        }
-        if *found {
-            if let Some(l) = &self.left {
-                assert!(Self::upd_curr_succ(x, curr, found, false));
-                l.succ_query(x, curr, found)
+        let (l, m) = self.lcp_with(x, d);
+
+        if l < m {
+            if x[d + l] { None } else { Some(Answer { depth: d + l, turn: None, node: self, offset: l }) }
+        } else if d + l == x.len() {
+            Some(Answer { depth: d + l, turn: None, node: self, offset: l })
+        } else if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            let end = d + self.s.len();
+            if x[end] {
+                right.succ_query(x, end + 1)
             } else {
-                Some(curr.clone())
+                left
+                    .succ_query(x, end + 1)
+                    .or(Some(Answer { depth: end, turn: Some(true), node: right, offset: 0 }))
             }
         } else {
-            if x[curr.len()] {
-                if let Some(r) = &self.right {
-                    assert!(Self::upd_curr_succ(x, curr, found, true));
-                    r.succ_query(x, curr, found)
-                } else {
-                    None
+            None
+        }
+    }
+
+    fn ex_pref_query(&self, x: BitSlice, d: usize) -> bool {
+        let (l, m) = self.lcp_with(x, d);
+
+        if l < m {
+            false
+        } else if d + l == x.len() {
+            true
+        } else if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            let end = d + self.s.len();
+            if x[end] { right.ex_pref_query(x, end + 1) } else { left.ex_pref_query(x, end + 1) }
+        } else {
+            false
+        }
+    }
+
+    // tight_x (tight_y) tells whether the path so far is a prefix of x (y):
+    // otherwise every key of the subtree is already known to be greater than x (smaller than y)
+    fn ex_range_query(&self, x: BitSlice, y: BitSlice, d: usize, tight_x: bool, tight_y: bool) -> bool {
+        let is_leaf = self.left.is_none();
+        let (mut tight_x, mut tight_y) = (tight_x, tight_y);
+
+        if tight_x {
+            let (l, m) = self.lcp_with(x, d);
+            if l < m {
+                if x[d + l] {
+                    return false;
                 }
-            } else {
-                if let Some(l) = &self.left {
-                    let tmp = &mut curr.clone();
-                    assert!(Self::upd_curr_succ(x, tmp, found, false));
-                    if let Some(res) = l.succ_query(x, tmp, found) {
-                        Some(res)
-                    } else {
-                        assert!(self.right.is_some());
-                        let r = self.right.as_ref().unwrap();
-                        assert!(Self::upd_curr_succ(x, curr, found, true));
-                        r.succ_query(x, curr, found)
-                    }
-                } else {
-                    None
+                tight_x = false;
+            } else if d + l == x.len() {
+                tight_x = false;
+            } else if is_leaf {
+                return false;
+            }
+        }
+
+        if tight_y {
+            let (l, m) = self.lcp_with(y, d);
+            if l < m {
+                if !y[d + l] {
+                    return false;
                 }
+                tight_y = false;
+            } else if d + l == y.len() {
+                return false;
+            } else if is_leaf {
+                tight_y = false;
             }
         }
+
+        if !tight_x && !tight_y {
+            true
+        } else if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            let end = d + self.s.len();
+            let (bx, by) = (tight_x && x[end], tight_y && y[end]);
+            // a tight bit of x set excludes the left child, a tight bit of y unset the right one
+            (!bx && left.ex_range_query(x, y, end + 1, tight_x, tight_y && !by)) ||
+                ((!tight_y || by) && right.ex_range_query(x, y, end + 1, tight_x && bx, tight_y))
+        } else {
+            true
+        }
+    }
+}
+
+// the leaves are sorted by construction here, the order is checked on the z-fast tries,
+// which keep their keys apart from the nodes
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+    use crate::bits;
+    use InvariantViolation::*;
+
+    fn corrupted(f: impl FnOnce(&mut TrieNode)) -> InvariantViolation {
+        let v = vec![bits!("000"), bits!("0010"), bits!("0011"), bits!("01")];
+        let mut ds = CompactTrie::new();
+        ds.build(&v);
+        assert!(ds.validate().is_ok());
+        f(ds.root.as_mut().unwrap());
+        ds.validate().unwrap_err()
+    }
+
+    #[test]
+    fn test_validate_rejects() {
+        assert!(corrupted(|root| root.right = None) == ChildCount { lind: 0 });
+        assert!(corrupted(|root| root.leaves = 5) == KeyCount { leaves: 4, keys: 5 });
+        assert!(corrupted(|root| root.right.as_mut().unwrap().leaves = 2) == KeyCount { leaves: 1, keys: 2 });
     }
 }
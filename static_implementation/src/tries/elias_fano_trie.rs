@@ -0,0 +1,117 @@
+use crate::elias_fano::*;
+use crate::traits::*;
+use crate::utils::bit_slice::*;
+use crate::utils::ord_str::*;
+use crate::utils::str::*;
+use crate::utils::str_conversions::*;
+use std::cmp::min;
+
+// keys are read as big-endian integers of `width` bits, so that the integer order
+// is the lexicographic order of the trie
+pub struct EliasFanoTrie {
+    width: usize,
+    ef: EliasFano,
+}
+
+impl EliasFanoTrie {
+    pub fn new() -> EliasFanoTrie {
+        EliasFanoTrie { width: 0, ef: EliasFano::from_sorted(vec![]) }
+    }
+
+    // value of the first `width` bits of x, padded with zeros if x is shorter
+    fn to_int(&self, x: BitSlice) -> usize {
+        let len = min(self.width, x.len());
+        if len == 0 {
+            0
+        } else {
+            let mut word = x.get_word(0);
+            if len < WORD_SIZE {
+                word &= (1 << len) - 1;
+            }
+            word.reverse_bits() >> (WORD_SIZE - self.width)
+        }
+    }
+
+    // index of the first key not smaller than x
+    fn succ_index(&self, x: BitSlice) -> usize {
+        let p = self.to_int(x);
+        let i = self.ef.rank(p);
+        // a longer x is greater than the key equal to its first `width` bits
+        if x.len() > self.width && i < self.ef.len() && self.ef.get(i) == p { i + 1 } else { i }
+    }
+
+    fn to_str(&self, x: usize) -> Str {
+        str_from_int(x, self.width, BitOrder::MsbFirst)
+    }
+}
+
+impl Default for EliasFanoTrie {
+    fn default() -> EliasFanoTrie {
+        EliasFanoTrie::new()
+    }
+}
+
+impl Trie for EliasFanoTrie {
+    fn build(&mut self, v: &[Str]) {
+        self.width = v.first().map_or(0, |x| x.len());
+        assert!(
+            self.width <= WORD_SIZE && v.iter().all(|x| x.len() == self.width),
+            "Build error: keys must have the same length, at most 64 bits"
+        );
+
+        let mut x: Vec<usize> = v.iter().map(|i| self.to_int(i.as_bit_slice())).collect();
+        x.sort();
+        self.ef = EliasFano::from_sorted(x);
+    }
+
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        let x = x.as_bit_slice();
+        let p = self.to_int(x);
+        // a longer x is greater than the key equal to its first `width` bits
+        let i = self.ef.rank(p);
+        if x.len() > self.width && i < self.ef.len() && self.ef.get(i) == p {
+            Some(Key::Owned(OrdStr(self.to_str(p))))
+        } else {
+            self.ef.predecessor(p).map(|k| Key::Owned(OrdStr(self.to_str(k))))
+        }
+    }
+
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        let i = self.succ_index(x.as_bit_slice());
+        if i < self.ef.len() { Some(Key::Owned(OrdStr(self.to_str(self.ef.get(i))))) } else { None }
+    }
+
+    fn ex_pref_query(&self, x: impl AsBitSlice) -> bool {
+        let x = x.as_bit_slice();
+        let i = self.succ_index(x);
+        if x.len() > self.width || i == self.ef.len() {
+            false
+        } else {
+            // the successor shares its first |x| bits with the padded x
+            let diff = self.ef.get(i) ^ self.to_int(x);
+            diff.checked_shr((self.width - x.len()) as u32).unwrap_or(0) == 0
+        }
+    }
+
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool {
+        let (x, y) = (x.as_bit_slice(), y.as_bit_slice());
+        let i = self.succ_index(x);
+        if i == self.ef.len() {
+            false
+        } else {
+            let (k, q) = (self.ef.get(i), self.to_int(y));
+            k < q || (k == q && y.len() > self.width)
+        }
+    }
+
+    fn rank_query(&self, x: impl AsBitSlice) -> usize {
+        self.succ_index(x.as_bit_slice())
+    }
+
+    fn stats(&self) -> TrieStats {
+        let keys: Vec<Str> = self.ef.iter().map(|x| self.to_str(x)).collect();
+        let mut res = TrieStats::of_keys(&keys);
+        res.rank_directory_bits = self.ef.directory_bits();
+        res
+    }
+}
@@ -1,9 +1,18 @@
+mod btree_set_trie;
+pub use btree_set_trie::*;
+
 mod compact_trie;
 pub use compact_trie::*;
 
+mod elias_fano_trie;
+pub use elias_fano_trie::*;
+
 mod naive_trie;
 pub use naive_trie::*;
 
+mod sorted_vec_trie;
+pub use sorted_vec_trie::*;
+
 mod z_fast_trie;
 pub use z_fast_trie::*;
 
@@ -1,52 +1,62 @@
 use crate::traits::*;
+use crate::utils::bit_slice::*;
+use crate::utils::ord_str::*;
 use crate::utils::str::*;
-use std::cmp::min;
 use std::cmp::Ordering::*;
 
 pub struct NaiveTrie {
-    v: Vec<Str>,
+    v: Vec<OrdStr>,
 }
 
 impl NaiveTrie {
     pub fn new() -> NaiveTrie {
         NaiveTrie { v: vec![] }
     }
+
+    fn succ(&self, x: BitSlice) -> Option<&OrdStr> {
+        self.v.iter().find(|i| cmp_prefix(i, x, x.len()) != Less)
+    }
 }
 
 impl Trie for NaiveTrie {
-    fn build(&mut self, v: &Vec<Str>) {
-        self.v = v.to_vec();
-        self.v.sort_by(cmp);
+    fn build(&mut self, v: &[Str]) {
+        self.v = v.iter().map(OrdStr::from).collect();
+        self.v.sort();
     }
 
-    fn pred_query(&self, x: &Str) -> Option<Str> {
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        let x = x.as_bit_slice();
         let mut res = None;
         for i in &self.v {
-            if cmp(i,x) == Less {
+            if cmp_prefix(i, x, x.len()) == Less {
                 res = Some(i);
             } else {
                 break;
             }
         }
-        res.cloned()
+        res.map(Key::Borrowed)
     }
 
-    fn succ_query(&self, x: &Str) -> Option<Str> {
-        let mut res = None;
-        for i in &self.v {
-            if cmp(i,x) != Less {
-                res = Some(i);
-                break;
-            }
-        }
-        res.cloned()
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.succ(x.as_bit_slice()).map(Key::Borrowed)
+    }
+
+    fn ex_pref_query(&self, x: impl AsBitSlice) -> bool {
+        let x = x.as_bit_slice();
+        if let Some(i) = self.succ(x) { is_prefix_of(x, i) } else { false }
+    }
+
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool {
+        let y = y.as_bit_slice();
+        if let Some(i) = self.succ(x.as_bit_slice()) { cmp_prefix(i, y, y.len()) == Less } else { false }
     }
 
-    fn ex_pref_query(&self, x: &Str) -> bool {
-        if let Some(i) = &self.succ_query(x) { get_substr(i,0,min(i.len(), x.len())) == *x } else { false }
+    fn rank_query(&self, x: impl AsBitSlice) -> usize {
+        let x = x.as_bit_slice();
+        self.v.iter().take_while(|i| cmp_prefix(i, x, x.len()) == Less).count()
     }
 
-    fn ex_range_query(&self, x: &Str, y: &Str) -> bool {
-        if let Some(i) = &self.succ_query(x) { cmp(i,y) == Less } else { false }
+    fn stats(&self) -> TrieStats {
+        TrieStats::of_keys(&self.v)
     }
 }
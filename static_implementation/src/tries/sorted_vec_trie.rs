@@ -0,0 +1,61 @@
+use crate::traits::*;
+use crate::utils::bit_slice::*;
+use crate::utils::ord_str::*;
+use crate::utils::str::*;
+use std::cmp::Ordering::*;
+
+// baseline for the benchmarks: the sorted keys and a binary search for every query
+pub struct SortedVecTrie {
+    v: Vec<OrdStr>,
+}
+
+impl SortedVecTrie {
+    pub fn new() -> SortedVecTrie {
+        SortedVecTrie { v: vec![] }
+    }
+
+    // index of the first key not smaller than x
+    fn succ_index(&self, x: BitSlice) -> usize {
+        self.v.partition_point(|k| cmp_prefix(k, x, x.len()) == Less)
+    }
+}
+
+impl Default for SortedVecTrie {
+    fn default() -> SortedVecTrie {
+        SortedVecTrie::new()
+    }
+}
+
+impl Trie for SortedVecTrie {
+    fn build(&mut self, v: &[Str]) {
+        self.v = v.iter().map(OrdStr::from).collect();
+        self.v.sort();
+    }
+
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        let i = self.succ_index(x.as_bit_slice());
+        if i > 0 { Some(Key::Borrowed(&self.v[i - 1])) } else { None }
+    }
+
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.v.get(self.succ_index(x.as_bit_slice())).map(Key::Borrowed)
+    }
+
+    fn ex_pref_query(&self, x: impl AsBitSlice) -> bool {
+        let x = x.as_bit_slice();
+        self.v.get(self.succ_index(x)).is_some_and(|k| is_prefix_of(x, k))
+    }
+
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool {
+        let y = y.as_bit_slice();
+        self.v.get(self.succ_index(x.as_bit_slice())).is_some_and(|k| cmp_prefix(k, y, y.len()) == Less)
+    }
+
+    fn rank_query(&self, x: impl AsBitSlice) -> usize {
+        self.succ_index(x.as_bit_slice())
+    }
+
+    fn stats(&self) -> TrieStats {
+        TrieStats::of_keys(&self.v)
+    }
+}
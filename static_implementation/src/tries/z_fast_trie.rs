@@ -1,133 +1,427 @@
+use crate::instrumentation;
 use crate::traits::*;
 use crate::utils::*;
-use std::cmp::min;
+use rand::prelude::*;
 use std::cmp::Ordering::*;
 use std::collections::HashMap;
+use std::io::{ self, Write };
+use std::sync::atomic::{ AtomicUsize, Ordering::Relaxed };
 
+// hash functions tried by a build with verify_handles before it keeps the colliding handles
+// aside, where every probe of their hash has to compare extents
+const MAX_HASH_ATTEMPTS: usize = 16;
+
+// the nodes are stored in preorder, the root first, and refer to each other by index:
+// the read path takes no borrow and the trie can be shared between threads
 pub struct ZFastTrie<H: Hash<DomainType = Str>> {
-    root: Option<Ptr<TrieNode>>,
-    z_map: HashMap<H::HashType, Ptr<TrieNode>>,
+    nodes: Vec<TrieNode>,
+    keys: Vec<OrdStr>,
+    z_map: HashMap<H::HashType, usize>,
+    // with verify_handles, the nodes whose handle hashes like the one of a node in the z-map
+    colliding: HashMap<H::HashType, Vec<usize>>,
+    seed: u64,
+    hash: H,
+    verify_handles: bool,
+    // handles overwritten in the z-map by the build, and false hits detected by the queries
+    build_collisions: usize,
+    collisions: AtomicUsize,
 }
 
 struct TrieNode {
-    left: Option<Ptr<TrieNode>>,
-    right: Option<Ptr<TrieNode>>,
+    left: Option<usize>,
+    right: Option<usize>,
     lind: usize,
-    jump_left: Option<Ptr<TrieNode>>,
-    jump_right: Option<Ptr<TrieNode>>,
-    to_leaf: Option<Ptr<TrieNode>>,
-    to_internal: Option<Ptr<TrieNode>>,
-    extent: Option<Str>,
+    jump_left: Option<usize>,
+    jump_right: Option<usize>,
+    to_leaf: Option<usize>,
+    to_internal: Option<usize>,
+    rind: usize,
+    // index in the sorted keys, only for leaves
+    key: Option<usize>,
 }
 
 impl<H: Hash<DomainType = Str>> Trie for ZFastTrie<H> {
-    fn build(&mut self, v: &Vec<Str>) {
-        let mut x = v.to_vec();
-        x.sort_by(cmp);
-        self.z_map = HashMap::new();
-        self.root = ZFastTrie::<H>::build_tree(&x, 0, 0, v.len(), &mut None).0;
-        if let Some(r) = &self.root {
-            r.borrow().precalc_z_map(&mut self.z_map, &mut H::new(), copy_ptr(&r));
-            r.borrow_mut().precalc_jumps(copy_ptr(&r));
-        }
+    fn build(&mut self, v: &[Str]) {
+        self.build_with(
+            v,
+            1,
+            |ds| (0..ds.nodes.len()).map(|p| ds.handle_hash(p)).collect(),
+            |ds| (0..ds.nodes.len()).map(|p| ds.node_jumps(p)).collect(),
+        );
+    }
+
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.pred_query_with(&self.query_context(&x))
+    }
+
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.succ_query_with(&self.query_context(&x))
+    }
+
+    fn ex_pref_query(&self, x: impl AsBitSlice) -> bool {
+        self.ex_pref_query_with(&self.query_context(&x))
+    }
+
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool {
+        self.ex_range_query_with(&self.query_context(&x), &self.query_context(&y))
+    }
+
+    fn rank_query(&self, x: impl AsBitSlice) -> usize {
+        self.rank_query_with(&self.query_context(&x))
+    }
+
+    fn pred_batch(&self, xs: &[Str]) -> Vec<Option<Key<'_>>> {
+        self.batch(xs, |x, eta| eta.and_then(|eta| self.query_from_exit(x, eta).0))
     }
 
-    fn pred_query(&self, x: &Str) -> Option<Str> {
-        self.query(x).0
+    fn succ_batch(&self, xs: &[Str]) -> Vec<Option<Key<'_>>> {
+        self.batch(xs, |x, eta| eta.and_then(|eta| self.query_from_exit(x, eta).1))
     }
 
-    fn succ_query(&self, x: &Str) -> Option<Str> {
-        self.query(x).1
+    fn ex_pref_batch(&self, xs: &[Str]) -> Vec<bool> {
+        self.batch(xs, |x, eta| eta.is_some_and(|eta| self.is_extent_prefixed_by(eta, x)))
     }
 
-    fn ex_pref_query(&self, x: &Str) -> bool {
-        if let Some(i) = &self.pref_query(x) {
-            i.len() == x.len() && get_substr(i,0,min(i.len(), x.len())) == *x
+    // the ranges are visited by left end, and both ends are hashed from the previous ones
+    fn ex_range_batch(&self, ranges: &[(Str, Str)]) -> Vec<bool> {
+        let mut order: Vec<usize> = (0..ranges.len()).collect();
+        order.sort_by(|&i, &j| cmp(&ranges[i].0, &ranges[j].0));
+
+        let mut res = vec![false; ranges.len()];
+        let (mut cx, mut cy) = (None, None);
+        for i in order {
+            let (x, y) = &ranges[i];
+            let (nx, ny) = (self.next_context(cx.take(), x.as_bit_slice()), self.next_context(cy.take(), y.as_bit_slice()));
+            res[i] = self.is_nonempty(&nx, &ny);
+            (cx, cy) = (Some(nx), Some(ny));
+        }
+        res
+    }
+
+    fn stats(&self) -> TrieStats {
+        let mut res = TrieStats::new(self.keys.len());
+        if !self.nodes.is_empty() {
+            self.node_stats(0, 0, &mut res);
+        }
+        res.z_map_entries = self.z_map.len() + self.colliding.values().map(Vec::len).sum::<usize>();
+        res
+    }
+
+    fn query_all(&self, x: impl AsBitSlice) -> QueryAnswers<'_> {
+        let x = x.as_bit_slice();
+        if let Some(eta) = self.locate_exit(&self.query_context(&x), x.len()) {
+            let (pred, succ) = self.query_from_exit(x, eta);
+            let ex_pref = self.is_extent_prefixed_by(eta, x);
+            QueryAnswers { pred, succ, ex_pref }
         } else {
-            false
+            QueryAnswers { pred: None, succ: None, ex_pref: false }
         }
     }
 
-    fn ex_range_query(&self, x: &Str, y: &Str) -> bool {
-        self.is_nonempty(x, y)
+    fn validate(&self) -> Result<(), InvariantViolation> {
+        // the leaves point to the keys in order, and the checks of the nodes rely on their order
+        for i in 1..self.keys.len() {
+            if self.keys[i - 1] >= self.keys[i] {
+                return Err(InvariantViolation::UnsortedLeaves { leaf: i - 1 });
+            }
+        }
+
+        let (mut leaves, mut handles) = (vec![], vec![]);
+        if !self.nodes.is_empty() {
+            self.validate_node(0, 0, &mut leaves, &mut handles)?;
+        }
+        if leaves.len() != self.keys.len() {
+            return Err(InvariantViolation::KeyCount { leaves: leaves.len(), keys: self.keys.len() });
+        }
+
+        // the leaves are linked through their jumps, and only the last one has no internal node
+        let links_to = |p: Option<usize>, i: Option<usize>| p == i.and_then(|i| leaves.get(i).copied());
+        for (i, &leaf) in leaves.iter().enumerate() {
+            let leaf = &self.nodes[leaf];
+            let last = i + 1 == leaves.len();
+            if !links_to(leaf.jump_left, i.checked_sub(1)) ||
+                !links_to(leaf.jump_right, Some(i + 1)) ||
+                (last && leaf.to_internal.is_some())
+            {
+                return Err(InvariantViolation::LeafLink { leaf: i });
+            }
+        }
+
+        // a handle missing from the z-map collided with the one of the node that took its place,
+        // or was kept aside by verify_handles, and the build counted every such collision
+        let (mut missing, mut kept) = (0, 0);
+        for (handle, node) in &handles {
+            let h = self.hash.slow_prefix_hash(handle.as_bit_slice(), handle.len());
+            let ok = match self.z_map.get(&h) {
+                Some(p) if p == node => true,
+                Some(_) if self.verify_handles => {
+                    kept += 1;
+                    self.colliding.get(&h).is_some_and(|c| c.contains(node))
+                }
+                Some(&p) => {
+                    missing += 1;
+                    self.get_handle(p).is_some_and(|s| self.hash.slow_prefix_hash(s.as_bit_slice(), s.len()) == h)
+                }
+                None => false,
+            };
+            if !ok {
+                let node = &self.nodes[*node];
+                return Err(InvariantViolation::ZMap { lind: node.lind, rind: node.rind });
+            }
+        }
+        let entries = self.z_map.len() + self.colliding.values().map(Vec::len).sum::<usize>();
+        if missing + kept != self.build_collisions || entries + missing != handles.len() {
+            return Err(InvariantViolation::ZMapSize { nodes: handles.len(), entries });
+        }
+        Ok(())
     }
 }
 
 impl<H: Hash<DomainType = Str>> ZFastTrie<H> {
     pub fn new() -> ZFastTrie<H> {
-        ZFastTrie::<H> { root: None, z_map: HashMap::new() }
+        ZFastTrie::<H>::with_seed(thread_rng().next_u64())
+    }
+
+    // the seed of the hash; a build that rehashes the handles after a collision draws the
+    // next hash seeds from it, so hash().seed() may differ from seed but the same keys
+    // always get the same hash
+    pub fn with_seed(seed: u64) -> ZFastTrie<H> {
+        ZFastTrie::<H> {
+            nodes: vec![],
+            keys: vec![],
+            z_map: HashMap::new(),
+            colliding: HashMap::new(),
+            seed,
+            hash: H::with_seed(seed),
+            verify_handles: false,
+            build_collisions: 0,
+            collisions: AtomicUsize::new(0),
+        }
+    }
+
+    // the hash of the last build, not always the one of with_seed
+    pub fn hash(&self) -> &H {
+        &self.hash
     }
 
+    pub fn set_verify_handles(&mut self, verify_handles: bool) {
+        self.verify_handles = verify_handles;
+    }
+
+    pub fn build_collisions(&self) -> usize {
+        self.build_collisions
+    }
+
+    pub fn collisions(&self) -> usize {
+        self.collisions.load(Relaxed)
+    }
+
+    // the construction of build and par_build, which give the hashes of the handles and
+    // the jumps of every node, computed on one thread or on many
+    fn build_with(
+        &mut self,
+        v: &[Str],
+        threads: usize,
+        hashes: impl Fn(&Self) -> Vec<Option<H::HashType>>,
+        jumps: impl Fn(&Self) -> Vec<Option<(usize, usize)>>
+    ) {
+        let x = par_sort(v, threads);
+        self.z_map = HashMap::new();
+        self.colliding = HashMap::new();
+        self.build_collisions = 0;
+        self.collisions.store(0, Relaxed);
+        self.hash = H::with_seed(self.seed);
+        self.nodes = ZFastTrie::<H>::par_build_tree(&x, threads);
+        self.keys = x.into_iter().map(OrdStr).collect();
+        if !self.nodes.is_empty() {
+            let mut rng = SmallRng::seed_from_u64(self.seed);
+            for attempt in 1.. {
+                // a colliding handle overwrites another one and makes its node unreachable,
+                // unless verify_handles keeps it aside; a hash range smaller than the handles
+                // can't avoid collisions, so the last attempt is kept
+                self.build_collisions = self.fill_z_map(hashes(self));
+                if self.build_collisions == 0 || !self.verify_handles || attempt == MAX_HASH_ATTEMPTS {
+                    break;
+                }
+                self.hash = H::with_seed(rng.next_u64());
+            }
+            self.fill_jumps(jumps(self));
+        }
+    }
+
+    // pushes the subtree of the keys in [l, r) whose skip interval starts at lind and returns
+    // its root and its rightmost leaf; the unary nodes are skipped keeping lind
     fn build_tree(
-        v: &Vec<Str>,
-        ind: usize,
+        nodes: &mut Vec<TrieNode>,
+        v: &[Str],
+        lind: usize,
         l: usize,
         r: usize,
-        last_leaf_ref: &mut Option<Ptr<TrieNode>>
-    ) -> (Option<Ptr<TrieNode>>, Option<Ptr<TrieNode>>) {
-        assert!(ind < v[l].len() || l + 1 >= r, "Build error: v is not prefix free");
+        last_leaf: &mut Option<usize>
+    ) -> Option<(usize, usize)> {
         if l == r {
-            (None, None)
-        } else if l + 1 == r {
-            let leaf = new_ptr(TrieNode {
-                left: None,
-                right: None,
-                lind: ind,
-                jump_left: last_leaf_ref.clone(),
-                jump_right: None,
-                to_leaf: None,
-                to_internal: None,
-                extent: Some(v[l].clone()),
-            });
-
-            let leafp = copy_ptr(&leaf);
-            if let Some(x) = last_leaf_ref {
-                x.borrow_mut().jump_right = Some(leafp.clone());
+            return None;
+        }
+        let res = nodes.len();
+        nodes.push(TrieNode {
+            left: None,
+            right: None,
+            lind,
+            jump_left: None,
+            jump_right: None,
+            to_leaf: None,
+            to_internal: None,
+            rind: lind,
+            key: None,
+        });
+
+        if l + 1 == r {
+            nodes[res].jump_left = *last_leaf;
+            nodes[res].rind = v[l].len();
+            nodes[res].key = Some(l);
+            if let Some(x) = *last_leaf {
+                nodes[x].jump_right = Some(res);
             }
-            *last_leaf_ref = Some(leafp.clone());
+            *last_leaf = Some(res);
+            return Some((res, res));
+        }
+
+        // v is sorted, so the common prefix of v[l..r] is the lcp of its first and last key
+        let ind = lcp_len(&v[l], &v[r - 1]);
+        assert!(ind < v[l].len(), "Build error: v is not prefix free");
+        let mid = l + v[l..r].partition_point(|x| !x[ind]);
 
-            (Some(leaf), Some(leafp))
+        let (left, pl) = ZFastTrie::<H>::build_tree(nodes, v, ind + 1, l, mid, last_leaf).unwrap();
+        let (right, pr) = ZFastTrie::<H>::build_tree(nodes, v, ind + 1, mid, r, last_leaf).unwrap();
+        nodes[res].left = Some(left);
+        nodes[res].right = Some(right);
+        nodes[res].rind = ind;
+        nodes[res].to_leaf = Some(pl);
+        nodes[pl].to_internal = Some(res);
+        Some((res, pr))
+    }
+
+    // the links are shifted by the position of the subtree in the nodes of the trie
+    fn shifted(mut node: TrieNode, d: usize) -> TrieNode {
+        for p in [&mut node.left, &mut node.right, &mut node.jump_left, &mut node.jump_right, &mut node.to_leaf, &mut node.to_internal] {
+            *p = p.map(|p| p + d);
+        }
+        node
+    }
+
+    // the nodes whose handle hashes to h: the one in the z-map, then the colliding ones
+    fn z_map_nodes<'a>(&'a self, h: &H::HashType) -> impl Iterator<Item = usize> + 'a {
+        let first = self.z_map.get(h);
+        let colliding = first.and_then(|_| self.colliding.get(h));
+        first.into_iter().chain(colliding.into_iter().flatten()).copied()
+    }
+
+    fn get_handle(&self, p: usize) -> Option<Str> {
+        let node = &self.nodes[p];
+        if node.to_leaf.is_some() {
+            let h = get_fattest(node.rind, node.lind);
+            Some(self.with_extent(p, |s, _| get_substr(s, 0, h)))
         } else {
-            let mut mid = l;
-            while mid < r && !v[mid][ind] {
-                mid += 1;
-            }
+            None
+        }
+    }
 
-            if mid != l && mid != r {
-                let (l, pl) = ZFastTrie::<H>::build_tree(v, ind + 1, l, mid, last_leaf_ref);
-                let (r, pr) = ZFastTrie::<H>::build_tree(v, ind + 1, mid, r, last_leaf_ref);
+    pub fn query_context<'a, X: AsBitSlice + ?Sized>(&self, x: &'a X) -> QueryContext<'a, H::State> {
+        let x = x.as_bit_slice();
+        QueryContext::new(x, self.hash.compute_state(x))
+    }
 
-                let res = new_ptr(TrieNode {
-                    left: l,
-                    right: r,
-                    lind: ind,
-                    jump_left: None,
-                    jump_right: None,
-                    to_leaf: pl,
-                    to_internal: None,
-                    extent: None,
-                });
+    pub fn pred_query_with(&self, cx: &QueryContext<H::State>) -> Option<Key<'_>> {
+        self.query(cx).0
+    }
 
-                if let Some(p) = &res.borrow().to_leaf {
-                    p.borrow_mut().to_internal = Some(copy_ptr(&res));
-                }
+    pub fn succ_query_with(&self, cx: &QueryContext<H::State>) -> Option<Key<'_>> {
+        self.query(cx).1
+    }
 
-                (Some(res), pr)
-            } else {
-                let (x, y) = ZFastTrie::<H>::build_tree(v, ind + 1, l, r, last_leaf_ref);
-                assert!(x.is_some());
-                let res = x.unwrap();
-                res.borrow_mut().lind = ind;
-                (Some(res), y)
+    pub fn ex_pref_query_with(&self, cx: &QueryContext<H::State>) -> bool {
+        let x = cx.key();
+        if let Some(exit_node) = self.locate_exit(cx, x.len()) {
+            self.is_extent_prefixed_by(exit_node, x)
+        } else {
+            false
+        }
+    }
+
+    pub fn ex_range_query_with(
+        &self,
+        cx: &QueryContext<H::State>,
+        cy: &QueryContext<H::State>
+    ) -> bool {
+        self.is_nonempty(cx, cy)
+    }
+
+    pub fn rank_query_with(&self, cx: &QueryContext<H::State>) -> usize {
+        let x = cx.key();
+        if let Some(eta) = self.locate_exit(cx, x.len()) { self.rank_from_exit(x, eta) } else { 0 }
+    }
+
+    // graphviz rendering of the trie: skip interval, extent and handle of every node,
+    // jump pointers dashed and the links between the leaves dotted
+    pub fn to_dot(&self, w: impl Write) -> io::Result<()> {
+        self.write_dot(w, None)
+    }
+
+    // the same, with the path from the root to the exit node found by locate_exit for x
+    pub fn to_dot_with_query(&self, w: impl Write, x: impl AsBitSlice) -> io::Result<()> {
+        let x = x.as_bit_slice();
+        self.write_dot(w, self.locate_exit(&self.query_context(&x), x.len()))
+    }
+
+    // the nodes are numbered by their index, which is their position in preorder
+    fn write_dot(&self, mut w: impl Write, exit: Option<usize>) -> io::Result<()> {
+        let mut parents = vec![None; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            for child in [node.left, node.right].into_iter().flatten() {
+                parents[child] = Some(i);
+            }
+        }
+        let marks = dot_path_marks(&parents, exit);
+
+        dot_begin(&mut w)?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut label = vec![format!("[{}, {}]", node.lind, node.rind)];
+            label.push(self.with_extent(i, |s, len| dot_extent(s, len)));
+            match node.key {
+                Some(k) => label.push(format!("key {}", k)),
+                None => label.push(format!("handle {}", get_fattest(node.rind, node.lind))),
             }
+            dot_node(&mut w, i, &label, node.key.is_some(), marks[i])?;
         }
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let (Some(l), Some(r)) = (node.left, node.right) {
+                dot_child_edge(&mut w, i, l, false)?;
+                dot_child_edge(&mut w, i, r, true)?;
+            }
+            // the leaves keep their neighbours in the jump pointers
+            for (j, right) in [(node.jump_left, false), (node.jump_right, true)] {
+                match (j, node.key) {
+                    (Some(j), Some(_)) => dot_leaf_edge(&mut w, i, j, right)?,
+                    (Some(j), None) => dot_jump_edge(&mut w, i, j, right)?,
+                    (None, _) => {}
+                }
+            }
+        }
+        dot_end(&mut w)
     }
 
-    fn locate_exit_or_parex_prob(&self, x: &Str) -> Option<Ptr<TrieNode>> {
-        let mut res = None;
-        let (mut a, mut b) = (0, x.len());
-        let hash = H::new();
-        let state = hash.compute_state(x);
+    // all the functions below work on the prefix of length `len` of the key of `cx`,
+    // so that prefixes of an already hashed key reuse its state
+    fn locate_exit_or_parex_prob(
+        &self,
+        cx: &QueryContext<H::State>,
+        len: usize,
+        start: Option<usize>
+    ) -> Option<usize> {
+        let x = cx.key();
+        let a = start.map_or(0, |p| self.nodes[p].rind + 1);
+        let mut res = start;
+        let (mut a, mut b) = (a, len);
         let mut m = {
             if a == 0 { calc(b) } else { calc((a - 1) ^ b) }
         };
@@ -138,22 +432,30 @@ impl<H: Hash<DomainType = Str>> ZFastTrie<H> {
                     if a == 0 { 0 } else { m & b }
                 };
 
-                let beta = self.z_map.get(&hash.fast_prefix_hash(x, &state, f));
-                if let Some(node_ref) = beta {
-                    let rind = node_ref.borrow().get_rind();
-                    let lind = node_ref.borrow().lind;
+                let h = self.hash.fast_prefix_hash(x, cx.state(), f);
+                instrumentation::record(|s| {
+                    s.z_map_probes += 1;
+                    s.z_map_hits += self.z_map.contains_key(&h) as usize;
+                });
+                let beta = self.z_map_nodes(&h).find(|&node_ref| {
+                    let rind = self.nodes[node_ref].rind;
+                    let lind = self.nodes[node_ref].lind;
                     //check importante: serve per la validità del teorema 5
-                    if get_fattest(rind, lind) == f {
-                        a = rind + 1;
-                        res = Some(node_ref.clone());
-                    } else {
-                        //check per evitare underflow
-                        if f == 0 {
-                            break;
-                        }
-                        b = f - 1;
+                    let mut valid = get_fattest(rind, lind) == f;
+                    if valid && self.verify_handles {
+                        valid = self.with_extent(node_ref, |s, _| lcp_len_range(x, 0, s, 0, f) == f);
+                    }
+                    if !valid {
+                        self.collisions.fetch_add(1, Relaxed);
+                        instrumentation::record(|s| s.false_hits += 1);
                     }
+                    valid
+                });
+                if let Some(node_ref) = beta {
+                    a = self.nodes[node_ref].rind + 1;
+                    res = Some(node_ref);
                 } else {
+                    //check per evitare underflow
                     if f == 0 {
                         break;
                     }
@@ -166,11 +468,16 @@ impl<H: Hash<DomainType = Str>> ZFastTrie<H> {
         res
     }
 
-    fn locate_parex(&self, x: &Str) -> Option<Ptr<TrieNode>> {
-        let mut res = None;
-        let (mut a, mut b) = (0, x.len());
-        let hash = H::new();
-        let state = hash.compute_state(x);
+    fn locate_parex(
+        &self,
+        cx: &QueryContext<H::State>,
+        len: usize,
+        start: Option<usize>
+    ) -> Option<usize> {
+        let x = cx.key();
+        let a = start.map_or(0, |p| self.nodes[p].rind + 1);
+        let mut res = start;
+        let (mut a, mut b) = (a, len);
 
         let mut m = {
             if a == 0 { calc(b) } else { calc((a - 1) ^ b) }
@@ -182,24 +489,16 @@ impl<H: Hash<DomainType = Str>> ZFastTrie<H> {
                     if a == 0 { 0 } else { m & b }
                 };
 
-                let beta = self.z_map.get(&hash.fast_prefix_hash(x, &state, f));
+                let h = self.hash.fast_prefix_hash(x, cx.state(), f);
+                instrumentation::record(|s| s.parex_probes += 1);
+                let beta = self.z_map_nodes(&h).find(|&node_ref| {
+                    let rind = self.nodes[node_ref].rind;
+                    let lind = self.nodes[node_ref].lind;
+                    rind < len && get_fattest(rind, lind) == f && self.is_extent_prefix_of(node_ref, x)
+                });
                 if let Some(node_ref) = beta {
-                    let extent = node_ref.borrow().get_extent();
-                    let rind = node_ref.borrow().get_rind();
-                    let lind = node_ref.borrow().lind;
-                    if
-                        extent.len() < x.len() &&
-                        get_fattest(rind, lind) == f &&
-                        extent == get_substr(x,0,extent.len())
-                    {
-                        a = rind + 1;
-                        res = Some(node_ref.clone());
-                    } else {
-                        if f == 0 {
-                            break;
-                        }
-                        b = f - 1;
-                    }
+                    a = self.nodes[node_ref].rind + 1;
+                    res = Some(node_ref);
                 } else {
                     if f == 0 {
                         break;
@@ -213,286 +512,522 @@ impl<H: Hash<DomainType = Str>> ZFastTrie<H> {
         res
     }
 
-    fn locate_exit_or_parex(&self, x: &Str) -> Option<Ptr<TrieNode>> {
-        let mut res = self.locate_exit_or_parex_prob(x);
-        let handle = {
-            if res.is_some() {
-                res.as_ref().unwrap().borrow().get_handle().unwrap()
+    // `start`, when given, is a node whose extent is a prefix of the key shorter than len:
+    // the search goes on below it
+    fn locate_exit_or_parex(
+        &self,
+        cx: &QueryContext<H::State>,
+        len: usize,
+        start: Option<usize>
+    ) -> Option<usize> {
+        let x = cx.key();
+        let mut res = self.locate_exit_or_parex_prob(cx, len, start);
+        let valid = {
+            if let Some(node_ref) = res {
+                self.is_handle_prefix_of(node_ref, x, len)
             } else {
-                Str::new(0)
+                true
             }
         };
 
-        if handle != get_substr(x,0,min(x.len(), handle.len())) {
-            res = self.locate_parex(x);
+        if !valid {
+            instrumentation::record(|s| s.parex_fallbacks += 1);
+            res = self.locate_parex(cx, len, start);
         }
         res
     }
 
-    fn locate_exit_from_node(
-        &self,
-        x: &Str,
-        sigma: Option<Ptr<TrieNode>>
-    ) -> Option<Ptr<TrieNode>> {
-        if let Some(node_ref) = &sigma {
-            let node = node_ref.borrow();
-            let extent = node.get_extent();
-
-            if extent.len() < x.len() && extent == get_substr(x,0,min(extent.len(), x.len())) {
-                if !x[extent.len()] {
-                    assert!(node.left.is_some());
-                    Some(copy_ptr(&node.left.as_ref().unwrap()))
+    fn locate_exit_from_node(&self, x: BitSlice, len: usize, sigma: Option<usize>) -> usize {
+        if let Some(node_ref) = sigma {
+            let node = &self.nodes[node_ref];
+            let rind = node.rind;
+
+            if rind < len && self.is_extent_prefix_of(node_ref, x) {
+                if !x[rind] {
+                    node.left.unwrap()
                 } else {
-                    assert!(node.right.is_some());
-                    Some(copy_ptr(&node.right.as_ref().unwrap()))
+                    node.right.unwrap()
                 }
             } else {
-                sigma.clone()
+                node_ref
             }
         } else {
-            assert!(self.root.is_some());
-            let r = self.root.as_ref().unwrap();
-            Some(copy_ptr(&r))
+            0
         }
     }
 
-    fn locate_exit(&self, x: &Str) -> Option<Ptr<TrieNode>> {
-        if !self.root.is_some() {
-            None
+    fn locate_exit(&self, cx: &QueryContext<H::State>, len: usize) -> Option<usize> {
+        self.locate_exit_below(cx, len, None).1
+    }
+
+    // the parent of the exit node, when the search went through it, and the exit node:
+    // the next key of a sorted batch can start from the parent
+    fn locate_exit_below(
+        &self,
+        cx: &QueryContext<H::State>,
+        len: usize,
+        start: Option<usize>
+    ) -> (Option<usize>, Option<usize>) {
+        instrumentation::record(|s| s.exit_searches += 1);
+        if self.nodes.is_empty() {
+            (None, None)
         } else {
-            let res = self.locate_exit_or_parex(x);
-            let eta = {
-                if res.is_some() {
-                    self.locate_exit_from_node(x, res)
-                } else {
-                    Some(copy_ptr(&self.root.as_ref().unwrap()))
-                }
-            };
-            eta.clone()
+            let res = self.locate_exit_or_parex(cx, len, start);
+            let eta = self.locate_exit_from_node(cx.key(), len, res);
+            let rind = self.nodes[eta].rind;
+            (res.filter(|&p| self.nodes[p].rind < rind), Some(eta))
         }
     }
 
-    fn query(&self, x: &Str) -> (Option<Str>, Option<Str>) {
-        if let Some(eta) = self.locate_exit(x) {
-            let (mut prev, mut succ) = (None, None);
+    // the context of x, hashed from the one of the previous key of a batch
+    fn next_context<'a>(&self, prev: Option<QueryContext<'a, H::State>>, x: BitSlice<'a>) -> QueryContext<'a, H::State> {
+        match prev {
+            Some(cy) => {
+                let lcp = lcp_len(cy.key(), x);
+                QueryContext::new(x, self.hash.update_state(x, cy.into_state(), lcp))
+            }
+            None => QueryContext::new(x, self.hash.compute_state(x)),
+        }
+    }
 
-            let leaf;
-            if cmp(x , &eta.borrow().get_extent()) != Greater {
-                leaf = eta.borrow().get_leftmost(eta.clone());
-                succ = Some(leaf.borrow().get_extent());
+    // answers the batch in sorted order: every key is hashed from the state of the previous
+    // one, and its exit node is searched below the parent of the previous exit node when
+    // their common prefix covers its extent
+    fn batch<T>(&self, xs: &[Str], mut f: impl FnMut(BitSlice, Option<usize>) -> T) -> Vec<T> {
+        let mut order: Vec<usize> = (0..xs.len()).collect();
+        order.sort_by(|&i, &j| cmp(&xs[i], &xs[j]));
 
-                if let Some(leaf2) = &leaf.borrow().jump_left {
-                    prev = Some(leaf2.borrow().get_extent());
-                }
-            } else {
-                leaf = eta.borrow().get_rightmost(eta.clone());
-                prev = Some(leaf.borrow().get_extent());
+        let mut res: Vec<Option<T>> = xs.iter().map(|_| None).collect();
+        let (mut cx, mut parex, mut eta) = (None, None, None);
+        for i in order {
+            let x = xs[i].as_bit_slice();
+            let lcp = cx.as_ref().map_or(0, |cy: &QueryContext<_>| lcp_len(cy.key(), x));
+            // a repeated key has the same exit node
+            if cx.as_ref().is_none_or(|cy| lcp < x.len() || lcp < cy.key().len()) {
+                let start = parex.filter(|&p: &usize| self.nodes[p].rind < lcp);
+                let next = self.next_context(cx.take(), x);
+                (parex, eta) = self.locate_exit_below(&next, x.len(), start);
+                cx = Some(next);
+            }
+            res[i] = Some(f(x, eta));
+        }
+        res.into_iter().map(Option::unwrap).collect()
+    }
 
-                if let Some(leaf2) = &leaf.borrow().jump_right {
-                    succ = Some(leaf2.borrow().get_extent());
-                }
+    fn get_key(&self, leaf: usize) -> Key<'_> {
+        Key::Borrowed(&self.keys[self.nodes[leaf].key.unwrap()])
+    }
+
+    fn query_from_exit(&self, x: BitSlice, eta: usize) -> (Option<Key<'_>>, Option<Key<'_>>) {
+        let (mut prev, mut succ) = (None, None);
+
+        if self.cmp_extent(eta, x) != Greater {
+            let leaf = self.get_leftmost(eta);
+            succ = Some(self.get_key(leaf));
+
+            if let Some(leaf2) = self.nodes[leaf].jump_left {
+                prev = Some(self.get_key(leaf2));
             }
+        } else {
+            let leaf = self.get_rightmost(eta);
+            prev = Some(self.get_key(leaf));
 
-            (prev, succ)
+            if let Some(leaf2) = self.nodes[leaf].jump_right {
+                succ = Some(self.get_key(leaf2));
+            }
+        }
+
+        (prev, succ)
+    }
+
+    // the leaves are numbered as the keys: the ones smaller than x end right before the
+    // leftmost leaf below the exit node, or right after the rightmost one
+    fn rank_from_exit(&self, x: BitSlice, eta: usize) -> usize {
+        if self.cmp_extent(eta, x) != Greater {
+            self.nodes[self.get_leftmost(eta)].key.unwrap()
         } else {
-            (None, None)
+            self.nodes[self.get_rightmost(eta)].key.unwrap() + 1
         }
     }
 
-    fn pref_query(&self, x: &Str) -> Option<Str> {
-        if let Some(exit_node) = self.locate_exit(x) {
-            Some(exit_node.borrow().get_prefix_extent(x.len()))
+    fn query(&self, cx: &QueryContext<H::State>) -> (Option<Key<'_>>, Option<Key<'_>>) {
+        let x = cx.key();
+        if let Some(eta) = self.locate_exit(cx, x.len()) {
+            self.query_from_exit(x, eta)
         } else {
-            None
+            (None, None)
         }
     }
 
-    fn is_nonempty(&self, x: &Str, y: &Str) -> bool {
+    fn is_nonempty(&self, cx: &QueryContext<H::State>, cy: &QueryContext<H::State>) -> bool {
+        let (x, y) = (cx.key(), cy.key());
         if x == y {
             false
-        } else if let Some(mut alpha) = self.locate_exit(x) {
-            if cmp(x,&alpha.borrow().get_extent()) != Greater {
-                while
-                    alpha.borrow().to_leaf.is_some() &&
-                    alpha.borrow().get_extent().len() < y.len()
-                {
-                    let tmp = alpha.borrow().jump_left.clone().unwrap();
-                    alpha = tmp;
+        } else if let Some(mut alpha) = self.locate_exit(cx, x.len()) {
+            if self.cmp_extent(alpha, x) != Greater {
+                while self.nodes[alpha].to_leaf.is_some() && self.nodes[alpha].rind < y.len() {
+                    instrumentation::record(|s| s.jump_hops += 1);
+                    alpha = self.nodes[alpha].jump_left.unwrap();
                 }
-                return cmp(&alpha.borrow().get_extent(),y) == Less;
+                return self.cmp_extent(alpha, y) == Greater;
             }
 
-            let mut beta = self.locate_exit(y).unwrap();
-            if cmp(x,&beta.borrow().get_extent()) == Greater {
-                while beta.borrow().to_leaf.is_some() && beta.borrow().get_extent().len() < x.len() {
-                    let tmp = beta.borrow().jump_right.clone().unwrap();
-                    beta = tmp;
+            let mut beta = self.locate_exit(cy, y.len()).unwrap();
+            if self.cmp_extent(beta, x) == Greater {
+                while self.nodes[beta].to_leaf.is_some() && self.nodes[beta].rind < x.len() {
+                    instrumentation::record(|s| s.jump_hops += 1);
+                    beta = self.nodes[beta].jump_right.unwrap();
                 }
-                return cmp(x,&beta.borrow().get_extent()) != Greater;
+                return self.cmp_extent(beta, x) != Greater;
             }
 
-            let z = lcp(x, y);
-            let eta = self.locate_exit(&z).unwrap();
+            // lcp(x, y) is a prefix of x: its exit node is found with the state of x
+            let z = lcp_len(x, y);
+            let eta = self.locate_exit(cx, z).unwrap();
 
-            alpha = copy_ptr(&eta.borrow().left.as_ref().unwrap());
-            while alpha.borrow().to_leaf.is_some() && alpha.borrow().get_extent().len() < x.len() {
-                let tmp = alpha.borrow().jump_right.clone().unwrap();
-                alpha = tmp;
+            alpha = self.nodes[eta].left.unwrap();
+            while self.nodes[alpha].to_leaf.is_some() && self.nodes[alpha].rind < x.len() {
+                instrumentation::record(|s| s.jump_hops += 1);
+                alpha = self.nodes[alpha].jump_right.unwrap();
             }
-            if cmp(x,&alpha.borrow().get_extent()) != Greater {
+            if self.cmp_extent(alpha, x) != Greater {
                 return true;
             }
 
-            beta = copy_ptr(&eta.borrow().right.as_ref().unwrap());
-            while beta.borrow().to_leaf.is_some() && beta.borrow().get_extent().len() < y.len() {
-                let tmp = beta.borrow().jump_left.clone().unwrap();
-                beta = tmp;
+            beta = self.nodes[eta].right.unwrap();
+            while self.nodes[beta].to_leaf.is_some() && self.nodes[beta].rind < y.len() {
+                instrumentation::record(|s| s.jump_hops += 1);
+                beta = self.nodes[beta].jump_left.unwrap();
             }
 
-            let candidate = beta.borrow().get_extent();
-            cmp(&candidate,y) == Less
+            self.cmp_extent(beta, y) == Greater
         } else {
             false
         }
     }
-}
 
-impl TrieNode {
-    fn get_leftmost(&self, noderef: Ptr<TrieNode>) -> Ptr<TrieNode> {
-        if !self.extent.is_some() {
-            self.jump_left
-                .as_ref()
-                .unwrap()
-                .borrow()
-                .get_leftmost(self.jump_left.as_ref().unwrap().clone())
-        } else {
-            noderef
+    fn node_stats(&self, p: usize, depth: usize, res: &mut TrieStats) {
+        let node = &self.nodes[p];
+        res.add_node(node.lind, node.rind, depth, node.key.is_some());
+        for child in [node.left, node.right].into_iter().flatten() {
+            self.node_stats(child, depth + 1, res);
         }
     }
 
-    fn get_rightmost(&self, noderef: Ptr<TrieNode>) -> Ptr<TrieNode> {
-        if !self.extent.is_some() {
-            self.jump_right
-                .as_ref()
-                .unwrap()
-                .borrow()
-                .get_rightmost(self.jump_right.as_ref().unwrap().clone())
-        } else {
-            noderef
+    // checks the subtree whose skip interval should start at lind and returns the range
+    // of its leaves; the leaves and the handles of the internal nodes are collected in order
+    fn validate_node(
+        &self,
+        p: usize,
+        lind: usize,
+        leaves: &mut Vec<usize>,
+        handles: &mut Vec<(Str, usize)>
+    ) -> Result<(usize, usize), InvariantViolation> {
+        let (keys, node) = (&self.keys, &self.nodes[p]);
+        let rind = node.rind;
+        if node.lind != lind || lind > rind {
+            return Err(InvariantViolation::SkipInterval { lind: node.lind, rind });
+        }
+
+        match (node.left, node.right) {
+            (None, None) => {
+                let i = leaves.len();
+                if node.key != Some(i) {
+                    return Err(InvariantViolation::LeafKey { leaf: i, key: node.key });
+                }
+                if i < keys.len() && keys[i].len() != rind {
+                    return Err(InvariantViolation::SkipInterval { lind, rind });
+                }
+                if node.to_leaf.is_some() {
+                    return Err(InvariantViolation::BackLink { lind, rind });
+                }
+                leaves.push(p);
+                Ok((i, i + 1))
+            }
+            (Some(left), Some(right)) => {
+                let (lo, mid) = self.validate_node(left, rind + 1, leaves, handles)?;
+                let (_, hi) = self.validate_node(right, rind + 1, leaves, handles)?;
+                if hi > keys.len() {
+                    return Err(InvariantViolation::KeyCount { leaves: hi, keys: keys.len() });
+                }
+
+                // the keys below share the extent and the children split them on the bit at rind
+                let (a, b) = (&keys[mid - 1], &keys[mid]);
+                if
+                    lcp_len(&keys[lo], &keys[hi - 1]) != rind ||
+                    lcp_len(a, b) != rind ||
+                    rind >= a.len() ||
+                    rind >= b.len() ||
+                    a[rind] ||
+                    !b[rind]
+                {
+                    return Err(InvariantViolation::Extent { lind, rind });
+                }
+
+                let to_leaf = leaves[mid - 1];
+                let linked = node.to_leaf == Some(to_leaf) && self.nodes[to_leaf].to_internal == Some(p);
+                if !linked || node.to_internal.is_some() {
+                    return Err(InvariantViolation::BackLink { lind, rind });
+                }
+
+                let h = get_fattest(rind, lind);
+                InvariantViolation::check_handle(lind, rind, h)?;
+                handles.push((get_substr(&keys[lo], 0, h), p));
+
+                let t = if h == 0 { usize::MAX } else { h + (1 << h.trailing_zeros()) };
+                if node.jump_left != Some(self.jump_target(left, t, false)) {
+                    return Err(InvariantViolation::Jump { lind, rind, right: false });
+                }
+                if node.jump_right != Some(self.jump_target(right, t, true)) {
+                    return Err(InvariantViolation::Jump { lind, rind, right: true });
+                }
+                Ok((lo, hi))
+            }
+            _ => Err(InvariantViolation::ChildCount { lind }),
         }
     }
 
-    fn get_rind(&self) -> usize {
-        if let Some(p) = &self.left {
-            p.borrow().lind - 1
-        } else {
-            assert!(self.extent.is_some());
-            let s = self.extent.as_ref().unwrap();
-            s.len()
+    // the first node on the leftmost (rightmost) path from p whose skip interval contains t,
+    // or the leaf at its end
+    fn jump_target(&self, mut p: usize, t: usize, right: bool) -> usize {
+        loop {
+            let node = &self.nodes[p];
+            let next = if node.lind <= t && t <= node.rind {
+                None
+            } else if right {
+                node.right
+            } else {
+                node.left
+            };
+            match next {
+                Some(q) => p = q,
+                None => return p,
+            }
         }
     }
 
-    fn get_extent(&self) -> Str {
-        let rind = self.get_rind();
+    fn get_leftmost(&self, mut p: usize) -> usize {
+        while self.nodes[p].key.is_none() {
+            p = self.nodes[p].jump_left.unwrap();
+        }
+        p
+    }
 
-        if let Some(p) = &self.to_leaf {
-            assert!(p.borrow().extent.is_some());
-            get_substr(p.borrow().extent.as_ref().unwrap(),0,rind)
-        } else {
-            assert!(self.extent.is_some());
-            get_substr(self.extent.as_ref().unwrap(),0,rind)
+    fn get_rightmost(&self, mut p: usize) -> usize {
+        while self.nodes[p].key.is_none() {
+            p = self.nodes[p].jump_right.unwrap();
         }
+        p
     }
 
-    fn get_prefix_extent(&self, x: usize) -> Str {
-        let rind = min(self.get_rind(), x);
+    // calls f with the string of the leaf below the node and the length of the extent,
+    // which is a prefix of it: no copy of the extent is made
+    fn with_extent<R, F: FnOnce(&Str, usize) -> R>(&self, p: usize, f: F) -> R {
+        let node = &self.nodes[p];
+        let leaf = node.to_leaf.map_or(node, |q| &self.nodes[q]);
+        f(&self.keys[leaf.key.unwrap()], node.rind)
+    }
 
-        if let Some(p) = &self.to_leaf {
-            assert!(p.borrow().extent.is_some());
-            let s = get_substr(p.borrow().extent.as_ref().unwrap(),0,rind);
-            s
-        } else {
-            assert!(self.extent.is_some());
-            let s = get_substr(self.extent.as_ref().unwrap(),0,rind);
-            s
-        }
+    // compares x with the extent
+    fn cmp_extent(&self, p: usize, x: BitSlice) -> std::cmp::Ordering {
+        self.with_extent(p, |s, rind| cmp_prefix(x, s, rind))
     }
 
-    fn get_kth_left(&self, noderef: Ptr<TrieNode>, k: usize) -> Ptr<TrieNode> {
-        let rind = self.get_rind();
-        if k >= self.lind && k <= rind {
-            noderef
-        } else if let Some(x) = &self.left {
-            x.borrow().get_kth_left(copy_ptr(&x), k)
-        } else {
-            noderef
-        }
+    fn is_extent_prefix_of(&self, p: usize, x: BitSlice) -> bool {
+        self.with_extent(p, |s, rind| rind <= x.len() && lcp_len_range(x, 0, s, 0, rind) == rind)
     }
 
-    fn get_kth_right(&self, noderef: Ptr<TrieNode>, k: usize) -> Ptr<TrieNode> {
-        let rind = self.get_rind();
-        if k >= self.lind && k <= rind {
-            noderef
-        } else if let Some(x) = &self.right {
-            x.borrow().get_kth_right(copy_ptr(&x), k)
-        } else {
-            noderef
-        }
+    fn is_extent_prefixed_by(&self, p: usize, x: BitSlice) -> bool {
+        self.with_extent(p, |s, rind| {
+            x.len() <= rind && lcp_len_range(x, 0, s, 0, x.len()) == x.len()
+        })
     }
 
-    fn precalc_jumps(&mut self, noderef: Ptr<TrieNode>) {
-        let k = get_fattest(self.get_rind(), self.lind);
-        if let Some(x) = &self.left {
-            self.jump_left = Some(
-                self.get_kth_left(noderef.clone(), if k == 0 {
-                    usize::MAX
-                } else {
-                    k + (1 << k.trailing_zeros())
-                })
-            );
-            x.borrow_mut().precalc_jumps(copy_ptr(x));
-        }
+    fn is_handle_prefix_of(&self, p: usize, x: BitSlice, len: usize) -> bool {
+        let h = get_fattest(self.nodes[p].rind, self.nodes[p].lind);
+        h <= len && self.with_extent(p, |s, _| lcp_len_range(x, 0, s, 0, h) == h)
+    }
+}
 
-        if let Some(x) = &self.right {
-            self.jump_right = Some(
-                self.get_kth_right(noderef, if k == 0 {
-                    usize::MAX
-                } else {
-                    k + (1 << k.trailing_zeros())
-                })
-            );
-            x.borrow_mut().precalc_jumps(copy_ptr(&x));
-        }
+impl<H: Hash<DomainType = Str> + Sync> ZFastTrie<H> where H::HashType: Send + Sync {
+    // the same structure as build, with the keys sorted, the subtrees built and the handles
+    // hashed on `threads` threads, 0 for one per core
+    pub fn par_build(&mut self, v: &[Str], threads: usize) {
+        let ids = |ds: &Self| (0..ds.nodes.len()).collect::<Vec<usize>>();
+        self.build_with(
+            v,
+            threads,
+            |ds| par_map(&ids(ds), threads, |_| 1, |&p| ds.handle_hash(p)),
+            |ds| par_map(&ids(ds), threads, |_| 1, |&p| ds.node_jumps(p)),
+        );
     }
+}
+
+impl<H: Hash<DomainType = Str>> ZFastTrie<H> {
+    // the subtrees below the top of the trie are built concurrently, each one in its own
+    // nodes, then shifted to its position; the top nodes and the links between the
+    // subtrees are filled afterwards
+    fn par_build_tree(v: &[Str], threads: usize) -> Vec<TrieNode> {
+        let top = trie_top(v, threads);
+        let weight = |t: &TrieTop| if let TrieTop::Subtree { l, r, .. } = *t { r - l } else { 0 };
+        let mut subtrees = par_map(&top, threads, weight, |t| match *t {
+            TrieTop::Subtree { pos, lind, l, r } => {
+                let mut nodes = vec![];
+                ZFastTrie::<H>::build_tree(&mut nodes, v, lind, l, r, &mut None);
+                nodes.into_iter().map(|node| ZFastTrie::<H>::shifted(node, pos)).collect()
+            }
+            TrieTop::Internal { .. } => vec![],
+        }).into_iter();
 
-    fn get_handle(&self) -> Option<Str> {
-        let rind = self.get_rind();
-        if let Some(p) = &self.to_leaf {
-            if let Some(s) = &p.borrow().extent {
-                Some(get_substr(s,0,get_fattest(rind, self.lind)))
+        let mut nodes = Vec::with_capacity((2 * v.len()).saturating_sub(1));
+        for t in &top {
+            let subtree = subtrees.next().unwrap();
+            if let TrieTop::Internal { pos, lind, rind, l, mid, .. } = *t {
+                // the left subtree follows its parent, and its rightmost leaf is its last node
+                nodes.push(TrieNode {
+                    left: Some(pos + 1),
+                    right: Some(pos + 2 * (mid - l)),
+                    lind,
+                    jump_left: None,
+                    jump_right: None,
+                    to_leaf: Some(pos + 2 * (mid - l) - 1),
+                    to_internal: None,
+                    rind,
+                    key: None,
+                });
             } else {
-                None
+                nodes.extend(subtree);
             }
-        } else {
-            None
         }
-    }
 
-    fn precalc_z_map<T: Hash<DomainType = Str>>(
-        &self,
-        z_map: &mut HashMap<T::HashType, Ptr<TrieNode>>,
-        hash: &mut T,
-        r: Ptr<TrieNode>
-    ) {
-        if let Some(s) = &self.get_handle() {
-            z_map.insert(hash.slow_prefix_hash(s, s.len()), r);
+        let mut last_leaf = None;
+        for p in 0..nodes.len() {
+            if let Some(q) = nodes[p].to_leaf {
+                nodes[q].to_internal = Some(p);
+            }
+            if nodes[p].key.is_some() {
+                nodes[p].jump_left = last_leaf;
+                if let Some(q) = last_leaf {
+                    nodes[q].jump_right = Some(p);
+                }
+                last_leaf = Some(p);
+            }
         }
+        nodes
+    }
 
-        if let Some(x) = &self.left {
-            x.borrow().precalc_z_map(z_map, hash, copy_ptr(&x));
+    fn handle_hash(&self, p: usize) -> Option<H::HashType> {
+        self.get_handle(p).map(|s| self.hash.slow_prefix_hash(s.as_bit_slice(), s.len()))
+    }
+
+    // the hashes of the handles are inserted in preorder, which is the order of the nodes;
+    // returns the number of handles that collided with another one
+    fn fill_z_map(&mut self, hashes: Vec<Option<H::HashType>>) -> usize {
+        self.z_map = HashMap::new();
+        self.colliding = HashMap::new();
+        let mut collisions = 0;
+        for (p, h) in hashes.into_iter().enumerate() {
+            if let Some(h) = h {
+                if !self.z_map.contains_key(&h) {
+                    self.z_map.insert(h, p);
+                } else {
+                    collisions += 1;
+                    if self.verify_handles {
+                        self.colliding.entry(h).or_default().push(p);
+                    } else {
+                        self.z_map.insert(h, p);
+                    }
+                }
+            }
         }
-        if let Some(x) = &self.right {
-            x.borrow().precalc_z_map(z_map, hash, copy_ptr(&x));
+        collisions
+    }
+
+    fn node_jumps(&self, p: usize) -> Option<(usize, usize)> {
+        let node = &self.nodes[p];
+        node.left.zip(node.right).map(|(l, r)| {
+            let k = get_fattest(node.rind, node.lind);
+            let t = if k == 0 { usize::MAX } else { k + (1 << k.trailing_zeros()) };
+            (self.jump_target(l, t, false), self.jump_target(r, t, true))
+        })
+    }
+
+    fn fill_jumps(&mut self, jumps: Vec<Option<(usize, usize)>>) {
+        for (node, jumps) in self.nodes.iter_mut().zip(jumps) {
+            if let Some((l, r)) = jumps {
+                (node.jump_left, node.jump_right) = (Some(l), Some(r));
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+    use crate::bits;
+    use crate::hashes::*;
+    use crate::testing::*;
+    use InvariantViolation::*;
+
+    type Ds = ZFastTrie<RollingHash>;
+
+    // nodes in preorder: [0, 1], [2, 2], 000, [3, 3], 0010, 0011, 01
+    fn corrupted(f: impl FnOnce(&mut Ds)) -> InvariantViolation {
+        let v = vec![bits!("000"), bits!("0010"), bits!("0011"), bits!("01")];
+        let mut ds = Ds::with_seed(0);
+        ds.build(&v);
+        assert!(ds.validate().is_ok());
+        f(&mut ds);
+        ds.validate().unwrap_err()
+    }
+
+    #[test]
+    fn test_validate_rejects() {
+        assert!(corrupted(|ds| ds.nodes[0].right = None) == ChildCount { lind: 0 });
+        assert!(corrupted(|ds| ds.keys.push(OrdStr(bits!("1")))) == KeyCount { leaves: 4, keys: 5 });
+        assert!(corrupted(|ds| ds.keys.swap(0, 1)) == UnsortedLeaves { leaf: 0 });
+        assert!(corrupted(|ds| ds.nodes[2].key = Some(1)) == LeafKey { leaf: 0, key: Some(1) });
+        assert!(corrupted(|ds| ds.nodes[1].lind = 1) == SkipInterval { lind: 1, rind: 2 });
+        let extent = |ds: &mut Ds| (ds.nodes[0].rind, ds.nodes[1].lind, ds.nodes[6].lind) = (0, 1, 1);
+        assert!(corrupted(extent) == Extent { lind: 0, rind: 0 });
+        assert!(InvariantViolation::check_handle(3, 7, 5) == Err(Handle { lind: 3, rind: 7, handle: 5 }));
+        assert!(corrupted(|ds| ds.nodes[0].jump_left = Some(0)) == Jump { lind: 0, rind: 1, right: false });
+        assert!(corrupted(|ds| ds.nodes[0].jump_right = Some(0)) == Jump { lind: 0, rind: 1, right: true });
+        assert!(corrupted(|ds| ds.nodes[2].jump_right = None) == LeafLink { leaf: 0 });
+        assert!(corrupted(|ds| ds.nodes[1].to_leaf = None) == BackLink { lind: 2, rind: 2 });
+        assert!(corrupted(|ds| ds.nodes[2].to_internal = None) == BackLink { lind: 2, rind: 2 });
+        assert!(corrupted(|ds| ds.z_map.retain(|_, p| *p != 0)) == ZMap { lind: 0, rind: 1 });
+        let extra = |ds: &mut Ds| {
+            let h = (0..).find(|h| !ds.z_map.contains_key(h)).unwrap();
+            ds.z_map.insert(h, 0);
+        };
+        assert!(corrupted(extra) == ZMapSize { nodes: 3, entries: 4 });
+    }
+
+    #[test]
+    fn test_validate_collisions() {
+        let v = KeySet::Random.generate(&mut SmallRng::seed_from_u64(0), 200, 100);
+        let mut ds = Ds::with_seed(0);
+        ds.build(&v);
+
+        // with a hash of 101 values the handles collide, and only the overwritten ones are missing
+        ds.hash = RollingHash::from_parameters(3, 101, 0);
+        ds.build_collisions = ds.fill_z_map((0..ds.nodes.len()).map(|p| ds.handle_hash(p)).collect());
+        assert!(ds.build_collisions > 0 && ds.validate().is_ok());
+        ds.build_collisions -= 1;
+        assert!(matches!(ds.validate(), Err(ZMapSize { .. })));
+        ds.build_collisions += 1;
+        let hs: Vec<usize> = ds.z_map.keys().copied().take(2).collect();
+        let (p, q) = (ds.z_map[&hs[0]], ds.z_map[&hs[1]]);
+        ds.z_map.insert(hs[0], q);
+        ds.z_map.insert(hs[1], p);
+        assert!(matches!(ds.validate(), Err(ZMap { .. })));
+
+        // verify_handles keeps every colliding handle aside
+        ds.verify_handles = true;
+        ds.build_collisions = ds.fill_z_map((0..ds.nodes.len()).map(|p| ds.handle_hash(p)).collect());
+        assert!(ds.build_collisions > 0 && ds.validate().is_ok());
+        ds.colliding.values_mut().next().unwrap().pop();
+        assert!(matches!(ds.validate(), Err(ZMap { .. })));
+    }
+}
@@ -1,166 +1,503 @@
 use crate::static_dicts::minimal_perfect_hash_static_dict::MinimalPerfectHashStaticDict;
+use crate::instrumentation;
 use crate::traits::*;
 use crate::utils::*;
-use refbox::*;
-use std::cmp::min;
+use std::cmp::Ordering;
+use std::io::{ self, Write };
 use std::cmp::Ordering::*;
 
+// the nodes are stored in preorder, the root first, and refer to each other by index:
+// the read path takes no borrow and the trie can be shared between threads
 pub struct ZFastTrieSux<H: Hash<DomainType = Str> + ParametricHash> {
-    root: Option<RefBox<dyn TrieNode>>,
-    z_map: MinimalPerfectHashStaticDict<Str, Ref<dyn TrieNode>, H>,
+    nodes: Vec<TrieNode>,
+    keys: Vec<OrdStr>,
+    z_map: MinimalPerfectHashStaticDict<Str, usize, H>,
 }
 
-trait TrieNode {
-    fn is_leaf(&self) -> bool;
-    fn get_prev(&self) -> &Option<Ref<LeafTrieNode>>;
-    fn get_next(&self) -> &Option<Ref<LeafTrieNode>>;
-    fn get_left(&self) -> Option<Ref<dyn TrieNode>>;
-    fn get_right(&self) -> Option<Ref<dyn TrieNode>>;
-    fn get_leftmost(&self, noderef: Ref<dyn TrieNode>) -> Ref<dyn TrieNode>;
-    fn get_rightmost(&self, noderef: Ref<dyn TrieNode>) -> Ref<dyn TrieNode>;
-    fn get_jump_left(&self) -> Option<Ref<dyn TrieNode>>;
-    fn get_jump_right(&self) -> Option<Ref<dyn TrieNode>>;
-    fn set_lind(&mut self, x: usize);
-    fn get_lind(&self) -> usize;
-    fn get_rind(&self) -> usize;
-    fn get_extent(&self) -> Str;
-    fn get_prefix_extent(&self, x: usize) -> Str;
-    fn get_kth_left(&self, noderef: Ref<dyn TrieNode>, k: usize) -> Ref<dyn TrieNode>;
-    fn get_kth_right(&self, noderef: Ref<dyn TrieNode>, k: usize) -> Ref<dyn TrieNode>;
-    fn precalc_jumps(&mut self, noderef: Ref<dyn TrieNode>);
-    fn get_handle(&self) -> Option<Str>;
-    fn precalc_z_map(
-        &self,
-        keys: &mut Vec<Str>,
-        values: &mut Vec<Ref<dyn TrieNode>>,
-        r: Ref<dyn TrieNode>
-    );
+enum TrieNode {
+    Internal(InternalTrieNode),
+    Leaf(LeafTrieNode),
 }
 
+// the right end of the skip interval is the left end of the children minus one
 struct InternalTrieNode {
-    left: RefBox<dyn TrieNode>,
-    right: RefBox<dyn TrieNode>,
+    left: usize,
+    right: usize,
     lind: usize,
-    jump_left: Ref<dyn TrieNode>,
-    jump_right: Ref<dyn TrieNode>,
-    to_leaf: Ref<LeafTrieNode>,
+    jump_left: usize,
+    jump_right: usize,
+    to_leaf: usize,
 }
 
 struct LeafTrieNode {
     lind: usize,
-    prev: Option<Ref<LeafTrieNode>>,
-    next: Option<Ref<LeafTrieNode>>,
-    to_internal: Option<Ref<InternalTrieNode>>,
-    extent: Str,
+    prev: Option<usize>,
+    next: Option<usize>,
+    to_internal: Option<usize>,
+    rind: usize,
+    key: usize,
 }
 
 impl<H: Hash<DomainType = Str> + ParametricHash> Trie for ZFastTrieSux<H> {
-    fn build(&mut self, v: &Vec<Str>) {
-        let mut x = v.to_vec();
-        x.sort_by(cmp);
-        self.z_map = MinimalPerfectHashStaticDict::new();
-        self.root = ZFastTrieSux::<H>::build_tree(&x, 0, 0, v.len(), &mut None).0;
-        if let Some(r) = &self.root {
-            let mut keys = Vec::new();
-            let mut values = Vec::new();
-            r.try_borrow_mut().unwrap().precalc_z_map(&mut keys, &mut values, r.create_ref());
-            r.try_borrow_mut().unwrap().precalc_jumps(r.create_ref());
-            self.z_map.build(&keys, &values);
-        }
+    fn build(&mut self, v: &[Str]) {
+        self.build_with(
+            v,
+            1,
+            |ds| (0..ds.nodes.len()).map(|p| ds.get_handle(p)).collect(),
+            |ds| (0..ds.nodes.len()).map(|p| ds.node_jumps(p)).collect(),
+            |z_map, handles, values| z_map.build(&handles, &values),
+        );
+    }
+
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.pred_query_with(&self.query_context(&x))
+    }
+
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.succ_query_with(&self.query_context(&x))
+    }
+
+    fn ex_pref_query(&self, x: impl AsBitSlice) -> bool {
+        self.ex_pref_query_with(&self.query_context(&x))
     }
 
-    fn pred_query(&self, x: &Str) -> Option<Str> {
-        self.query(x).0
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool {
+        self.ex_range_query_with(&self.query_context(&x), &self.query_context(&y))
     }
 
-    fn succ_query(&self, x: &Str) -> Option<Str> {
-        self.query(x).1
+    fn rank_query(&self, x: impl AsBitSlice) -> usize {
+        self.rank_query_with(&self.query_context(&x))
     }
 
-    fn ex_pref_query(&self, x: &Str) -> bool {
-        if let Some(i) = &self.pref_query(x) {
-            i.len() == x.len() && get_substr(i,0,min(i.len(), x.len())) == *x
+    fn pred_batch(&self, xs: &[Str]) -> Vec<Option<Key<'_>>> {
+        self.batch(xs, |x, eta| eta.and_then(|eta| self.query_from_exit(x, eta).0))
+    }
+
+    fn succ_batch(&self, xs: &[Str]) -> Vec<Option<Key<'_>>> {
+        self.batch(xs, |x, eta| eta.and_then(|eta| self.query_from_exit(x, eta).1))
+    }
+
+    fn ex_pref_batch(&self, xs: &[Str]) -> Vec<bool> {
+        self.batch(xs, |x, eta| eta.is_some_and(|eta| self.is_extent_prefixed_by(eta, x)))
+    }
+
+    // the ranges are visited by left end, and both ends are hashed from the previous ones
+    fn ex_range_batch(&self, ranges: &[(Str, Str)]) -> Vec<bool> {
+        let mut order: Vec<usize> = (0..ranges.len()).collect();
+        order.sort_by(|&i, &j| cmp(&ranges[i].0, &ranges[j].0));
+
+        let mut res = vec![false; ranges.len()];
+        let (mut cx, mut cy) = (None, None);
+        for i in order {
+            let (x, y) = &ranges[i];
+            let (nx, ny) = (self.next_context(cx.take(), x.as_bit_slice()), self.next_context(cy.take(), y.as_bit_slice()));
+            res[i] = self.is_nonempty(&nx, &ny);
+            (cx, cy) = (Some(nx), Some(ny));
+        }
+        res
+    }
+
+    fn stats(&self) -> TrieStats {
+        let mut res = TrieStats::new(self.keys.len());
+        if !self.nodes.is_empty() {
+            self.node_stats(0, 0, &mut res);
+        }
+        res.z_map_entries = self.z_map.len();
+        let table_size = self.z_map.table_size();
+        if table_size > 0 {
+            res.mphf = Some(MphfStats {
+                table_size,
+                load_factor: (res.z_map_entries as f64) / (table_size as f64),
+                attempts: self.z_map.attempts(),
+            });
+        }
+        res.rank_directory_bits = self.z_map.directory_bits();
+        res
+    }
+
+    fn query_all(&self, x: impl AsBitSlice) -> QueryAnswers<'_> {
+        let x = x.as_bit_slice();
+        if let Some(eta) = self.locate_exit(&self.query_context(&x), x.len()) {
+            let (pred, succ) = self.query_from_exit(x, eta);
+            let ex_pref = self.is_extent_prefixed_by(eta, x);
+            QueryAnswers { pred, succ, ex_pref }
         } else {
-            false
+            QueryAnswers { pred: None, succ: None, ex_pref: false }
         }
     }
 
-    fn ex_range_query(&self, x: &Str, y: &Str) -> bool {
-        self.is_nonempty(x, y)
+    fn validate(&self) -> Result<(), InvariantViolation> {
+        // the leaves point to the keys in order, and the checks of the nodes rely on their order
+        for i in 1..self.keys.len() {
+            if self.keys[i - 1] >= self.keys[i] {
+                return Err(InvariantViolation::UnsortedLeaves { leaf: i - 1 });
+            }
+        }
+
+        let (mut leaves, mut handles) = (vec![], vec![]);
+        if !self.nodes.is_empty() {
+            self.validate_node(0, 0, &mut leaves, &mut handles)?;
+        }
+        if leaves.len() != self.keys.len() {
+            return Err(InvariantViolation::KeyCount { leaves: leaves.len(), keys: self.keys.len() });
+        }
+
+        // the leaves are linked in order, and only the last one has no internal node
+        let links_to = |p: Option<usize>, i: Option<usize>| p == i.and_then(|i| leaves.get(i).copied());
+        for (i, &p) in leaves.iter().enumerate() {
+            let TrieNode::Leaf(leaf) = &self.nodes[p] else { unreachable!() };
+            let last = i + 1 == leaves.len();
+            if !links_to(leaf.prev, i.checked_sub(1)) ||
+                !links_to(leaf.next, Some(i + 1)) ||
+                (last && leaf.to_internal.is_some())
+            {
+                return Err(InvariantViolation::LeafLink { leaf: i });
+            }
+        }
+
+        for (handle, node) in &handles {
+            if self.z_map.get(handle) != Some(node) {
+                return Err(InvariantViolation::ZMap { lind: self.lind(*node), rind: self.rind(*node) });
+            }
+        }
+        // every handle maps to its node, so only extra keys are left in the table
+        if self.z_map.len() != handles.len() {
+            return Err(InvariantViolation::ZMapSize { nodes: handles.len(), entries: self.z_map.len() });
+        }
+        Ok(())
     }
 }
 
 impl<H: Hash<DomainType = Str> + ParametricHash> ZFastTrieSux<H> {
     pub fn new() -> ZFastTrieSux<H> {
-        ZFastTrieSux::<H> { root: None, z_map: MinimalPerfectHashStaticDict::new() }
+        ZFastTrieSux::<H> { nodes: vec![], keys: vec![], z_map: MinimalPerfectHashStaticDict::new() }
+    }
+
+    pub fn with_seed(seed: u64) -> ZFastTrieSux<H> {
+        ZFastTrieSux::<H> {
+            nodes: vec![],
+            keys: vec![],
+            z_map: MinimalPerfectHashStaticDict::with_seed(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.z_map.seed()
+    }
+
+    // the construction of build and par_build, which give the handles and the jumps of
+    // every node and build the z-map, on one thread or on many
+    fn build_with(
+        &mut self,
+        v: &[Str],
+        threads: usize,
+        handles: impl Fn(&Self) -> Vec<Option<Str>>,
+        jumps: impl Fn(&Self) -> Vec<Option<(usize, usize)>>,
+        build_z_map: impl FnOnce(&mut MinimalPerfectHashStaticDict<Str, usize, H>, Vec<Str>, Vec<usize>)
+    ) {
+        let x = par_sort(v, threads);
+        self.z_map = MinimalPerfectHashStaticDict::with_seed(self.z_map.seed());
+        self.nodes = ZFastTrieSux::<H>::par_build_tree(&x, threads);
+        self.keys = x.into_iter().map(OrdStr).collect();
+        if !self.nodes.is_empty() {
+            // the handles are inserted in preorder, which is the order of the nodes
+            let (handles, values): (Vec<Str>, Vec<usize>) = handles(self)
+                .into_iter()
+                .enumerate()
+                .filter_map(|(p, s)| s.map(|s| (s, p)))
+                .unzip();
+            self.fill_jumps(jumps(self));
+            build_z_map(&mut self.z_map, handles, values);
+        }
     }
 
+    // pushes the subtree of the keys in [l, r) whose skip interval starts at lind and returns
+    // its root and its rightmost leaf; the unary nodes are skipped keeping lind
     fn build_tree(
-        v: &Vec<Str>,
-        ind: usize,
+        nodes: &mut Vec<TrieNode>,
+        v: &[Str],
+        lind: usize,
         l: usize,
         r: usize,
-        last_leaf_ref: &mut Option<Ref<LeafTrieNode>>
-    ) -> (Option<RefBox<dyn TrieNode>>, Option<Ref<LeafTrieNode>>) {
-        assert!(ind < v[l].len() || l + 1 >= r, "Build error: v is not prefix free");
+        last_leaf: &mut Option<usize>
+    ) -> Option<(usize, usize)> {
         if l == r {
-            (None, None)
-        } else if l + 1 == r {
-            let leaf = RefBox::new(LeafTrieNode {
-                lind: ind,
-                prev: last_leaf_ref.clone(),
+            return None;
+        }
+        let res = nodes.len();
+
+        if l + 1 == r {
+            nodes.push(TrieNode::Leaf(LeafTrieNode {
+                lind,
+                prev: *last_leaf,
                 next: None,
                 to_internal: None,
-                extent: v[l].clone(),
-            });
+                rind: v[l].len(),
+                key: l,
+            }));
+            if let Some(x) = *last_leaf {
+                if let TrieNode::Leaf(leaf) = &mut nodes[x] {
+                    leaf.next = Some(res);
+                }
+            }
+            *last_leaf = Some(res);
+            return Some((res, res));
+        }
 
-            let leafp = leaf.create_ref();
-            if let Some(x) = last_leaf_ref {
-                x.try_borrow_mut().unwrap().next = Some(leafp.clone());
+        // v is sorted, so the common prefix of v[l..r] is the lcp of its first and last key
+        let ind = lcp_len(&v[l], &v[r - 1]);
+        assert!(ind < v[l].len(), "Build error: v is not prefix free");
+        let mid = l + v[l..r].partition_point(|x| !x[ind]);
+
+        // the links are filled once the children are in place
+        nodes.push(TrieNode::Internal(InternalTrieNode {
+            left: res,
+            right: res,
+            lind,
+            jump_left: res,
+            jump_right: res,
+            to_leaf: res,
+        }));
+        let (left, pl) = ZFastTrieSux::<H>::build_tree(nodes, v, ind + 1, l, mid, last_leaf)
+            .expect("left child in ZFastTrieSux build");
+        let (right, pr) = ZFastTrieSux::<H>::build_tree(nodes, v, ind + 1, mid, r, last_leaf)
+            .expect("right child in ZFastTrieSux build");
+        if let TrieNode::Internal(node) = &mut nodes[res] {
+            (node.left, node.right, node.to_leaf) = (left, right, pl);
+            (node.jump_left, node.jump_right) = (left, right);
+        }
+        if let TrieNode::Leaf(leaf) = &mut nodes[pl] {
+            leaf.to_internal = Some(res);
+        }
+        Some((res, pr))
+    }
+
+    // the links are shifted by the position of the subtree in the nodes of the trie
+    fn shifted(node: TrieNode, d: usize) -> TrieNode {
+        match node {
+            TrieNode::Internal(node) => TrieNode::Internal(InternalTrieNode {
+                left: node.left + d,
+                right: node.right + d,
+                lind: node.lind,
+                jump_left: node.jump_left + d,
+                jump_right: node.jump_right + d,
+                to_leaf: node.to_leaf + d,
+            }),
+            TrieNode::Leaf(leaf) => TrieNode::Leaf(LeafTrieNode {
+                prev: leaf.prev.map(|p| p + d),
+                next: leaf.next.map(|p| p + d),
+                to_internal: leaf.to_internal.map(|p| p + d),
+                ..leaf
+            }),
+        }
+    }
+
+    fn get_handle(&self, p: usize) -> Option<Str> {
+        match &self.nodes[p] {
+            TrieNode::Internal(node) => {
+                let h = get_fattest(self.rind(p), node.lind);
+                Some(self.with_extent(p, |s, _| get_substr(s, 0, h)))
             }
-            *last_leaf_ref = Some(leafp.clone());
+            TrieNode::Leaf(_) => None,
+        }
+    }
 
-            (Some(coerce!(leaf => dyn TrieNode)), Some(leafp))
-        } else {
-            let mut mid = l;
-            while mid < r && !v[mid][ind] {
-                mid += 1;
+    // checks the subtree whose skip interval should start at lind and returns the range
+    // of its leaves; the leaves and the handles of the internal nodes are collected in order
+    fn validate_node(
+        &self,
+        p: usize,
+        lind: usize,
+        leaves: &mut Vec<usize>,
+        handles: &mut Vec<(Str, usize)>
+    ) -> Result<(usize, usize), InvariantViolation> {
+        let (node_lind, rind) = (self.lind(p), self.rind(p));
+        if node_lind != lind || lind > rind {
+            return Err(InvariantViolation::SkipInterval { lind: node_lind, rind });
+        }
+
+        match &self.nodes[p] {
+            TrieNode::Internal(node) => {
+                let (lo, mid) = self.validate_node(node.left, rind + 1, leaves, handles)?;
+                let (_, hi) = self.validate_node(node.right, rind + 1, leaves, handles)?;
+                if hi > self.keys.len() {
+                    return Err(InvariantViolation::KeyCount { leaves: hi, keys: self.keys.len() });
+                }
+
+                // the keys below share the extent and the children split them on the bit at rind
+                let (a, b) = (&self.keys[mid - 1], &self.keys[mid]);
+                if
+                    lcp_len(&self.keys[lo], &self.keys[hi - 1]) != rind ||
+                    lcp_len(a, b) != rind ||
+                    rind >= a.len() ||
+                    rind >= b.len() ||
+                    a[rind] ||
+                    !b[rind]
+                {
+                    return Err(InvariantViolation::Extent { lind, rind });
+                }
+
+                let to_leaf = leaves[mid - 1];
+                let linked = matches!(&self.nodes[to_leaf], TrieNode::Leaf(leaf) if leaf.to_internal == Some(p));
+                if node.to_leaf != to_leaf || !linked {
+                    return Err(InvariantViolation::BackLink { lind, rind });
+                }
+
+                let h = get_fattest(rind, lind);
+                InvariantViolation::check_handle(lind, rind, h)?;
+                handles.push((get_substr(&self.keys[lo], 0, h), p));
+
+                let t = if h == 0 { usize::MAX } else { h + (1 << h.trailing_zeros()) };
+                if node.jump_left != self.jump_target(node.left, t, false) {
+                    return Err(InvariantViolation::Jump { lind, rind, right: false });
+                }
+                if node.jump_right != self.jump_target(node.right, t, true) {
+                    return Err(InvariantViolation::Jump { lind, rind, right: true });
+                }
+                Ok((lo, hi))
             }
+            TrieNode::Leaf(leaf) => {
+                let i = leaves.len();
+                if leaf.key != i {
+                    return Err(InvariantViolation::LeafKey { leaf: i, key: Some(leaf.key) });
+                }
+                if i < self.keys.len() && self.keys[i].len() != rind {
+                    return Err(InvariantViolation::SkipInterval { lind, rind });
+                }
+                leaves.push(p);
+                Ok((i, i + 1))
+            }
+        }
+    }
 
-            if mid != l && mid != r {
-                let (l, pl) = ZFastTrieSux::<H>::build_tree(v, ind + 1, l, mid, last_leaf_ref);
-                let (r, pr) = ZFastTrieSux::<H>::build_tree(v, ind + 1, mid, r, last_leaf_ref);
-
-                let tmpl = l.expect("left child in ZFastTrieSux build");
-                let tmpr = r.expect("right child in ZFastTrieSux build");
-                let res = RefBox::new(InternalTrieNode {
-                    jump_left: tmpl.create_ref(),
-                    jump_right: tmpr.create_ref(),
-                    left: tmpl,
-                    right: tmpr,
-                    lind: ind,
-                    to_leaf: pl.clone().expect("to leaf pointer in ZFastTrieSux build"),
-                });
-                pl.expect("ok").try_borrow_mut().unwrap().to_internal = Some(res.create_ref());
+    // the first node on the leftmost (rightmost) path from p whose skip interval contains t,
+    // or the leaf at its end
+    fn jump_target(&self, mut p: usize, t: usize, right: bool) -> usize {
+        while let TrieNode::Internal(node) = &self.nodes[p] {
+            if node.lind <= t && t <= self.rind(p) {
+                break;
+            }
+            p = if right { node.right } else { node.left };
+        }
+        p
+    }
 
-                (Some(coerce!(res => dyn TrieNode)), pr)
-            } else {
-                let (x, y) = ZFastTrieSux::<H>::build_tree(v, ind + 1, l, r, last_leaf_ref);
-                assert!(x.is_some());
-                let res = x.unwrap();
-                res.try_borrow_mut().unwrap().set_lind(ind);
-                (Some(res), y)
+    // graphviz rendering of the trie: skip interval, extent and handle of every node,
+    // jump pointers dashed and the links between the leaves dotted
+    pub fn to_dot(&self, w: impl Write) -> io::Result<()> {
+        self.write_dot(w, None)
+    }
+
+    // the same, with the path from the root to the exit node found by locate_exit for x
+    pub fn to_dot_with_query(&self, w: impl Write, x: impl AsBitSlice) -> io::Result<()> {
+        let x = x.as_bit_slice();
+        self.write_dot(w, self.locate_exit(&self.query_context(&x), x.len()))
+    }
+
+    // the nodes are numbered by their index, which is their position in preorder
+    fn write_dot(&self, mut w: impl Write, exit: Option<usize>) -> io::Result<()> {
+        let mut parents = vec![None; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let TrieNode::Internal(node) = node {
+                parents[node.left] = Some(i);
+                parents[node.right] = Some(i);
+            }
+        }
+        let marks = dot_path_marks(&parents, exit);
+
+        dot_begin(&mut w)?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let (lind, rind) = (self.lind(i), self.rind(i));
+            let mut label = vec![format!("[{}, {}]", lind, rind)];
+            label.push(self.with_extent(i, |s, len| dot_extent(s, len)));
+            match node {
+                TrieNode::Leaf(leaf) => label.push(format!("key {}", leaf.key)),
+                TrieNode::Internal(_) => label.push(format!("handle {}", get_fattest(rind, lind))),
             }
+            dot_node(&mut w, i, &label, matches!(node, TrieNode::Leaf(_)), marks[i])?;
         }
+        for (i, node) in self.nodes.iter().enumerate() {
+            match node {
+                TrieNode::Internal(node) => {
+                    dot_child_edge(&mut w, i, node.left, false)?;
+                    dot_child_edge(&mut w, i, node.right, true)?;
+                    dot_jump_edge(&mut w, i, node.jump_left, false)?;
+                    dot_jump_edge(&mut w, i, node.jump_right, true)?;
+                }
+                TrieNode::Leaf(leaf) => {
+                    if let Some(l) = leaf.prev {
+                        dot_leaf_edge(&mut w, i, l, false)?;
+                    }
+                    if let Some(l) = leaf.next {
+                        dot_leaf_edge(&mut w, i, l, true)?;
+                    }
+                }
+            }
+        }
+        dot_end(&mut w)
+    }
+
+    fn node_stats(&self, p: usize, depth: usize, res: &mut TrieStats) {
+        res.add_node(self.lind(p), self.rind(p), depth, self.is_leaf(p));
+        if let TrieNode::Internal(node) = &self.nodes[p] {
+            self.node_stats(node.left, depth + 1, res);
+            self.node_stats(node.right, depth + 1, res);
+        }
+    }
+
+    pub fn query_context<'a, X: AsBitSlice + ?Sized>(
+        &self,
+        x: &'a X
+    ) -> QueryContext<'a, [H::State; 3]> {
+        let x = x.as_bit_slice();
+        QueryContext::new(x, self.z_map.compute_state(x))
+    }
+
+    pub fn pred_query_with(&self, cx: &QueryContext<[H::State; 3]>) -> Option<Key<'_>> {
+        self.query(cx).0
+    }
+
+    pub fn succ_query_with(&self, cx: &QueryContext<[H::State; 3]>) -> Option<Key<'_>> {
+        self.query(cx).1
+    }
+
+    pub fn ex_pref_query_with(&self, cx: &QueryContext<[H::State; 3]>) -> bool {
+        let x = cx.key();
+        if let Some(exit_node) = self.locate_exit(cx, x.len()) {
+            self.is_extent_prefixed_by(exit_node, x)
+        } else {
+            false
+        }
+    }
+
+    pub fn ex_range_query_with(
+        &self,
+        cx: &QueryContext<[H::State; 3]>,
+        cy: &QueryContext<[H::State; 3]>
+    ) -> bool {
+        self.is_nonempty(cx, cy)
+    }
+
+    pub fn rank_query_with(&self, cx: &QueryContext<[H::State; 3]>) -> usize {
+        let x = cx.key();
+        if let Some(eta) = self.locate_exit(cx, x.len()) { self.rank_from_exit(x, eta) } else { 0 }
     }
 
-    fn locate_exit_or_parex_prob(&self, x: &Str) -> Option<Ref<dyn TrieNode>> {
-        let mut res = None;
-        let (mut a, mut b) = (0, x.len());
+    // all the functions below work on the prefix of length `len` of the key of `cx`,
+    // so that prefixes of an already hashed key reuse its state
+    fn locate_exit_or_parex_prob(
+        &self,
+        cx: &QueryContext<[H::State; 3]>,
+        len: usize,
+        start: Option<usize>
+    ) -> Option<usize> {
+        let x = cx.key();
+        let a = start.map_or(0, |p| self.rind(p) + 1);
+        let mut res = start;
+        let (mut a, mut b) = (a, len);
         let mut m = {
             if a == 0 { calc(b) } else { calc((a - 1) ^ b) }
         };
-        let state = self.z_map.compute_state(x);
 
         while a <= b {
             if a == 0 || (m & (a - 1)) != (m & b) {
@@ -168,14 +505,19 @@ impl<H: Hash<DomainType = Str> + ParametricHash> ZFastTrieSux<H> {
                     if a == 0 { 0 } else { m & b }
                 };
 
-                let beta = self.z_map.fast_prefix_get(x, &state, f);
-                if let Some(node_ref) = beta {
-                    let rind = node_ref.try_borrow_mut().unwrap().get_rind();
-                    let lind = node_ref.try_borrow_mut().unwrap().get_lind();
+                let beta = self.z_map.fast_prefix_get(x, cx.state(), f);
+                instrumentation::record(|s| {
+                    s.z_map_probes += 1;
+                    s.z_map_hits += beta.is_some() as usize;
+                });
+                if let Some(&node_ref) = beta {
+                    let rind = self.rind(node_ref);
+                    let lind = self.lind(node_ref);
                     if get_fattest(rind, lind) == f {
                         a = rind + 1;
-                        res = Some(node_ref.clone());
+                        res = Some(node_ref);
                     } else {
+                        instrumentation::record(|s| s.false_hits += 1);
                         if f == 0 {
                             break;
                         }
@@ -194,13 +536,19 @@ impl<H: Hash<DomainType = Str> + ParametricHash> ZFastTrieSux<H> {
         res
     }
 
-    fn locate_parex(&self, x: &Str) -> Option<Ref<dyn TrieNode>> {
-        let mut res = None;
-        let (mut a, mut b) = (0, x.len());
+    fn locate_parex(
+        &self,
+        cx: &QueryContext<[H::State; 3]>,
+        len: usize,
+        start: Option<usize>
+    ) -> Option<usize> {
+        let x = cx.key();
+        let a = start.map_or(0, |p| self.rind(p) + 1);
+        let mut res = start;
+        let (mut a, mut b) = (a, len);
         let mut m = {
             if a == 0 { calc(b) } else { calc((a - 1) ^ b) }
         };
-        let state = self.z_map.compute_state(x);
 
         while a <= b {
             if a == 0 || (m & (a - 1)) != (m & b) {
@@ -208,18 +556,18 @@ impl<H: Hash<DomainType = Str> + ParametricHash> ZFastTrieSux<H> {
                     if a == 0 { 0 } else { m & b }
                 };
 
-                let beta = self.z_map.fast_prefix_get(x, &state, f);
-                if let Some(node_ref) = beta {
-                    let extent = node_ref.try_borrow_mut().unwrap().get_extent();
-                    let rind = node_ref.try_borrow_mut().unwrap().get_rind();
-                    let lind = node_ref.try_borrow_mut().unwrap().get_lind();
+                let beta = self.z_map.fast_prefix_get(x, cx.state(), f);
+                instrumentation::record(|s| s.parex_probes += 1);
+                if let Some(&node_ref) = beta {
+                    let rind = self.rind(node_ref);
+                    let lind = self.lind(node_ref);
                     if
-                        extent.len() < x.len() &&
+                        rind < len &&
                         get_fattest(rind, lind) == f &&
-                        extent == get_substr(x,0,extent.len())
+                        self.is_extent_prefix_of(node_ref, x)
                     {
                         a = rind + 1;
-                        res = Some(node_ref.clone());
+                        res = Some(node_ref);
                     } else {
                         if f == 0 {
                             break;
@@ -239,388 +587,423 @@ impl<H: Hash<DomainType = Str> + ParametricHash> ZFastTrieSux<H> {
         res
     }
 
-    fn locate_exit_or_parex(&self, x: &Str) -> Option<Ref<dyn TrieNode>> {
-        let mut res = self.locate_exit_or_parex_prob(x);
-        let handle = {
-            if res.is_some() {
-                res.as_ref().unwrap().try_borrow_mut().unwrap().get_handle().unwrap()
+    // `start`, when given, is a node whose extent is a prefix of the key shorter than len:
+    // the search goes on below it
+    fn locate_exit_or_parex(
+        &self,
+        cx: &QueryContext<[H::State; 3]>,
+        len: usize,
+        start: Option<usize>
+    ) -> Option<usize> {
+        let x = cx.key();
+        let mut res = self.locate_exit_or_parex_prob(cx, len, start);
+        let valid = {
+            if let Some(node_ref) = res {
+                self.is_handle_prefix_of(node_ref, x, len)
             } else {
-                Str::new(0)
+                true
             }
         };
 
-        if handle != get_substr(x,0,min(x.len(), handle.len())) {
-            res = self.locate_parex(x);
+        if !valid {
+            instrumentation::record(|s| s.parex_fallbacks += 1);
+            res = self.locate_parex(cx, len, start);
         }
         return res;
     }
 
-    fn locate_exit_from_node(
-        &self,
-        x: &Str,
-        sigma: Option<Ref<dyn TrieNode>>
-    ) -> Option<Ref<dyn TrieNode>> {
-        if let Some(node_ref) = &sigma {
-            let node = node_ref.try_borrow_mut().unwrap();
-            let extent = node.get_extent();
-
-            if extent.len() < x.len() && extent == get_substr(x,0,min(extent.len(), x.len())) {
-                if !x[extent.len()] {
-                    assert!(node.get_left().is_some());
-                    Some(node.get_left().unwrap().clone())
-                } else {
-                    assert!(node.get_right().is_some());
-                    Some(node.get_right().unwrap().clone())
+    fn locate_exit_from_node(&self, x: BitSlice, len: usize, sigma: Option<usize>) -> usize {
+        if let Some(node_ref) = sigma {
+            let rind = self.rind(node_ref);
+
+            match &self.nodes[node_ref] {
+                TrieNode::Internal(node) if rind < len && self.is_extent_prefix_of(node_ref, x) => {
+                    if !x[rind] { node.left } else { node.right }
                 }
-            } else {
-                sigma.clone()
+                _ => node_ref,
             }
         } else {
-            assert!(self.root.is_some());
-            let r = self.root.as_ref().unwrap();
-            Some(r.create_ref())
+            0
         }
     }
 
-    fn locate_exit(&self, x: &Str) -> Option<Ref<dyn TrieNode>> {
-        if !self.root.is_some() {
-            None
-        } else {
-            let res = self.locate_exit_or_parex(x);
-            let eta = {
-                if res.is_some() {
-                    self.locate_exit_from_node(x, res)
-                } else {
-                    Some(self.root.as_ref().unwrap().create_ref())
-                }
-            };
-            eta.clone()
-        }
+    fn locate_exit(&self, cx: &QueryContext<[H::State; 3]>, len: usize) -> Option<usize> {
+        self.locate_exit_below(cx, len, None).1
     }
 
-    fn query(&self, x: &Str) -> (Option<Str>, Option<Str>) {
-        if let Some(eta) = self.locate_exit(x) {
-            let (mut prev, mut succ) = (None, None);
-
-            let leaf;
-            if cmp(x,&eta.try_borrow_mut().unwrap().get_extent()) != Greater {
-                leaf = eta.try_borrow_mut().unwrap().get_leftmost(eta.clone());
-                succ = Some(leaf.try_borrow_mut().unwrap().get_extent());
-
-                if let Some(leaf2) = &leaf.try_borrow_mut().unwrap().get_prev() {
-                    prev = Some(leaf2.try_borrow_mut().unwrap().get_extent());
-                }
-            } else {
-                leaf = eta.try_borrow_mut().unwrap().get_rightmost(eta.clone());
-                prev = Some(leaf.try_borrow_mut().unwrap().get_extent());
-
-                if let Some(leaf2) = &leaf.try_borrow_mut().unwrap().get_next() {
-                    succ = Some(leaf2.try_borrow_mut().unwrap().get_extent());
-                }
-            }
-
-            (prev, succ)
-        } else {
+    // the parent of the exit node, when the search went through it, and the exit node:
+    // the next key of a sorted batch can start from the parent
+    fn locate_exit_below(
+        &self,
+        cx: &QueryContext<[H::State; 3]>,
+        len: usize,
+        start: Option<usize>
+    ) -> (Option<usize>, Option<usize>) {
+        instrumentation::record(|s| s.exit_searches += 1);
+        if self.nodes.is_empty() {
             (None, None)
-        }
-    }
-
-    fn pref_query(&self, x: &Str) -> Option<Str> {
-        if let Some(exit_node) = self.locate_exit(x) {
-            Some(exit_node.try_borrow_mut().unwrap().get_prefix_extent(x.len()))
         } else {
-            None
+            let res = self.locate_exit_or_parex(cx, len, start);
+            let eta = self.locate_exit_from_node(cx.key(), len, res);
+            let rind = self.rind(eta);
+            (res.filter(|&p| self.rind(p) < rind), Some(eta))
         }
     }
 
-    fn is_nonempty(&self, x: &Str, y: &Str) -> bool {
-        if x == y {
-            return false;
-        } else if let Some(mut alpha) = self.locate_exit(x) {
-            if cmp(x,&alpha.try_borrow_mut().unwrap().get_extent()) != Greater {
-                while !alpha.try_borrow_mut().unwrap().is_leaf() {
-                    if alpha.try_borrow_mut().unwrap().get_extent().len() < y.len() {
-                        let tmp = alpha
-                            .try_borrow_mut()
-                            .unwrap()
-                            .get_jump_left()
-                            .clone()
-                            .unwrap()
-                            .clone();
-                        alpha = tmp;
-                    } else {
-                        break;
-                    }
-                }
-                return cmp(&alpha.try_borrow_mut().unwrap().get_extent(),y) == Less;
-            }
-
-            let mut beta = self.locate_exit(y).unwrap();
-            if cmp(x,&beta.try_borrow_mut().unwrap().get_extent()) == Greater {
-                while !beta.try_borrow_mut().unwrap().is_leaf() {
-                    if beta.try_borrow_mut().unwrap().get_extent().len() < x.len() {
-                        let tmp = beta
-                            .try_borrow_mut()
-                            .unwrap()
-                            .get_jump_right()
-                            .clone()
-                            .unwrap()
-                            .clone();
-                        beta = tmp;
-                    } else {
-                        break;
-                    }
-                }
-                return cmp(x,&beta.try_borrow_mut().unwrap().get_extent()) != Greater;
-            }
-
-            let z = lcp(x, y);
-            let eta = self.locate_exit(&z).unwrap();
-
-            alpha = eta.try_borrow_mut().unwrap().get_left().unwrap().clone();
-            while !alpha.try_borrow_mut().unwrap().is_leaf() {
-                if alpha.try_borrow_mut().unwrap().get_extent().len() < x.len() {
-                    let tmp = alpha
-                        .try_borrow_mut()
-                        .unwrap()
-                        .get_jump_right()
-                        .clone()
-                        .unwrap()
-                        .clone();
-                    alpha = tmp;
-                } else {
-                    break;
-                }
-            }
-            if cmp(x,&alpha.try_borrow_mut().unwrap().get_extent()) != Greater {
-                return true;
-            }
-
-            beta = eta.try_borrow_mut().unwrap().get_right().unwrap().clone();
-            while !beta.try_borrow_mut().unwrap().is_leaf() {
-                if beta.try_borrow_mut().unwrap().get_extent().len() < y.len() {
-                    let tmp = beta
-                        .try_borrow_mut()
-                        .unwrap()
-                        .get_jump_left()
-                        .clone()
-                        .unwrap()
-                        .clone();
-                    beta = tmp;
-                } else {
-                    break;
-                }
+    // the context of x, hashed from the one of the previous key of a batch
+    fn next_context<'a>(
+        &self,
+        prev: Option<QueryContext<'a, [H::State; 3]>>,
+        x: BitSlice<'a>
+    ) -> QueryContext<'a, [H::State; 3]> {
+        match prev {
+            Some(cy) => {
+                let lcp = lcp_len(cy.key(), x);
+                QueryContext::new(x, self.z_map.update_state(x, cy.into_state(), lcp))
             }
-
-            let candidate = beta.try_borrow_mut().unwrap().get_extent();
-            cmp(&candidate,y) == Less
-        } else {
-            false
+            None => QueryContext::new(x, self.z_map.compute_state(x)),
         }
     }
-}
-
-impl TrieNode for InternalTrieNode {
-    fn is_leaf(&self) -> bool {
-        false
-    }
-
-    fn get_prev(&self) -> &Option<Ref<LeafTrieNode>> {
-        &None
-    }
 
-    fn get_next(&self) -> &Option<Ref<LeafTrieNode>> {
-        &None
+    // answers the batch in sorted order: every key is hashed from the state of the previous
+    // one, and its exit node is searched below the parent of the previous exit node when
+    // their common prefix covers its extent
+    fn batch<T>(&self, xs: &[Str], mut f: impl FnMut(BitSlice, Option<usize>) -> T) -> Vec<T> {
+        let mut order: Vec<usize> = (0..xs.len()).collect();
+        order.sort_by(|&i, &j| cmp(&xs[i], &xs[j]));
+
+        let mut res: Vec<Option<T>> = xs.iter().map(|_| None).collect();
+        let (mut cx, mut parex, mut eta) = (None, None, None);
+        for i in order {
+            let x = xs[i].as_bit_slice();
+            let lcp = cx.as_ref().map_or(0, |cy: &QueryContext<_>| lcp_len(cy.key(), x));
+            // a repeated key has the same exit node
+            if cx.as_ref().is_none_or(|cy| lcp < x.len() || lcp < cy.key().len()) {
+                let start = parex.filter(|&p: &usize| self.rind(p) < lcp);
+                let next = self.next_context(cx.take(), x);
+                (parex, eta) = self.locate_exit_below(&next, x.len(), start);
+                cx = Some(next);
+            }
+            res[i] = Some(f(x, eta));
+        }
+        res.into_iter().map(Option::unwrap).collect()
     }
 
-    fn get_left(&self) -> Option<Ref<dyn TrieNode>> {
-        Some(self.left.create_ref())
+    fn get_key(&self, leaf: usize) -> Key<'_> {
+        Key::Borrowed(&self.keys[self.leaf(leaf).key])
     }
 
-    fn get_right(&self) -> Option<Ref<dyn TrieNode>> {
-        Some(self.right.create_ref())
-    }
+    fn query_from_exit(&self, x: BitSlice, eta: usize) -> (Option<Key<'_>>, Option<Key<'_>>) {
+        let (mut prev, mut succ) = (None, None);
 
-    fn get_leftmost(&self, _noderef: Ref<dyn TrieNode>) -> Ref<dyn TrieNode> {
-        self.jump_left.try_borrow_mut().unwrap().get_leftmost(self.jump_left.clone())
-    }
+        if self.cmp_extent(eta, x) != Greater {
+            let leaf = self.get_leftmost(eta);
+            succ = Some(self.get_key(leaf));
 
-    fn get_rightmost(&self, _noderef: Ref<dyn TrieNode>) -> Ref<dyn TrieNode> {
-        self.jump_right.try_borrow_mut().unwrap().get_rightmost(self.jump_right.clone())
-    }
+            if let Some(leaf2) = self.leaf(leaf).prev {
+                prev = Some(self.get_key(leaf2));
+            }
+        } else {
+            let leaf = self.get_rightmost(eta);
+            prev = Some(self.get_key(leaf));
 
-    fn get_jump_left(&self) -> Option<Ref<dyn TrieNode>> {
-        Some(self.jump_left.clone())
-    }
+            if let Some(leaf2) = self.leaf(leaf).next {
+                succ = Some(self.get_key(leaf2));
+            }
+        }
 
-    fn get_jump_right(&self) -> Option<Ref<dyn TrieNode>> {
-        Some(self.jump_right.clone())
+        (prev, succ)
     }
 
-    fn set_lind(&mut self, x: usize) {
-        self.lind = x;
+    // the leaves are numbered as the keys: the ones smaller than x end right before the
+    // leftmost leaf below the exit node, or right after the rightmost one
+    fn rank_from_exit(&self, x: BitSlice, eta: usize) -> usize {
+        if self.cmp_extent(eta, x) != Greater {
+            self.leaf(self.get_leftmost(eta)).key
+        } else {
+            self.leaf(self.get_rightmost(eta)).key + 1
+        }
     }
 
-    fn get_lind(&self) -> usize {
-        self.lind
+    fn query(&self, cx: &QueryContext<[H::State; 3]>) -> (Option<Key<'_>>, Option<Key<'_>>) {
+        let x = cx.key();
+        if let Some(eta) = self.locate_exit(cx, x.len()) {
+            self.query_from_exit(x, eta)
+        } else {
+            (None, None)
+        }
     }
 
-    fn get_rind(&self) -> usize {
-        self.left.try_borrow_mut().unwrap().get_lind() - 1
+    // follows the left (right) jumps from p while the skip interval ends before len
+    fn jump_while_below(&self, mut p: usize, len: usize, right: bool) -> usize {
+        while let TrieNode::Internal(node) = &self.nodes[p] {
+            if self.rind(p) >= len {
+                break;
+            }
+            instrumentation::record(|s| s.jump_hops += 1);
+            p = if right { node.jump_right } else { node.jump_left };
+        }
+        p
     }
 
-    fn get_extent(&self) -> Str {
-        let rind = self.get_rind();
+    fn is_nonempty(
+        &self,
+        cx: &QueryContext<[H::State; 3]>,
+        cy: &QueryContext<[H::State; 3]>
+    ) -> bool {
+        let (x, y) = (cx.key(), cy.key());
+        if x == y {
+            return false;
+        } else if let Some(alpha) = self.locate_exit(cx, x.len()) {
+            if self.cmp_extent(alpha, x) != Greater {
+                let alpha = self.jump_while_below(alpha, y.len(), false);
+                return self.cmp_extent(alpha, y) == Greater;
+            }
 
-        let p = &self.to_leaf;
-        get_substr(&p.try_borrow_mut().unwrap().extent,0,rind)
-    }
+            let beta = self.locate_exit(cy, y.len()).unwrap();
+            if self.cmp_extent(beta, x) == Greater {
+                let beta = self.jump_while_below(beta, x.len(), true);
+                return self.cmp_extent(beta, x) != Greater;
+            }
 
-    fn get_prefix_extent(&self, x: usize) -> Str {
-        let rind = min(self.get_rind(), x);
+            // lcp(x, y) is a prefix of x: its exit node is found with the state of x
+            let z = lcp_len(x, y);
+            let eta = self.locate_exit(cx, z).unwrap();
+            let TrieNode::Internal(eta) = &self.nodes[eta] else { unreachable!() };
 
-        let p = &self.to_leaf;
-        p.try_borrow_mut().unwrap().get_prefix_extent(rind)
-    }
+            let alpha = self.jump_while_below(eta.left, x.len(), true);
+            if self.cmp_extent(alpha, x) != Greater {
+                return true;
+            }
 
-    fn get_kth_left(&self, noderef: Ref<dyn TrieNode>, k: usize) -> Ref<dyn TrieNode> {
-        let rind = self.get_rind();
-        if k >= self.lind && k <= rind {
-            noderef
+            let beta = self.jump_while_below(eta.right, y.len(), false);
+            self.cmp_extent(beta, y) == Greater
         } else {
-            let x = &self.left;
-            x.try_borrow_mut().unwrap().get_kth_left(x.create_ref(), k)
+            false
         }
     }
 
-    fn get_kth_right(&self, noderef: Ref<dyn TrieNode>, k: usize) -> Ref<dyn TrieNode> {
-        let rind = self.get_rind();
-        if k >= self.lind && k <= rind {
-            noderef
-        } else {
-            let x = &self.right;
-            x.try_borrow_mut().unwrap().get_kth_right(x.create_ref(), k)
+    fn leaf(&self, p: usize) -> &LeafTrieNode {
+        match &self.nodes[p] {
+            TrieNode::Leaf(leaf) => leaf,
+            TrieNode::Internal(_) => panic!("not a leaf"),
         }
     }
 
-    fn precalc_jumps(&mut self, noderef: Ref<dyn TrieNode>) {
-        let k = get_fattest(self.get_rind(), self.lind);
-
-        self.jump_left = self.get_kth_left(noderef.clone(), if k == 0 {
-            usize::MAX
-        } else {
-            k + (1 << k.trailing_zeros())
-        });
-
-        self.left.try_borrow_mut().unwrap().precalc_jumps(self.left.create_ref());
-
-        self.jump_right = self.get_kth_right(noderef, if k == 0 {
-            usize::MAX
-        } else {
-            k + (1 << k.trailing_zeros())
-        });
-        self.right.try_borrow_mut().unwrap().precalc_jumps(self.right.create_ref());
+    fn is_leaf(&self, p: usize) -> bool {
+        matches!(self.nodes[p], TrieNode::Leaf(_))
     }
 
-    fn get_handle(&self) -> Option<Str> {
-        let rind = self.get_rind();
-
-        let p = &self.to_leaf;
-        Some(get_substr(&p.try_borrow_mut().unwrap().extent,0,get_fattest(rind, self.lind)))
+    fn lind(&self, p: usize) -> usize {
+        match &self.nodes[p] {
+            TrieNode::Internal(node) => node.lind,
+            TrieNode::Leaf(leaf) => leaf.lind,
+        }
     }
 
-    fn precalc_z_map(
-        &self,
-        keys: &mut Vec<Str>,
-        values: &mut Vec<Ref<dyn TrieNode>>,
-        r: Ref<dyn TrieNode>
-    ) {
-        let s = self.get_handle();
-        assert!(s.is_some());
-        keys.push(s.unwrap());
-        values.push(r);
-
-        self.left.try_borrow_mut().unwrap().precalc_z_map(keys, values, self.left.create_ref());
-        self.right.try_borrow_mut().unwrap().precalc_z_map(keys, values, self.right.create_ref());
+    fn rind(&self, p: usize) -> usize {
+        match &self.nodes[p] {
+            TrieNode::Internal(node) => self.lind(node.left) - 1,
+            TrieNode::Leaf(leaf) => leaf.rind,
+        }
     }
-}
 
-impl TrieNode for LeafTrieNode {
-    fn is_leaf(&self) -> bool {
-        true
+    fn get_leftmost(&self, mut p: usize) -> usize {
+        while let TrieNode::Internal(node) = &self.nodes[p] {
+            p = node.jump_left;
+        }
+        p
     }
 
-    fn get_prev(&self) -> &Option<Ref<LeafTrieNode>> {
-        &self.prev
+    fn get_rightmost(&self, mut p: usize) -> usize {
+        while let TrieNode::Internal(node) = &self.nodes[p] {
+            p = node.jump_right;
+        }
+        p
     }
 
-    fn get_next(&self) -> &Option<Ref<LeafTrieNode>> {
-        &self.next
+    // calls f with the string of the leaf below the node and the length of the extent,
+    // which is a prefix of it: no copy of the extent is made
+    fn with_extent<R, F: FnOnce(&Str, usize) -> R>(&self, p: usize, f: F) -> R {
+        let leaf = match &self.nodes[p] {
+            TrieNode::Internal(node) => self.leaf(node.to_leaf),
+            TrieNode::Leaf(leaf) => leaf,
+        };
+        f(&self.keys[leaf.key], self.rind(p))
     }
 
-    fn get_left(&self) -> Option<Ref<dyn TrieNode>> {
-        None
+    // compares x with the extent
+    fn cmp_extent(&self, p: usize, x: BitSlice) -> Ordering {
+        self.with_extent(p, |s, rind| cmp_prefix(x, s, rind))
     }
 
-    fn get_right(&self) -> Option<Ref<dyn TrieNode>> {
-        None
+    fn is_extent_prefix_of(&self, p: usize, x: BitSlice) -> bool {
+        self.with_extent(p, |s, rind| rind <= x.len() && lcp_len_range(x, 0, s, 0, rind) == rind)
     }
 
-    fn get_leftmost(&self, noderef: Ref<dyn TrieNode>) -> Ref<dyn TrieNode> {
-        noderef
+    fn is_extent_prefixed_by(&self, p: usize, x: BitSlice) -> bool {
+        self.with_extent(p, |s, rind| {
+            x.len() <= rind && lcp_len_range(x, 0, s, 0, x.len()) == x.len()
+        })
     }
 
-    fn get_rightmost(&self, noderef: Ref<dyn TrieNode>) -> Ref<dyn TrieNode> {
-        noderef
+    fn is_handle_prefix_of(&self, p: usize, x: BitSlice, len: usize) -> bool {
+        let h = get_fattest(self.rind(p), self.lind(p));
+        h <= len && self.with_extent(p, |s, _| lcp_len_range(x, 0, s, 0, h) == h)
     }
+}
 
-    fn get_jump_left(&self) -> Option<Ref<dyn TrieNode>> {
-        None
+impl<H: Hash<DomainType = Str> + ParametricHash + Sync> ZFastTrieSux<H> {
+    // the same structure as build, with the keys sorted, the subtrees built, the handles
+    // found and the z-map hashed on `threads` threads, 0 for one per core
+    pub fn par_build(&mut self, v: &[Str], threads: usize) {
+        let ids = |ds: &Self| (0..ds.nodes.len()).collect::<Vec<usize>>();
+        self.build_with(
+            v,
+            threads,
+            |ds| par_map(&ids(ds), threads, |_| 1, |&p| ds.get_handle(p)),
+            |ds| par_map(&ids(ds), threads, |_| 1, |&p| ds.node_jumps(p)),
+            |z_map, handles, values| z_map.par_build(&handles, &values, threads),
+        );
     }
+}
 
-    fn get_jump_right(&self) -> Option<Ref<dyn TrieNode>> {
-        None
-    }
+impl<H: Hash<DomainType = Str> + ParametricHash> ZFastTrieSux<H> {
+    // the subtrees below the top of the trie are built concurrently, each one in its own
+    // nodes, then shifted to its position; the top nodes and the links between the
+    // subtrees are filled afterwards
+    fn par_build_tree(v: &[Str], threads: usize) -> Vec<TrieNode> {
+        let top = trie_top(v, threads);
+        let weight = |t: &TrieTop| if let TrieTop::Subtree { l, r, .. } = *t { r - l } else { 0 };
+        let mut subtrees = par_map(&top, threads, weight, |t| match *t {
+            TrieTop::Subtree { pos, lind, l, r } => {
+                let mut nodes = vec![];
+                ZFastTrieSux::<H>::build_tree(&mut nodes, v, lind, l, r, &mut None);
+                nodes.into_iter().map(|node| ZFastTrieSux::<H>::shifted(node, pos)).collect()
+            }
+            TrieTop::Internal { .. } => vec![],
+        }).into_iter();
+
+        let mut nodes = Vec::with_capacity((2 * v.len()).saturating_sub(1));
+        for t in &top {
+            let subtree = subtrees.next().unwrap();
+            if let TrieTop::Internal { pos, lind, l, mid, .. } = *t {
+                // the left subtree follows its parent, and its rightmost leaf is its last node
+                let (left, right) = (pos + 1, pos + 2 * (mid - l));
+                nodes.push(TrieNode::Internal(InternalTrieNode {
+                    left,
+                    right,
+                    lind,
+                    jump_left: left,
+                    jump_right: right,
+                    to_leaf: right - 1,
+                }));
+            } else {
+                nodes.extend(subtree);
+            }
+        }
 
-    fn set_lind(&mut self, x: usize) {
-        self.lind = x;
+        let mut last_leaf = None;
+        for p in 0..nodes.len() {
+            match &nodes[p] {
+                TrieNode::Internal(node) => {
+                    let q = node.to_leaf;
+                    if let TrieNode::Leaf(leaf) = &mut nodes[q] {
+                        leaf.to_internal = Some(p);
+                    }
+                }
+                TrieNode::Leaf(_) => {
+                    if let Some(q) = last_leaf {
+                        if let TrieNode::Leaf(leaf) = &mut nodes[q] {
+                            leaf.next = Some(p);
+                        }
+                    }
+                    if let TrieNode::Leaf(leaf) = &mut nodes[p] {
+                        leaf.prev = last_leaf;
+                    }
+                    last_leaf = Some(p);
+                }
+            }
+        }
+        nodes
     }
 
-    fn get_lind(&self) -> usize {
-        self.lind
+    fn node_jumps(&self, p: usize) -> Option<(usize, usize)> {
+        match &self.nodes[p] {
+            TrieNode::Internal(node) => {
+                let k = get_fattest(self.rind(p), node.lind);
+                let t = if k == 0 { usize::MAX } else { k + (1 << k.trailing_zeros()) };
+                Some((self.jump_target(node.left, t, false), self.jump_target(node.right, t, true)))
+            }
+            TrieNode::Leaf(_) => None,
+        }
     }
 
-    fn get_rind(&self) -> usize {
-        self.extent.len()
+    fn fill_jumps(&mut self, jumps: Vec<Option<(usize, usize)>>) {
+        for (node, jumps) in self.nodes.iter_mut().zip(jumps) {
+            if let (TrieNode::Internal(node), Some(jumps)) = (node, jumps) {
+                (node.jump_left, node.jump_right) = jumps;
+            }
+        }
     }
+}
 
-    fn get_extent(&self) -> Str {
-        self.extent.clone()
-    }
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+    use crate::bits;
+    use crate::hashes::*;
+    use InvariantViolation::*;
 
-    fn get_prefix_extent(&self, x: usize) -> Str {
-        let rind = min(self.get_rind(), x);
-        get_substr(&self.extent,0,rind)
-    }
+    type Ds = ZFastTrieSux<RollingHash>;
 
-    fn get_kth_left(&self, noderef: Ref<dyn TrieNode>, _k: usize) -> Ref<dyn TrieNode> {
-        noderef
+    fn internal(ds: &mut Ds, p: usize) -> &mut InternalTrieNode {
+        match &mut ds.nodes[p] {
+            TrieNode::Internal(node) => node,
+            TrieNode::Leaf(_) => panic!("not an internal node"),
+        }
     }
 
-    fn get_kth_right(&self, noderef: Ref<dyn TrieNode>, _k: usize) -> Ref<dyn TrieNode> {
-        noderef
+    fn leaf(ds: &mut Ds, p: usize) -> &mut LeafTrieNode {
+        match &mut ds.nodes[p] {
+            TrieNode::Leaf(leaf) => leaf,
+            TrieNode::Internal(_) => panic!("not a leaf"),
+        }
     }
 
-    fn precalc_jumps(&mut self, _noderef: Ref<dyn TrieNode>) {}
-
-    fn get_handle(&self) -> Option<Str> {
-        None
+    // nodes in preorder: [0, 1], [2, 2], 000, [3, 3], 0010, 0011, 01
+    fn corrupted(f: impl FnOnce(&mut Ds)) -> InvariantViolation {
+        let v = vec![bits!("000"), bits!("0010"), bits!("0011"), bits!("01")];
+        let mut ds = Ds::with_seed(0);
+        ds.build(&v);
+        assert!(ds.validate().is_ok());
+        f(&mut ds);
+        ds.validate().unwrap_err()
+    }
+
+    #[test]
+    fn test_validate_rejects() {
+        assert!(corrupted(|ds| ds.keys.push(OrdStr(bits!("1")))) == KeyCount { leaves: 4, keys: 5 });
+        assert!(corrupted(|ds| ds.keys.swap(0, 1)) == UnsortedLeaves { leaf: 0 });
+        assert!(corrupted(|ds| leaf(ds, 2).key = 1) == LeafKey { leaf: 0, key: Some(1) });
+        assert!(corrupted(|ds| leaf(ds, 6).lind = 3) == SkipInterval { lind: 3, rind: 2 });
+        let extent = |ds: &mut Ds| (internal(ds, 1).lind, leaf(ds, 6).lind) = (1, 1);
+        assert!(corrupted(extent) == Extent { lind: 0, rind: 0 });
+        assert!(corrupted(|ds| internal(ds, 0).jump_left = 0) == Jump { lind: 0, rind: 1, right: false });
+        assert!(corrupted(|ds| internal(ds, 0).jump_right = 0) == Jump { lind: 0, rind: 1, right: true });
+        assert!(corrupted(|ds| leaf(ds, 2).next = None) == LeafLink { leaf: 0 });
+        assert!(corrupted(|ds| internal(ds, 1).to_leaf = 4) == BackLink { lind: 2, rind: 2 });
+        assert!(corrupted(|ds| leaf(ds, 2).to_internal = None) == BackLink { lind: 2, rind: 2 });
+        let z_map = |ds: &mut Ds| {
+            let handles: Vec<Str> = [0, 1, 3].iter().map(|&p| ds.get_handle(p).unwrap()).collect();
+            ds.z_map.build(&handles, &vec![0, 1, 1]);
+        };
+        assert!(corrupted(z_map) == ZMap { lind: 3, rind: 3 });
+        let extra = |ds: &mut Ds| {
+            let mut handles: Vec<Str> = [0, 1, 3].iter().map(|&p| ds.get_handle(p).unwrap()).collect();
+            handles.push(bits!("111"));
+            ds.z_map.build(&handles, &vec![0, 1, 3, 0]);
+        };
+        assert!(corrupted(extra) == ZMapSize { nodes: 3, entries: 4 });
     }
-
-    fn precalc_z_map(
-        &self,
-        _keys: &mut Vec<Str>,
-        _values: &mut Vec<Ref<dyn TrieNode>>,
-        _r: Ref<dyn TrieNode>
-    ) {}
 }
@@ -0,0 +1,134 @@
+use crate::utils::str::*;
+use std::fmt;
+use std::ops::Index;
+
+// borrowed view of `len` bits of a word buffer, starting at bit `offset`:
+// queries read keys through it, so a substring of a larger buffer is not copied
+#[derive(Clone, Copy)]
+pub struct BitSlice<'a> {
+    words: &'a [usize],
+    offset: usize,
+    len: usize,
+}
+
+pub trait AsBitSlice {
+    fn as_bit_slice(&self) -> BitSlice<'_>;
+}
+
+impl<'a> BitSlice<'a> {
+    pub fn new(words: &'a [usize], offset: usize, len: usize) -> BitSlice<'a> {
+        assert!(offset + len <= words.len() * WORD_SIZE, "BitSlice out of bounds");
+        BitSlice { words, offset, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, ind: usize) -> bool {
+        assert!(ind < self.len, "BitSlice index out of bounds");
+        let pos = self.offset + ind;
+        (self.words[pos / WORD_SIZE] >> (pos % WORD_SIZE)) & 1 == 1
+    }
+
+    // the 64 bits starting at ind, with zeros past the end of the slice
+    pub fn get_word(&self, ind: usize) -> usize {
+        if ind >= self.len {
+            return 0;
+        }
+        let pos = self.offset + ind;
+        let (q, r) = (pos / WORD_SIZE, pos % WORD_SIZE);
+        let mut res = self.words[q] >> r;
+        if r != 0 && q + 1 < self.words.len() {
+            res |= self.words[q + 1] << (WORD_SIZE - r);
+        }
+        if self.len - ind < WORD_SIZE {
+            res &= (1 << (self.len - ind)) - 1;
+        }
+        res
+    }
+
+    pub fn slice(&self, start: usize, end: usize) -> BitSlice<'a> {
+        assert!(start <= end && end <= self.len);
+        BitSlice { words: self.words, offset: self.offset + start, len: end - start }
+    }
+
+    pub fn to_str(&self) -> Str {
+        get_substr(self, 0, self.len)
+    }
+}
+
+impl Index<usize> for BitSlice<'_> {
+    type Output = bool;
+
+    fn index(&self, ind: usize) -> &bool {
+        if self.get(ind) { &true } else { &false }
+    }
+}
+
+impl PartialEq for BitSlice<'_> {
+    fn eq(&self, other: &BitSlice) -> bool {
+        self.len == other.len && lcp_len(self, other) == self.len
+    }
+}
+
+impl Eq for BitSlice<'_> {}
+
+// binary; a precision truncates it, as in {:.32}
+impl fmt::Display for BitSlice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shown = f.precision().map_or(self.len, |p| p.min(self.len));
+        for i in 0..shown {
+            f.write_str(if self.get(i) { "1" } else { "0" })?;
+        }
+        if shown < self.len {
+            write!(f, "... ({} bits)", self.len)?;
+        }
+        Ok(())
+    }
+}
+
+// 4 bits per digit, the first one the most significant; the last digit is padded with zeros
+impl fmt::LowerHex for BitSlice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.len.div_ceil(4);
+        let shown = f.precision().map_or(digits, |p| p.min(digits));
+        for i in 0..shown {
+            let nibble = ((self.get_word(4 * i) & 0xF) as u8).reverse_bits() >> 4;
+            write!(f, "{:x}", nibble)?;
+        }
+        if shown < digits {
+            write!(f, "... ({} bits)", self.len)?;
+        }
+        Ok(())
+    }
+}
+
+// printed as a literal that can be pasted back into a test
+impl fmt::Debug for BitSlice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bits!(\"{}\")", self)
+    }
+}
+
+impl AsBitSlice for Str {
+    fn as_bit_slice(&self) -> BitSlice<'_> {
+        BitSlice::new(self.as_ref(), 0, self.len())
+    }
+}
+
+impl AsBitSlice for BitSlice<'_> {
+    fn as_bit_slice(&self) -> BitSlice<'_> {
+        *self
+    }
+}
+
+impl<T: AsBitSlice + ?Sized> AsBitSlice for &T {
+    fn as_bit_slice(&self) -> BitSlice<'_> {
+        (**self).as_bit_slice()
+    }
+}
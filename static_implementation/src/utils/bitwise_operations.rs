@@ -13,3 +13,13 @@ pub fn get_fattest(x: usize, low: usize) -> usize {
 pub fn calc(i: usize) -> usize {
     !((i + 1).next_power_of_two() - 1)
 }
+
+// whether h is the 2-fattest number of [low, x], i.e. the one with the most trailing zeros
+pub fn is_fattest(h: usize, low: usize, x: usize) -> bool {
+    if low == 0 {
+        h == 0
+    } else {
+        let lowbit = h & h.wrapping_neg();
+        low <= h && h <= x && h - lowbit < low && h.checked_add(lowbit).is_none_or(|y| y > x)
+    }
+}
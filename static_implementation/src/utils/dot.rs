@@ -0,0 +1,77 @@
+use crate::utils::bit_slice::*;
+use std::io::{ self, Write };
+
+// bits of an extent shown in a node, the rest is elided
+pub const DOT_EXTENT_BITS: usize = 32;
+
+// nodes on the path of a query are filled, and the exit node is filled darker
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DotMark {
+    None,
+    Path,
+    Exit,
+}
+
+pub fn dot_begin(w: &mut impl Write) -> io::Result<()> {
+    writeln!(w, "digraph trie {{")?;
+    writeln!(w, "    node [shape=box, fontname=monospace];")
+}
+
+pub fn dot_end(w: &mut impl Write) -> io::Result<()> {
+    writeln!(w, "}}")
+}
+
+// the leaves are drawn with rounded corners, the label has one line per entry
+pub fn dot_node(w: &mut impl Write, id: usize, label: &[String], leaf: bool, mark: DotMark) -> io::Result<()> {
+    let mut style = vec![];
+    if leaf {
+        style.push("rounded");
+    }
+    let fill = match mark {
+        DotMark::None => "",
+        DotMark::Path => ", fillcolor=\"lightyellow\"",
+        DotMark::Exit => ", fillcolor=\"orange\"",
+    };
+    if mark != DotMark::None {
+        style.extend(["filled", "bold"]);
+    }
+    writeln!(w, "    n{} [label=\"{}\", style=\"{}\"{}];", id, label.join("\\n"), style.join(","), fill)
+}
+
+pub fn dot_edge(w: &mut impl Write, from: usize, to: usize, attrs: &str) -> io::Result<()> {
+    writeln!(w, "    n{} -> n{} [{}];", from, to, attrs)
+}
+
+pub fn dot_child_edge(w: &mut impl Write, from: usize, to: usize, bit: bool) -> io::Result<()> {
+    dot_edge(w, from, to, if bit { "label=\"1\"" } else { "label=\"0\"" })
+}
+
+// jumps and leaf links do not change the layout of the tree
+pub fn dot_jump_edge(w: &mut impl Write, from: usize, to: usize, right: bool) -> io::Result<()> {
+    let side = if right { "jr" } else { "jl" };
+    dot_edge(w, from, to, &format!("style=dashed, color=blue, constraint=false, label=\"{}\"", side))
+}
+
+pub fn dot_leaf_edge(w: &mut impl Write, from: usize, to: usize, next: bool) -> io::Result<()> {
+    let side = if next { "next" } else { "prev" };
+    dot_edge(w, from, to, &format!("style=dotted, color=gray40, constraint=false, label=\"{}\"", side))
+}
+
+pub fn dot_extent(x: impl AsBitSlice, len: usize) -> String {
+    let x = x.as_bit_slice().slice(0, len);
+    if len == 0 { "ε".to_string() } else { format!("{:.*}", DOT_EXTENT_BITS, x) }
+}
+
+// the marks of the nodes from the root to `exit`, given the parent of every node
+pub fn dot_path_marks(parents: &[Option<usize>], exit: Option<usize>) -> Vec<DotMark> {
+    let mut res = vec![DotMark::None; parents.len()];
+    if let Some(e) = exit {
+        res[e] = DotMark::Exit;
+        let mut p = parents[e];
+        while let Some(i) = p {
+            res[i] = DotMark::Path;
+            p = parents[i];
+        }
+    }
+    res
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+
+// exact counts of small values, the larger ones in the last bucket
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Histogram {
+    counts: Vec<usize>,
+    total: usize,
+    max: usize,
+}
+
+impl Histogram {
+    pub const BUCKETS: usize = 65;
+
+    pub fn new() -> Histogram {
+        Histogram { counts: vec![0; Histogram::BUCKETS], total: 0, max: 0 }
+    }
+
+    pub fn add(&mut self, x: usize) {
+        self.counts[x.min(Histogram::BUCKETS - 1)] += 1;
+        self.total += x;
+        self.max = self.max.max(x);
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // how many values were x, or at least x for the last bucket
+    pub fn count(&self, x: usize) -> usize {
+        self.counts[x.min(Histogram::BUCKETS - 1)]
+    }
+
+    // sum of the values
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.is_empty() { 0.0 } else { (self.total as f64) / (self.len() as f64) }
+    }
+
+    // the smallest value not exceeded by a fraction p of the values
+    pub fn percentile(&self, p: f64) -> usize {
+        let target = ((p * (self.len() as f64)).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (x, c) in self.counts.iter().enumerate() {
+            seen += c;
+            if seen >= target {
+                return if x == Histogram::BUCKETS - 1 { self.max } else { x };
+            }
+        }
+        self.max
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram::new()
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "mean {:.2}, p50 {}, p90 {}, p99 {}, max {}",
+            self.mean(), self.percentile(0.5), self.percentile(0.9), self.percentile(0.99), self.max
+        )
+    }
+}
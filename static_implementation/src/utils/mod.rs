@@ -1,8 +1,26 @@
+pub mod bit_slice;
+pub use bit_slice::*;
+
 pub mod bitwise_operations;
 pub use bitwise_operations::*;
 
-pub mod ptr;
-pub use ptr::*;
+pub mod dot;
+pub use dot::*;
+
+pub mod histogram;
+pub use histogram::*;
+
+pub mod ord_str;
+pub use ord_str::*;
+
+pub mod par_batch;
+pub use par_batch::*;
+
+pub mod par_build;
+pub use par_build::*;
 
 pub mod str;
 pub use self::str::*;
+
+pub mod str_conversions;
+pub use str_conversions::*;
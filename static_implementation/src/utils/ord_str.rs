@@ -0,0 +1,97 @@
+use crate::utils::bit_slice::*;
+use crate::utils::str::*;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Deref;
+
+// Str ordered as the tries order it (lexicographic, a prefix comes first),
+// so that it can be sorted with sort() and stored in a BTreeSet or BTreeMap
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct OrdStr(pub Str);
+
+impl OrdStr {
+    pub fn into_inner(self) -> Str {
+        self.0
+    }
+}
+
+impl Ord for OrdStr {
+    fn cmp(&self, other: &OrdStr) -> Ordering {
+        cmp(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for OrdStr {
+    fn partial_cmp(&self, other: &OrdStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Deref for OrdStr {
+    type Target = Str;
+
+    fn deref(&self) -> &Str {
+        &self.0
+    }
+}
+
+impl Borrow<Str> for OrdStr {
+    fn borrow(&self) -> &Str {
+        &self.0
+    }
+}
+
+impl AsRef<Str> for OrdStr {
+    fn as_ref(&self) -> &Str {
+        &self.0
+    }
+}
+
+impl From<Str> for OrdStr {
+    fn from(x: Str) -> OrdStr {
+        OrdStr(x)
+    }
+}
+
+impl From<&Str> for OrdStr {
+    fn from(x: &Str) -> OrdStr {
+        OrdStr(x.clone())
+    }
+}
+
+impl From<BitSlice<'_>> for OrdStr {
+    fn from(x: BitSlice) -> OrdStr {
+        OrdStr(x.to_str())
+    }
+}
+
+impl From<OrdStr> for Str {
+    fn from(x: OrdStr) -> Str {
+        x.0
+    }
+}
+
+impl AsBitSlice for OrdStr {
+    fn as_bit_slice(&self) -> BitSlice<'_> {
+        self.0.as_bit_slice()
+    }
+}
+
+impl PartialEq<Str> for OrdStr {
+    fn eq(&self, other: &Str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for OrdStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.as_bit_slice(), f)
+    }
+}
+
+impl fmt::Debug for OrdStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.as_bit_slice(), f)
+    }
+}
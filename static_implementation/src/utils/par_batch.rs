@@ -0,0 +1,32 @@
+use crate::utils::par_build::*;
+use std::thread;
+
+// answers the batch with f on `threads` scoped threads sharing ds, 0 for one per core:
+// every thread gets a contiguous chunk, so that f can still sort its own part, and the
+// answers are concatenated in the order of the batch
+pub fn par_query_batch<'a, T, Q, R, F>(ds: &'a T, batch: &[Q], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync + ?Sized,
+    Q: Sync,
+    R: Send,
+    F: Fn(&'a T, &[Q]) -> Vec<R> + Sync,
+{
+    let threads = thread_count(threads);
+    let chunk = batch.len().div_ceil(threads).max(1);
+    if threads == 1 || batch.len() <= chunk {
+        return f(ds, batch);
+    }
+
+    let f = &f;
+    thread::scope(|s| {
+        let workers: Vec<_> = batch
+            .chunks(chunk)
+            .map(|part| s.spawn(move || f(ds, part)))
+            .collect();
+        let mut res = Vec::with_capacity(batch.len());
+        for w in workers {
+            res.extend(w.join().unwrap());
+        }
+        res
+    })
+}
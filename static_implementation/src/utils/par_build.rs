@@ -0,0 +1,134 @@
+use crate::utils::str::*;
+use std::cmp::Ordering::*;
+use std::thread;
+
+// the number of threads to use when the user asks for `threads`, 0 meaning one per core
+pub fn thread_count(threads: usize) -> usize {
+    match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        t => t,
+    }
+}
+
+// f applied to every item on scoped threads, each one taking a contiguous run of items of
+// about the same total weight; the answers are in the order of the items
+pub fn par_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    weight: impl Fn(&T) -> usize,
+    f: impl Fn(&T) -> R + Sync
+) -> Vec<R> {
+    let threads = thread_count(threads);
+    if threads == 1 || items.len() <= 1 {
+        return items.iter().map(&f).collect();
+    }
+
+    let total = items.iter().map(&weight).sum::<usize>().max(1);
+    let (mut runs, mut start, mut acc) = (vec![], 0, 0);
+    for (i, x) in items.iter().enumerate() {
+        acc += weight(x);
+        if acc * threads >= total * (runs.len() + 1) || i + 1 == items.len() {
+            runs.push(&items[start..=i]);
+            start = i + 1;
+        }
+    }
+
+    let f = &f;
+    thread::scope(|s| {
+        let workers: Vec<_> = runs
+            .into_iter()
+            .map(|run| s.spawn(move || run.iter().map(f).collect::<Vec<R>>()))
+            .collect();
+        workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+    })
+}
+
+fn merge(x: Vec<Str>, y: Vec<Str>) -> Vec<Str> {
+    let mut res = Vec::with_capacity(x.len() + y.len());
+    let (mut x, mut y) = (x.into_iter().peekable(), y.into_iter().peekable());
+    while let (Some(a), Some(b)) = (x.peek(), y.peek()) {
+        if cmp(b, a) == Less {
+            res.push(y.next().unwrap());
+        } else {
+            res.push(x.next().unwrap());
+        }
+    }
+    res.extend(x);
+    res.extend(y);
+    res
+}
+
+// the keys sorted as by sort_by(cmp): every thread sorts a chunk, then the chunks are
+// merged in pairs, the merges of a round running concurrently
+pub fn par_sort(v: &[Str], threads: usize) -> Vec<Str> {
+    let threads = thread_count(threads);
+    if threads == 1 || v.len() <= 1 {
+        let mut res = v.to_vec();
+        res.sort_by(cmp);
+        return res;
+    }
+
+    let mut runs: Vec<Vec<Str>> = thread::scope(|s| {
+        let workers: Vec<_> = v
+            .chunks(v.len().div_ceil(threads))
+            .map(|c| s.spawn(move || {
+                let mut c = c.to_vec();
+                c.sort_by(cmp);
+                c
+            }))
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+    while runs.len() > 1 {
+        let mut pairs = vec![];
+        let mut it = runs.into_iter();
+        while let Some(x) = it.next() {
+            pairs.push((x, it.next().unwrap_or_default()));
+        }
+        runs = thread::scope(|s| {
+            let workers: Vec<_> = pairs.into_iter().map(|(x, y)| s.spawn(move || merge(x, y))).collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+    }
+    runs.pop().unwrap()
+}
+
+// the top of the compacted trie of the sorted keys v in preorder, down to subtrees small
+// enough to be built by a single thread; a subtree of m keys has 2m - 1 nodes, so the
+// position of every node is known before the subtrees are built
+pub enum TrieTop {
+    // the keys in [l, r) branch at rind, the ones in [mid, r) having the bit set
+    Internal { pos: usize, lind: usize, rind: usize, l: usize, mid: usize, r: usize },
+    Subtree { pos: usize, lind: usize, l: usize, r: usize },
+}
+
+// a single subtree for one thread, else subtrees of at most n / (4 * threads) keys,
+// so that the ones of an unbalanced trie can still be shared out evenly
+pub fn trie_top(v: &[Str], threads: usize) -> Vec<TrieTop> {
+    let threads = thread_count(threads);
+    let max = if threads == 1 { v.len() } else { v.len().div_ceil(4 * threads) };
+    let mut res = vec![];
+    if !v.is_empty() {
+        split_top(v, 0, 0, v.len(), max.max(1), &mut res);
+    }
+    res
+}
+
+fn split_top(v: &[Str], lind: usize, l: usize, r: usize, max: usize, res: &mut Vec<TrieTop>) {
+    let pos = res.last().map_or(0, |t| match *t {
+        TrieTop::Internal { pos, .. } => pos + 1,
+        TrieTop::Subtree { pos, l, r, .. } => pos + 2 * (r - l) - 1,
+    });
+    if r - l <= max {
+        res.push(TrieTop::Subtree { pos, lind, l, r });
+        return;
+    }
+
+    // v is sorted, so the common prefix of v[l..r] is the lcp of its first and last key
+    let rind = lcp_len(&v[l], &v[r - 1]);
+    assert!(rind < v[l].len(), "Build error: v is not prefix free");
+    let mid = l + v[l..r].partition_point(|x| !x[rind]);
+    res.push(TrieTop::Internal { pos, lind, rind, l, mid, r });
+    split_top(v, rind + 1, l, mid, max, res);
+    split_top(v, rind + 1, mid, r, max, res);
+}
@@ -1,67 +1,85 @@
 use std::cmp::*;
 use std::cmp::Ordering::*;
+use crate::utils::bit_slice::*;
 use sux::prelude::*;
  
 pub type Str = BitVec<Vec<usize>>;
 pub const WORD_SIZE: usize = 64;
 
 pub fn cmp(x: &Str, y: &Str) -> Ordering {
-    let mut currind: usize = 0;
-    let mut blockind: usize = 0;
-    let refx = x.as_ref();
-    let refy = y.as_ref();
-
-    while currind+WORD_SIZE <= min(x.len(),y.len()) {
-        if refx[blockind] != refy[blockind] {
-            let mut tmp = refx[blockind]^refy[blockind];
-            tmp = tmp & ((!tmp) + 1);
-            if (tmp & refx[blockind]) != 0 {
-                return Greater;
-            } else {
-                return Less;
-            }
+    cmp_prefix(x, y, y.len())
+}
+
+// compares x with the prefix of length len of y
+pub fn cmp_prefix(x: impl AsBitSlice, y: impl AsBitSlice, len: usize) -> Ordering {
+    let (x, y) = (x.as_bit_slice(), y.as_bit_slice());
+    assert!(len <= y.len());
+    let m = min(x.len(), len);
+    let l = lcp_len_range(x, 0, y, 0, m);
+
+    if l < m {
+        if x[l] {
+            Greater
+        } else {
+            Less
         }
-        currind+=WORD_SIZE;
-        blockind+=1;
+    } else {
+        x.len().cmp(&len)
     }
+}
 
-    while currind < min(x.len(), y.len()) {
-        if x[currind] < y[currind] {
-            return Less;
-        }
-        if x[currind] > y[currind] {
-            return Greater;
+pub fn lcp_len(x: impl AsBitSlice, y: impl AsBitSlice) -> usize {
+    let (x, y) = (x.as_bit_slice(), y.as_bit_slice());
+    lcp_len_range(x, 0, y, 0, min(x.len(), y.len()))
+}
+
+// lcp length of x[xstart..xstart+len] and y[ystart..ystart+len]
+pub fn lcp_len_range(
+    x: impl AsBitSlice,
+    xstart: usize,
+    y: impl AsBitSlice,
+    ystart: usize,
+    len: usize
+) -> usize {
+    let (x, y) = (x.as_bit_slice(), y.as_bit_slice());
+    assert!(xstart + len <= x.len() && ystart + len <= y.len());
+    let mut currind = 0;
+
+    while currind < len {
+        // i bit sono salvati dal meno significativo, quindi il primo
+        // bit diverso e' il trailing zero dello xor
+        let diff = x.get_word(xstart + currind) ^ y.get_word(ystart + currind);
+        if diff != 0 {
+            return min(len, currind + diff.trailing_zeros() as usize);
         }
-        currind += 1;
+        currind += WORD_SIZE;
     }
-    
-    return x.len().cmp(&y.len());
+    len
 }
 
-pub fn get_substr(x: &Str, start: usize, end: usize) -> Str {
-    let mut res : Str = Str::new(0);
+pub fn is_prefix_of(p: impl AsBitSlice, x: impl AsBitSlice) -> bool {
+    let (p, x) = (p.as_bit_slice(), x.as_bit_slice());
+    p.len() <= x.len() && lcp_len_range(p, 0, x, 0, p.len()) == p.len()
+}
 
-    if start==0 {
-        let mut currind: usize = 0;
-        let mut blockind: usize = 0;
-        let refx = x.as_ref();
+pub fn get_substr(x: impl AsBitSlice, start: usize, end: usize) -> Str {
+    let mut res: Str = Str::new(0);
+    append_substr(&mut res, x, start, end);
+    res
+}
 
-        while currind+WORD_SIZE <= end {
-            currind+=WORD_SIZE;
-            push_back(&mut res,refx[blockind]);
-            blockind+=1;
-        }
+// the 64 bits of x starting at ind, with zeros past its end
+pub fn get_word(x: impl AsBitSlice, ind: usize) -> usize {
+    x.as_bit_slice().get_word(ind)
+}
 
-        while currind < end {
-            res.push(x[currind]);
-            currind += 1;
-        }   
-        res
-    }else{
-        for i in start..end {
-           res.push(x[i]);
-        }
-        res
+// the bits of the last, incomplete word of the prefix of length ind
+pub fn get_tail_word(x: impl AsBitSlice, ind: usize) -> usize {
+    let bits = ind % WORD_SIZE;
+    if bits == 0 {
+        0
+    } else {
+        x.as_bit_slice().get_word(ind - bits) & ((1 << bits) - 1)
     }
 }
 
@@ -71,32 +89,60 @@ pub fn push_back(x: &mut Str, val: usize) {
     x.as_mut()[len] = val;
 }
 
-pub fn push_front(x: &mut Str, val: bool) {
-    let mut tmp : Str = Str::new(0);
-    tmp.push(val);
-    for i in 0..sux::traits::BitLength::len(&x) {
-        tmp.push(x[i]);
+// appends the lowest `bits` bits of val
+pub fn push_bits(x: &mut Str, val: usize, bits: usize) {
+    assert!(bits <= WORD_SIZE);
+    if bits == 0 {
+        return;
     }
-    *x = tmp;
-}
+    let val = if bits < WORD_SIZE { val & ((1 << bits) - 1) } else { val };
+    let len = x.len();
+    let (q, r) = (len / WORD_SIZE, len % WORD_SIZE);
+    x.resize(len + bits, false);
 
-pub fn lcp(x: &Str, y: &Str) -> Str {
-    let mut currind: usize = 0;
-    let mut blockind: usize = 0;
-    let mut res = Str::new(0);
+    let refx = x.as_mut();
+    refx[q] = (refx[q] & ((1 << r) - 1)) | (val << r);
+    if r + bits > WORD_SIZE {
+        refx[q + 1] = val >> (WORD_SIZE - r);
+    }
+}
 
-    let refx = x.as_ref();
-    let refy = y.as_ref();
+// appends y[start..end]
+pub fn append_substr(x: &mut Str, y: impl AsBitSlice, start: usize, end: usize) {
+    let y = y.as_bit_slice();
+    assert!(start <= end && end <= y.len());
+    let mut currind = start;
 
-    while currind+WORD_SIZE <= min(x.len(),y.len()) && refx[blockind]==refy[blockind] {
-        push_back(&mut res,refx[blockind]);
-        currind+=WORD_SIZE;
-        blockind+=1;
+    while currind + WORD_SIZE <= end {
+        push_bits(x, get_word(y, currind), WORD_SIZE);
+        currind += WORD_SIZE;
     }
+    push_bits(x, get_word(y, currind), end - currind);
+}
 
-    while currind < min(x.len(), y.len()) && x[currind] == y[currind] {
-        res.push(x[currind]);
-        currind += 1;
-    }
+pub fn append(x: &mut Str, y: impl AsBitSlice) {
+    let y = y.as_bit_slice();
+    append_substr(x, y, 0, y.len());
+}
+
+pub fn concat(x: &Str, y: &Str) -> Str {
+    let mut res = x.clone();
+    append(&mut res, y);
     res
 }
+
+pub fn prepend(x: &mut Str, y: &Str) {
+    *x = concat(y, x);
+}
+
+pub fn push_front(x: &mut Str, val: bool) {
+    let mut tmp : Str = Str::new(0);
+    tmp.push(val);
+    append(&mut tmp, &*x);
+    *x = tmp;
+}
+
+pub fn lcp(x: impl AsBitSlice, y: impl AsBitSlice) -> Str {
+    let x = x.as_bit_slice();
+    get_substr(x, 0, lcp_len(x, y))
+}
@@ -0,0 +1,117 @@
+use crate::utils::bit_slice::*;
+use crate::utils::str::*;
+use rand::RngCore;
+use std::fmt;
+
+// order in which the bits of a byte or of an integer are appended
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseStrError {
+    pub pos: usize,
+    pub c: char,
+}
+
+impl fmt::Display for ParseStrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid character {:?} at position {}", self.c, self.pos)
+    }
+}
+
+impl std::error::Error for ParseStrError {}
+
+// bits!("0110") builds a Str from a binary literal, with '_' allowed as separator
+#[macro_export]
+macro_rules! bits {
+    () => {
+        $crate::utils::Str::new(0)
+    };
+    ($s:expr) => {
+        $crate::utils::str_from_bin($s).expect("invalid bits! literal")
+    };
+}
+
+pub fn str_from_fn(n: usize, mut f: impl FnMut(usize) -> bool) -> Str {
+    let mut res = Str::new(0);
+    for i in 0..n {
+        res.push(f(i));
+    }
+    res
+}
+
+pub fn gen_bin_str<R: RngCore>(rng: &mut R, n: u32) -> Str {
+    str_from_fn(n as usize, |_| rng.next_u32().is_multiple_of(2))
+}
+
+pub fn str_from_bin(s: &str) -> Result<Str, ParseStrError> {
+    let mut res = Str::new(0);
+    for (pos, c) in s.chars().enumerate() {
+        match c {
+            '0' => res.push(false),
+            '1' => res.push(true),
+            '_' => {}
+            c if c.is_whitespace() => {}
+            c => {
+                return Err(ParseStrError { pos, c });
+            }
+        }
+    }
+    Ok(res)
+}
+
+// every digit gives 4 bits, the most significant first
+pub fn str_from_hex(s: &str) -> Result<Str, ParseStrError> {
+    let mut res = Str::new(0);
+    for (pos, c) in s.chars().enumerate() {
+        if c == '_' || c.is_whitespace() {
+            continue;
+        }
+        match c.to_digit(16) {
+            Some(d) => push_int(&mut res, d as usize, 4, BitOrder::MsbFirst),
+            None => {
+                return Err(ParseStrError { pos, c });
+            }
+        }
+    }
+    Ok(res)
+}
+
+pub fn str_from_bytes(bytes: &[u8], order: BitOrder) -> Str {
+    let mut res = Str::new(0);
+    for b in bytes {
+        push_int(&mut res, *b as usize, 8, order);
+    }
+    res
+}
+
+// the lowest `bits` bits of val
+pub fn str_from_int(val: usize, bits: usize, order: BitOrder) -> Str {
+    let mut res = Str::new(0);
+    push_int(&mut res, val, bits, order);
+    res
+}
+
+pub fn str_to_int(x: impl AsBitSlice, order: BitOrder) -> usize {
+    let x = x.as_bit_slice();
+    assert!(x.len() <= WORD_SIZE, "str_to_int: more than 64 bits");
+    let word = x.get_word(0);
+    match order {
+        BitOrder::LsbFirst => word,
+        BitOrder::MsbFirst => if x.is_empty() { 0 } else { word.reverse_bits() >> (WORD_SIZE - x.len()) },
+    }
+}
+
+pub fn push_int(x: &mut Str, val: usize, bits: usize, order: BitOrder) {
+    assert!(bits <= WORD_SIZE);
+    match order {
+        // i bit sono salvati dal meno significativo, come in push_bits
+        BitOrder::LsbFirst => push_bits(x, val, bits),
+        BitOrder::MsbFirst => if bits != 0 {
+            push_bits(x, val.reverse_bits() >> (WORD_SIZE - bits), bits);
+        },
+    }
+}
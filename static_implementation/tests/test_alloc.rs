@@ -0,0 +1,114 @@
+use rand::prelude::*;
+use std::alloc::{ GlobalAlloc, Layout, System };
+use std::cell::Cell;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::*;
+
+// the system allocator, counting the allocations of every thread
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations<R>(f: impl FnOnce() -> R) -> usize {
+    let start = ALLOCATIONS.with(|n| n.get());
+    let res = f();
+    let end = ALLOCATIONS.with(|n| n.get());
+    drop(res);
+    end - start
+}
+
+// every query runs once to warm up, then again counting; `keys` tells whether the returned
+// keys are copies, which may take one allocation each
+fn check_allocations<T: Trie>(ds: &T, xs: &[Str], keys: bool, name: &str) {
+    for pair in xs.windows(2) {
+        let (x, y) = if cmp(&pair[0], &pair[1]).is_gt() { (&pair[1], &pair[0]) } else { (&pair[0], &pair[1]) };
+        let queries = || {
+            (ds.pred_query(x), ds.succ_query(x), ds.ex_pref_query(x), ds.ex_range_query(x, y), ds.rank_query(x))
+        };
+        queries();
+        let n = allocations(|| (ds.ex_pref_query(x), ds.ex_range_query(x, y), ds.rank_query(x)));
+        assert!(n == 0, "{}: {} allocations in ex_pref, ex_range and rank", name, n);
+        let n = allocations(|| (ds.pred_query(x), ds.succ_query(x)));
+        assert!(n <= if keys { 2 } else { 0 }, "{}: {} allocations in pred and succ", name, n);
+    }
+}
+
+// the z-fast tries hash the query into a state: once the contexts hold it, the queries
+// allocate nothing
+fn check_context_allocations<'a, C, R>(
+    xs: &'a [Str],
+    context: impl Fn(&'a Str) -> C,
+    queries: impl Fn(&C, &C) -> R,
+    name: &str
+) {
+    for pair in xs.windows(2) {
+        let (x, y) = if cmp(&pair[0], &pair[1]).is_gt() { (&pair[1], &pair[0]) } else { (&pair[0], &pair[1]) };
+        let (cx, cy) = (context(x), context(y));
+        let n = allocations(|| queries(&cx, &cy));
+        assert!(n == 0, "{}: {} allocations in the queries of a context", name, n);
+    }
+}
+
+#[test]
+fn test_query_allocations() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for kind in [KeySet::Random, KeySet::VariableLength] {
+        let v = kind.generate(&mut rng, 1000, 300);
+        let xs: Vec<Str> = (0..300).map(|_| gen_query_key(&mut rng, &v, 300)).collect();
+
+        let mut ds1 = NaiveTrie::new();
+        let mut ds2 = CompactTrie::new();
+        let mut ds3: ZFastTrie<RollingHash> = ZFastTrie::with_seed(0);
+        let mut ds4: ZFastTrieSux<RollingHash> = ZFastTrieSux::with_seed(0);
+        ds1.build(&v);
+        ds2.build(&v);
+        ds3.build(&v);
+        ds4.build(&v);
+        check_allocations(&ds1, &xs, false, "naive");
+        check_allocations(&ds2, &xs, true, "compact");
+
+        check_context_allocations(&xs, |x| ds3.query_context(x), |cx, cy| (
+            ds3.pred_query_with(cx),
+            ds3.succ_query_with(cx),
+            ds3.ex_pref_query_with(cx),
+            ds3.ex_range_query_with(cx, cy),
+            ds3.rank_query_with(cx),
+        ), "z_fast");
+        check_context_allocations(&xs, |x| ds4.query_context(x), |cx, cy| (
+            ds4.pred_query_with(cx),
+            ds4.succ_query_with(cx),
+            ds4.ex_pref_query_with(cx),
+            ds4.ex_range_query_with(cx, cy),
+            ds4.rank_query_with(cx),
+        ), "z_fast_sux");
+
+        // a batch keeps one state for all its keys: it allocates its vectors and grows the
+        // state, whatever the number of keys
+        let batches = [(allocations(|| ds3.pred_batch(&xs)), "z_fast"), (allocations(|| ds4.pred_batch(&xs)), "z_fast_sux")];
+        for (n, name) in batches {
+            assert!(n <= 32, "{}: {} allocations in a batch of {} keys", name, n, xs.len());
+        }
+    }
+
+    let v = KeySet::Random.generate(&mut rng, 1000, 40);
+    let xs: Vec<Str> = (0..300).map(|_| gen_query_key(&mut rng, &v, 40)).collect();
+    let mut ds5 = EliasFanoTrie::new();
+    ds5.build(&v);
+    check_allocations(&ds5, &xs, true, "elias_fano");
+}
@@ -0,0 +1,70 @@
+use rand::prelude::*;
+use std::fs;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::*;
+
+#[test]
+fn test_parsers() {
+    let keys = make_prefix_free(parse_lines("ab\nabc\n\nb\nab\n"));
+    let text: Vec<String> = keys.iter().map(str_to_text).collect();
+    assert!(text == vec!["", "ab", "abc", "b"], "lines parsed as {:?}", text);
+
+    let keys = parse_urls("# crawl\nhttps://a.org/x\t200\n\n  https://a.org 301\n");
+    let text: Vec<String> = keys.iter().map(str_to_text).collect();
+    assert!(text == vec!["https://a.org/x", "https://a.org"], "urls parsed as {:?}", text);
+
+    let values = [5u64, 3, u64::MAX];
+    let plain: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let sosd: Vec<u8> = [3u64].iter().chain(values.iter()).flat_map(|x| x.to_le_bytes()).collect();
+    for (bytes, header) in [(&plain, false), (&sosd, true)] {
+        let keys = parse_u64s(bytes, header).unwrap();
+        let ints: Vec<usize> = keys.iter().map(|x| str_to_int(x, BitOrder::MsbFirst)).collect();
+        assert!(ints == vec![5, 3, usize::MAX], "u64 keys parsed as {:?} with header {}", ints, header);
+    }
+    // a first value equal to the count is a key unless the header is asked for
+    let counted: Vec<u8> = [2u64, 9].iter().flat_map(|x| x.to_le_bytes()).collect();
+    let ints: Vec<usize> = parse_u64s(&counted, false).unwrap().iter().map(|x| str_to_int(x, BitOrder::MsbFirst)).collect();
+    assert!(ints == vec![2, 9], "a first value equal to the count was taken as a header");
+    assert!(parse_u64s(&plain, true).is_err(), "SOSD file with a wrong count was accepted");
+    assert!(parse_u64s(&[], true).is_err(), "SOSD file without a count was accepted");
+    assert!(parse_u64s(&[1, 2, 3], false).is_err(), "u64 file with a partial value was accepted");
+}
+
+#[test]
+fn test_load_dir() {
+    let dir = std::env::temp_dir().join(format!("zft_datasets_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("words.txt"), "b\na\nb\n").unwrap();
+    fs::write(dir.join("hosts.urls"), "https://b.org\nhttps://a.org\n").unwrap();
+    fs::write(dir.join("ids.u64"), [7u64, 1].iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>()).unwrap();
+    fs::write(dir.join("keys.sosd"), [2u64, 4, 8].iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>()).unwrap();
+    fs::write(dir.join("notes.md"), "not a dataset").unwrap();
+
+    let res = load_dir(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let names: Vec<&str> = res.iter().map(|d| d.name.as_str()).collect();
+    assert!(names == vec!["hosts", "ids", "keys", "words"], "datasets loaded as {:?}", names);
+    assert!(res[0].keys.iter().map(str_to_text).collect::<Vec<_>>() == vec!["https://a.org", "https://b.org"]);
+    assert!(res[1].fixed_width() == Some(64) && res[1].keys.len() == 2, "ids are not 2 keys of 64 bits");
+    let ints: Vec<usize> = res[2].keys.iter().map(|x| str_to_int(x, BitOrder::MsbFirst)).collect();
+    assert!(ints == vec![4, 8], "SOSD keys loaded as {:?}", ints);
+    assert!(res[3].keys.len() == 2 && res[3].fixed_width() == Some(16), "words are not 2 distinct keys of 16 bits");
+    assert!(load_dir(dir.join("missing")).unwrap().is_empty(), "a missing directory is not an empty list");
+}
+
+#[test]
+fn test_synthetic_datasets() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for d in synthetic_datasets(0, 2000) {
+        assert!(d.keys.len() > 1000, "{} has only {} keys", d.name, d.keys.len());
+        assert!(is_prefix_free(&d.keys), "{} is not prefix free", d.name);
+        assert!(d.sample(100).keys.len() == 100, "sample of {} does not have 100 keys", d.name);
+
+        let bits = d.keys.iter().map(|x| x.len()).max().unwrap();
+        let queries = gen_queries(&mut rng, &d.keys, 2000, bits);
+        let mut ds2 = CompactTrie::new();
+        let mut ds4: ZFastTrieSux<RollingHash> = ZFastTrieSux::with_seed(0);
+        let f = Differential::new(0).add("compact", &mut ds2).add("z_fast_sux", &mut ds4).check(&d.keys, &queries);
+        assert!(f.is_none(), "answers ({}) don't match\n{}", d.name, f.unwrap());
+    }
+}
@@ -0,0 +1,35 @@
+use rand::prelude::*;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::rank_structures::*;
+
+fn crosstest_elias_fano<R: RankSelect>(v: &[usize], queries: &[usize]) {
+    let ef: EliasFano<R> = EliasFano::from_sorted(v.iter().copied());
+
+    assert!(ef.len() == v.len());
+    assert!(ef.iter().eq(v.iter().copied()), "answers (iter) don't match");
+    for q in queries {
+        let succ = v.iter().copied().find(|x| x >= q);
+        let pred = v.iter().copied().rev().find(|x| x < q);
+        assert!(ef.successor(*q) == succ, "answers (successor) don't match at {}", q);
+        assert!(ef.predecessor(*q) == pred, "answers (predecessor) don't match at {}", q);
+        assert!(ef.rank(*q) == v.iter().filter(|x| *x < q).count(), "answers (rank) don't match");
+    }
+}
+
+#[test]
+fn test_elias_fano() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for n in [0, 1, 2, 10, 1000] {
+        for universe in [1, 10, 1000, 1 << 40] {
+            let mut v: Vec<usize> = (0..n).map(|_| (rng.next_u64() as usize) % universe).collect();
+            v.sort();
+            let mut queries: Vec<usize> = (0..200).map(|_| (rng.next_u64() as usize) % (universe + 2)).collect();
+            queries.extend(v.iter().take(50));
+            crosstest_elias_fano::<SampledSelect<Rank9>>(&v, &queries);
+            crosstest_elias_fano::<NaiveRank>(&v, &queries);
+        }
+    }
+
+    let v = vec![0, 5, usize::MAX - 1, usize::MAX];
+    crosstest_elias_fano::<SampledSelect<Rank9>>(&v, &[0, 1, 6, usize::MAX - 1, usize::MAX]);
+}
@@ -0,0 +1,53 @@
+use rand::prelude::*;
+use std::fs;
+use std::panic::catch_unwind;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::*;
+
+// every input in tests/fuzz_corpus/<target>, so that the cases found by cargo fuzz
+// keep being checked without the fuzzer
+#[test]
+fn test_fuzz_corpus() {
+    for target in FUZZ_TARGETS {
+        let dir = format!("{}/tests/fuzz_corpus/{}", env!("CARGO_MANIFEST_DIR"), target);
+        let mut cont = 0;
+        for entry in fs::read_dir(&dir).expect("missing corpus directory") {
+            let path = entry.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            if let Err(e) = catch_unwind(|| run_fuzz_target(target, &data)) {
+                panic!("{}: {}", path.display(), panic_message(e));
+            }
+            cont += 1;
+        }
+        assert!(cont > 0, "empty corpus for {}", target);
+    }
+}
+
+#[test]
+fn test_fuzz_random_inputs() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for target in FUZZ_TARGETS {
+        for _ in 0..300 {
+            let len = rng.next_u32() % 4096;
+            let data: Vec<u8> = (0..len).map(|_| rng.next_u32() as u8).collect();
+            run_fuzz_target(target, &data);
+        }
+    }
+}
+
+#[test]
+fn test_fuzz_decoding() {
+    // 4 keys of at most 255 bits: 0110, then 011 + 1 sharing 3 bits with it, then 011
+    // that drops both as their prefix, then 1; an ex_pref query on 01, and a pred
+    // query on 1 that asks its rank too
+    let data = [
+        4, 255, 4, 0, 0b0110_0000, 4, 1, 3, 0b1000_0000, 3, 1, 3, 1, 0, 0b1000_0000, 2, 0, 2, 0b0100_0000, 0, 0, 1,
+        0b1000_0000,
+    ];
+    let mut r = ByteReader::new(&data);
+    let keys = decode_keys(&mut r, false);
+    assert!(keys == vec![bits!("011"), bits!("1")], "keys decoded as {:?}", keys);
+    let queries = decode_queries(&mut r, &keys);
+    let expected = vec![Query::ExPref(bits!("01")), Query::Rank(bits!("1")), Query::Pred(bits!("1"))];
+    assert!(queries == expected, "queries decoded as {:?}", queries);
+}
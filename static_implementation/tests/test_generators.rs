@@ -0,0 +1,50 @@
+use rand::prelude::*;
+use z_fast_trie_static_sux::prelude::*;
+
+fn check_sorted_and_prefix_free(v: &[Str]) {
+    for w in v.windows(2) {
+        assert!(cmp(&w[0], &w[1]) == std::cmp::Ordering::Less, "keys {:?} and {:?} not sorted", w[0], w[1]);
+        assert!(!is_prefix_of(&w[0], &w[1]), "key {:?} is a prefix of the next one", w[0]);
+    }
+}
+
+#[test]
+fn test_key_gen() {
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    // abbastanza chiavi da rendere impraticabile il controllo quadratico
+    let n = 200000;
+    let v = KeyGen::new(n, 100).set_lengths(LengthDist::spread(100)).generate(&mut rng);
+    assert!(v.len() == n, "{} keys instead of {}", v.len(), n);
+    assert!(v.iter().all(|s| s.len() >= 25 && s.len() < 100), "key length outside 25..100");
+    check_sorted_and_prefix_free(&v);
+
+    let v = KeyGen::new(1000, 200).set_shared_prefix(150).generate(&mut rng);
+    assert!(v.len() == 1000, "{} keys instead of 1000", v.len());
+    assert!(v.iter().all(|s| s.len() == 200 && lcp_len(s, &v[0]) >= 150), "key without the shared prefix of 150 bits");
+    check_sorted_and_prefix_free(&v);
+
+    let v = KeyGen::new(1000, 100).set_skew(0.9).generate(&mut rng);
+    let ones: usize = v.iter().map(|s| (0..s.len()).filter(|&i| s[i]).count()).sum();
+    assert!(ones * 100 > 85 * v.len() * 100, "only {} ones out of {} bits", ones, v.len() * 100);
+    check_sorted_and_prefix_free(&v);
+
+    let v = KeyGen::new(1000, 0)
+        .set_lengths(LengthDist::Geometric { min: 1, max: 40, p: 0.8 })
+        .generate(&mut rng);
+    assert!(v.iter().all(|s| s.len() != 0 && s.len() <= 40), "key length outside 1..=40");
+    check_sorted_and_prefix_free(&v);
+
+    // only 8 distinct keys of 3 bits exist
+    let v = KeyGen::new(100, 3).generate(&mut rng);
+    assert!(v.len() == 8, "{} keys of 3 bits instead of 8", v.len());
+    check_sorted_and_prefix_free(&v);
+}
+
+#[test]
+fn test_make_prefix_free() {
+    let v = vec![bits!("0110"), bits!("01"), bits!("1"), bits!("011"), bits!("1"), bits!("00")];
+    let res = make_prefix_free(v.clone());
+    assert!(res == vec![bits!("00"), bits!("01"), bits!("1")], "make_prefix_free gave {:?}", res);
+    assert!(!is_prefix_free(&v) && is_prefix_free(&res), "is_prefix_free misjudged {:?} or {:?}", v, res);
+}
@@ -0,0 +1,68 @@
+use rand::prelude::*;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::*;
+
+#[test]
+fn test_encodings() {
+    let keys = Encoding::Bytes.parse_keys("b\na\n\n").unwrap();
+    assert!(keys.len() == 3 && Encoding::Bytes.format_key(&keys[0]) == "b", "bytes parsed as {} keys", keys.len());
+
+    let prefix = Encoding::Bytes.parse_prefix("b").unwrap();
+    assert!(is_prefix_of(&prefix, &keys[0]) && !is_prefix_of(&keys[0], &prefix), "prefix b is not a proper prefix of the key b");
+
+    let keys = Encoding::Bits.parse_keys("0110\n1").unwrap();
+    assert!(keys == vec![bits!("0110"), bits!("1")], "bits parsed as {:?}", keys);
+    assert!(Encoding::Bits.parse_keys("01\n2").is_err(), "bit key with a 2 was accepted");
+
+    let keys = Encoding::U64.parse_keys("7\n\n18446744073709551615\n").unwrap();
+    let text: Vec<String> = keys.iter().map(|k| Encoding::U64.format_key(k)).collect();
+    assert!(text == vec!["7", "18446744073709551615"], "u64 keys formatted as {:?}", text);
+    assert!(Encoding::U64.parse_keys("-1").is_err(), "negative u64 key was accepted");
+
+    for e in Encoding::ALL {
+        assert!(Encoding::from_name(e.name()) == Some(e));
+    }
+}
+
+#[test]
+fn test_index_file() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for name in TRIE_IMPLS {
+        let kind = if name == "elias_fano" { KeySet::Random } else { KeySet::Skewed };
+        let keys = kind.generate(&mut rng, 200, if name == "elias_fano" { 40 } else { 100 });
+        let index = Index::new(name, Encoding::Bits, rng.next_u64(), keys).unwrap();
+
+        let mut bytes = vec![];
+        index.write_to(&mut bytes).unwrap();
+        let read = Index::read_from(&mut bytes.as_slice()).unwrap();
+        assert!(
+            read.implementation == name && read.encoding == Encoding::Bits && read.seed == index.seed,
+            "header of {} changed in the round trip", name
+        );
+        assert!(read.keys == index.keys, "keys of {} changed in the round trip", name);
+
+        let ds = read.build();
+        if let Err(e) = ds.dyn_validate() {
+            panic!("{}: {}", name, e)
+        }
+        assert!(read.par_build(3).dyn_stats() == ds.dyn_stats(), "par_build of {} gave another trie", name);
+        let queries = gen_queries(&mut rng, &read.keys, 200, 100);
+        let mut ds = new_trie(name, read.seed).unwrap();
+        assert!(Differential::new(0).add(name, ds.as_mut()).check(&read.keys, &queries).is_none());
+
+        assert!(Index::read_from(&mut &bytes[..bytes.len() - 1]).is_err(), "truncated index of {} was accepted", name);
+    }
+
+    assert!(trie_impl("sux") == Some("z_fast_sux"));
+    assert!(Index::new("patricia", Encoding::Bits, 0, vec![]).is_err());
+    assert!(Index::new("elias_fano", Encoding::Bits, 0, vec![bits!("0"), bits!("10")]).is_err());
+    assert!(Index::read_from(&mut &b"ZFT0"[..]).is_err(), "index with a wrong magic was accepted");
+
+    // a key length far beyond the input is an error, not a huge allocation
+    let mut bytes = vec![];
+    Index::new("compact", Encoding::Bits, 0, vec![bits!("01")]).unwrap().write_to(&mut bytes).unwrap();
+    let len_pos = bytes.len() - 9;
+    bytes[len_pos..len_pos + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    let res = Index::read_from(&mut bytes.as_slice());
+    assert!(res.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidData), "oversized key length was not rejected as invalid data");
+}
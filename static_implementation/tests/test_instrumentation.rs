@@ -0,0 +1,52 @@
+use rand::prelude::*;
+use z_fast_trie_static_sux::instrumentation::*;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::*;
+
+#[test]
+fn test_histogram() {
+    let mut h = Histogram::new();
+    assert!(h.is_empty() && h.mean() == 0.0 && h.percentile(0.5) == 0);
+    for x in [1, 1, 2, 3, 100] {
+        h.add(x);
+    }
+    assert!(h.len() == 5 && h.count(1) == 2 && h.count(100) == 1, "histogram counts {} values, {} ones and {} hundreds", h.len(), h.count(1), h.count(100));
+    assert!(h.mean() == 21.4 && h.max() == 100, "histogram mean {} and max {}", h.mean(), h.max());
+    assert!(h.percentile(0.5) == 2 && h.percentile(0.8) == 3 && h.percentile(1.0) == 100, "histogram percentiles are not 2, 3 and 100");
+}
+
+#[test]
+fn test_trace() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let v = KeyGen::new(2000, 200).generate(&mut rng);
+    let mut ds3 = ZFastTrie::<RollingHash>::with_seed(0);
+    let mut ds4 = ZFastTrieSux::<RollingHash>::with_seed(0);
+    ds3.build(&v);
+    ds4.build(&v);
+
+    let queries = gen_queries(&mut rng, &v, 500, 200);
+    let (mut w3, mut w4) = (WorkloadStats::new(), WorkloadStats::new());
+    for q in &queries {
+        let a3 = w3.trace(|| ds3.dyn_answer(q));
+        let a4 = w4.trace(|| ds4.dyn_answer(q));
+        assert!(a3 == a4, "answers ({}) don't match", q);
+    }
+
+    for w in [&w3, &w4] {
+        assert!(w.queries == queries.len());
+        if ENABLED {
+            // every query looks for an exit node, and the search probes the z-map
+            assert!(w.exit_searches.count(0) == 0 && w.z_map_probes.mean() >= 1.0, "a query without an exit search or a z-map probe");
+            assert!(w.jump_hops.max() > 0, "no query took a jump pointer");
+        } else {
+            assert!(w.z_map_probes.max() == 0 && w.jump_hops.max() == 0, "probes counted with instrumentation disabled");
+        }
+    }
+
+    // an enclosing trace sees the queries of the inner ones
+    let x = v[0].clone();
+    let ((_, inner), outer) = trace(|| trace(|| ds4.pred_query(&x)));
+    assert!(inner == outer, "enclosing trace saw {:?} instead of {:?}", outer, inner);
+    let (_, empty) = trace(|| ());
+    assert!(empty == QueryStats::default(), "empty trace counted {:?}", empty);
+}
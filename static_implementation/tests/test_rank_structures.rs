@@ -0,0 +1,58 @@
+use rand::prelude::*;
+use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::rank_structures::*;
+
+fn gen_bits(rng: &mut SmallRng, n: usize, density: u32) -> Str {
+    str_from_fn(n, |_| rng.next_u32() % 100 < density)
+}
+
+fn crosstest_rank_select<T1: RankSelect, T2: RankSelect>(v: &Str) {
+    let ds1 = T1::from_bits(v.clone());
+    let ds2 = T2::from_bits(v.clone());
+
+    assert!(ds1.count_ones() == ds2.count_ones(), "answers (count_ones) don't match");
+    for i in 0..=v.len() {
+        assert!(ds1.rank1(i) == ds2.rank1(i), "answers (rank1) don't match at {}", i);
+        assert!(ds1.rank0(i) == ds2.rank0(i), "answers (rank0) don't match at {}", i);
+    }
+    for k in 0..=v.len() {
+        assert!(ds1.select1(k) == ds2.select1(k), "answers (select1) don't match at {}", k);
+        assert!(ds1.select0(k) == ds2.select0(k), "answers (select0) don't match at {}", k);
+    }
+}
+
+fn crosstest_rank<T1: RankStructure, T2: RankStructure>(v0: &Str, v1: &Str) {
+    let mut ds1 = T1::new();
+    let mut ds2 = T2::new();
+    ds1.build(v0, v1);
+    ds2.build(v0, v1);
+
+    for i in 0..v0.len() {
+        assert!(ds1.rank(i, v0, v1) == ds2.rank(i, v0, v1), "answers (rank) don't match at {}", i);
+    }
+}
+
+#[test]
+fn test_rank_structures_and_naive() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for n in [1, 2, 3, 63, 64, 65, 511, 512, 513, 1000, 5000] {
+        for density in [0, 1, 30, 100] {
+            let v0 = gen_bits(&mut rng, n, density);
+            let v1 = gen_bits(&mut rng, n, density);
+            crosstest_rank::<NaiveRank, JacobsonRank>(&v0, &v1);
+            crosstest_rank::<NaiveRank, Rank9>(&v0, &v1);
+        }
+    }
+}
+
+#[test]
+fn test_sampled_select_and_naive() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for n in [0, 1, 2, 3, 63, 64, 65, 511, 512, 513, 1000, 5000] {
+        for density in [0, 1, 50, 99, 100] {
+            let v = gen_bits(&mut rng, n, density);
+            crosstest_rank_select::<NaiveRank, SampledSelect>(&v);
+            crosstest_rank_select::<NaiveRank, SampledSelect<Rank9>>(&v);
+        }
+    }
+}
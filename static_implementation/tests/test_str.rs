@@ -0,0 +1,136 @@
+use rand::prelude::*;
+use z_fast_trie_static_sux::prelude::*;
+
+fn naive_substr(x: &Str, start: usize, end: usize) -> Str {
+    let mut res = Str::new(0);
+    for i in start..end {
+        res.push(x[i]);
+    }
+    res
+}
+
+#[test]
+fn test_word_level_utilities() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 63, 64, 65, 127, 128, 129, 1000] {
+        let x = gen_bin_str(&mut rng, len);
+        for _ in 0..100 {
+            let a = if len == 0 { 0 } else { (rng.next_u32() % (len + 1)) as usize };
+            let b = if len == 0 { 0 } else { (rng.next_u32() % (len + 1)) as usize };
+            let (start, end) = if a < b { (a, b) } else { (b, a) };
+            assert!(get_substr(&x, start, end) == naive_substr(&x, start, end), "get_substr({}, {}) differs from the naive substring", start, end);
+        }
+
+        let ylen = rng.next_u32() % 200;
+        let y = gen_bin_str(&mut rng, ylen);
+        let mut xy = x.clone();
+        for i in &y {
+            xy.push(i);
+        }
+        assert!(concat(&x, &y) == xy, "concat differs from pushing the bits");
+
+        let mut z = y.clone();
+        prepend(&mut z, &x);
+        assert!(z == xy, "prepend differs from concat");
+
+        let mut w = x.clone();
+        push_front(&mut w, true);
+        let mut expected = Str::new(0);
+        expected.push(true);
+        assert!(w == concat(&expected, &x), "push_front differs from concat");
+    }
+}
+
+fn naive_lcp_len(x: &Str, y: &Str) -> usize {
+    let mut i = 0;
+    while i < x.len() && i < y.len() && x[i] == y[i] {
+        i += 1;
+    }
+    i
+}
+
+#[test]
+fn test_lcp_and_prefix_primitives() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for _ in 0..2000 {
+        let xlen = rng.next_u32() % 300;
+        let x = gen_bin_str(&mut rng, xlen);
+        // y shares a random prefix of x, so that long common prefixes are frequent
+        let shared = (rng.next_u32() % (xlen + 1)) as usize;
+        let ylen = rng.next_u32() % 300;
+        let mut y = naive_substr(&x, 0, shared);
+        append(&mut y, gen_bin_str(&mut rng, ylen));
+
+        let l = naive_lcp_len(&x, &y);
+        assert!(lcp_len(&x, &y) == l, "lcp_len differs from the naive one");
+        assert!(lcp(&x, &y) == naive_substr(&x, 0, l), "lcp is not the prefix of length lcp_len");
+        assert!(is_prefix_of(&x, &y) == (l == x.len()), "is_prefix_of disagrees with lcp_len");
+
+        let len = (rng.next_u32() as usize) % (y.len() + 1);
+        assert!(
+            cmp_prefix(&x, &y, len) == cmp(&x, &naive_substr(&y, 0, len)),
+            "cmp_prefix differs from cmp on the prefix of length {}", len
+        );
+
+        let xs = (rng.next_u32() as usize) % (x.len() + 1);
+        let ys = (rng.next_u32() as usize) % (y.len() + 1);
+        let m = std::cmp::min(x.len() - xs, y.len() - ys);
+        assert!(
+            lcp_len_range(&x, xs, &y, ys, m) ==
+                naive_lcp_len(&naive_substr(&x, xs, xs + m), &naive_substr(&y, ys, ys + m)),
+            "lcp_len_range({}, {}, {}) differs from the naive one", xs, ys, m
+        );
+    }
+}
+
+#[test]
+fn test_parsing_and_formatting() {
+    let x = bits!("1011_0010_1");
+    assert!(x.len() == 9 && x[0] && !x[1] && x[8], "bits! ignored the separator");
+    assert!(str_from_bin("10x1") == Err(ParseStrError { pos: 2, c: 'x' }), "str_from_bin did not report the x at position 2");
+    assert!(str_from_hex("b2 8") == Ok(bits!("1011 0010 1000")), "str_from_hex did not skip the space");
+    assert!(str_from_bytes(&[0xb2], BitOrder::MsbFirst) == bits!("10110010"), "str_from_bytes msb first reversed the bits");
+    assert!(str_from_bytes(&[0xb2], BitOrder::LsbFirst) == bits!("01001101"), "str_from_bytes lsb first kept the order");
+    assert!(str_from_int(6, 4, BitOrder::MsbFirst) == bits!("0110"), "str_from_int(6, 4) is not 0110");
+
+    assert!(format!("{}", x.as_bit_slice()) == "101100101", "Display is not the binary string");
+    assert!(format!("{:.4}", x.as_bit_slice()) == "1011... (9 bits)", "Display did not truncate at the precision");
+    assert!(format!("{:x}", x.as_bit_slice()) == "b28", "LowerHex is not b28");
+    assert!(format!("{:?}", x.as_bit_slice()) == "bits!(\"101100101\")", "Debug is not a bits! literal");
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 63, 64, 65, 200] {
+        let x = gen_bin_str(&mut rng, len);
+        let s = x.as_bit_slice();
+        assert!(str_from_bin(&format!("{}", s)) == Ok(x.clone()), "binary round trip changed a key of {} bits", len);
+        if len % 4 == 0 {
+            assert!(str_from_hex(&format!("{:x}", s)) == Ok(x.clone()), "hex round trip changed a key of {} bits", len);
+        }
+        if len <= 64 {
+            for order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+                let v = str_to_int(&x, order);
+                assert!(str_from_int(v, len as usize, order) == x, "int round trip changed a key of {} bits", len);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ord_str() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut v: Vec<Str> = (0..500).map(|_| {
+        let len = rng.next_u32() % 100;
+        gen_bin_str(&mut rng, len)
+    }).collect();
+    let mut w: Vec<OrdStr> = v.iter().map(OrdStr::from).collect();
+    v.sort_by(cmp);
+    w.sort();
+    assert!(w.iter().zip(&v).all(|(a, b)| *a == *b), "OrdStr sorts unlike cmp");
+
+    let set: std::collections::BTreeSet<OrdStr> = w.iter().cloned().collect();
+    assert!(set.iter().zip(set.iter().skip(1)).all(|(a, b)| cmp(a, b) == std::cmp::Ordering::Less));
+    assert!(OrdStr(bits!("01")) < OrdStr(bits!("011")) && OrdStr(bits!("1")) > OrdStr(bits!("0111")));
+
+    let hashed: std::collections::HashSet<OrdStr> = w.into_iter().collect();
+    assert!(v.iter().all(|x| hashed.contains(x)), "a key is not found in a set of OrdStr");
+}
@@ -1,263 +1,696 @@
 use rand::prelude::*;
-use std::cmp::min;
 use std::cmp::Ordering::*;
-use std::mem::swap;
 use z_fast_trie_static_sux::prelude::*;
+use z_fast_trie_static_sux::testing::*;
 
 type Ds1 = NaiveTrie;
 type Ds2 = CompactTrie;
 type Ds3 = ZFastTrie<RollingHash>;
 type Ds4 = ZFastTrieSux<RollingHash>;
+type Ds5 = EliasFanoTrie;
+
+#[test]
+fn test_naive() {
+    let mut ds1 = Ds1::new();
+    Differential::new(0).set_sizes(5, 100, 100, 2000).add("naive", &mut ds1).run();
+}
+
+#[test]
+fn test_baselines() {
+    let mut ds1 = SortedVecTrie::new();
+    let mut ds2 = BTreeSetTrie::new();
+    Differential::new(0)
+        .set_sizes(5, 100, 100, 2000)
+        .add("sorted_vec", &mut ds1)
+        .add("btree_set", &mut ds2)
+        .run();
+}
 
 #[test]
 fn abcd() {
-    let t = 1;
-    let bits = 30;
-    let n = 20;
-    let m = 500;
-    let deb = true;
-    let variablelen = false;
-    let fixed_seed = true;
     let mut ds1 = Ds1::new();
-    let mut ds2 = Ds4::new();
-    crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+    let mut ds2 = Ds4::with_seed(0);
+    Differential::new(0)
+        .set_kinds(&KeySet::FIXED_WIDTH)
+        .set_sizes(1, 20, 30, 500)
+        .add("naive", &mut ds1)
+        .add("z_fast_sux", &mut ds2)
+        .run();
 }
 
-#[cfg(test)]
-mod cross_tests {
-    use crate::*;
+// NaiveTrie that never finds a key with a given prefix
+struct BrokenExPref(NaiveTrie);
 
-    #[test]
-    fn test_compact_and_naive_fixed() {
-        let t = 5;
-        let bits = 100;
-        let n = 100;
-        let m = 5000;
-        let deb = false;
-        let variablelen = false;
-        let fixed_seed = false;
+impl Trie for BrokenExPref {
+    fn build(&mut self, v: &[Str]) {
+        self.0.build(v);
+    }
 
-        let mut ds1: Ds1 = Ds1::new();
-        let mut ds2: Ds2 = Ds2::new();
+    fn pred_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.0.pred_query(x)
+    }
 
-        crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+    fn succ_query(&self, x: impl AsBitSlice) -> Option<Key<'_>> {
+        self.0.succ_query(x)
     }
 
-    #[test]
-    fn test_compact_and_naive_variable() {
-        let t = 5;
-        let bits = 100;
-        let n = 1000;
-        let m = 5000;
-        let deb = false;
-        let variablelen = true;
-        let fixed_seed = true;
+    fn ex_pref_query(&self, _x: impl AsBitSlice) -> bool {
+        false
+    }
 
-        let mut ds1: Ds1 = Ds1::new();
-        let mut ds2: Ds2 = Ds2::new();
+    fn ex_range_query(&self, x: impl AsBitSlice, y: impl AsBitSlice) -> bool {
+        self.0.ex_range_query(x, y)
+    }
 
-        crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+    fn rank_query(&self, x: impl AsBitSlice) -> usize {
+        self.0.rank_query(x)
     }
 
-    #[test]
-    fn test_compact_and_z_fast_variable() {
-        let t = 5;
-        let bits = 100;
-        let n = 1000;
-        let m = 5000;
-        let deb = false;
-        let variablelen = true;
-        let fixed_seed = true;
+    fn stats(&self) -> TrieStats {
+        self.0.stats()
+    }
+}
 
-        let mut ds1: Ds2 = Ds2::new();
-        let mut ds2: Ds3 = Ds3::new();
+#[test]
+fn test_differential_shrinking() {
+    let mut ds = BrokenExPref(NaiveTrie::new());
+    let f = Differential::new(0)
+        .set_sizes(1, 100, 100, 1000)
+        .add("broken", &mut ds)
+        .find_failure()
+        .expect("the broken trie should fail");
+
+    // a single empty key is the smallest set where the empty string is a prefix of a key
+    assert!(f.trie == "broken" && f.keys == vec![bits!("")], "shrinking did not reach the single empty key\n{}", f);
+    assert!(f.query == Some(testing::Query::ExPref(bits!(""))), "shrinking did not reach the query on the empty prefix\n{}", f);
+    assert!(format!("{}", f).contains("let keys = vec![\n    bits!(\"\"),\n];"));
+}
 
-        crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+#[test]
+fn test_seeded_hash() {
+    let h1 = RollingHash::with_seed(42);
+    let h2 = RollingHash::with_seed(42);
+    assert!(h1.seed() == 42 && h1.base() == h2.base() && h1.modulo() == h2.modulo());
+
+    let h3 = RollingHash::from_parameters(h1.base(), h1.modulo(), h1.seed());
+    for base in [0, 1] {
+        let res = std::panic::catch_unwind(|| RollingHash::from_parameters(base, 101, 0));
+        assert!(res.is_err(), "degenerate base {} was accepted", base);
     }
+    let mut s = Str::new(0);
+    for i in 0..200 {
+        s.push(i % 3 == 0);
+    }
+    assert!(h1.hash(&s) == h3.hash(&s));
 
-    #[test]
-    fn test_compact_and_z_fast_fixed() {
-        let t = 5;
-        let bits = 200;
-        let n = 1000;
-        let m = 5000;
-        let deb = false;
-        let variablelen = false;
-        let fixed_seed = true;
+    let mut d1: MinimalPerfectHashStaticDict<Str, usize, RollingHash> = StaticDict::with_seed(7);
+    assert!(d1.seed() == 7);
 
-        let mut ds1: Ds2 = Ds2::new();
-        let mut ds2: Ds3 = Ds3::new();
+    // a second build starts over, and len counts the keys of the last one
+    let keys = vec![bits!(""), bits!("00"), bits!("001"), bits!("111")];
+    d1.build(&keys[..3].to_vec(), &vec![0, 1, 2]);
+    d1.build(&keys, &vec![3, 2, 1, 0]);
+    let values: Vec<Option<usize>> = keys.iter().map(|x| d1.get(x).copied()).collect();
+    assert!(d1.len() == 4 && values == vec![Some(3), Some(2), Some(1), Some(0)], "the rebuild kept the keys or values of the first build");
+}
+
+struct TinyHash(RollingHash);
+
+impl Hash for TinyHash {
+    type DomainType = Str;
+    type HashType = usize;
+    type State = RollingHashState;
 
-        crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+    fn new() -> Self {
+        Self::with_seed(0)
     }
 
-    #[test]
-    fn test_compact_and_z_fast_fixed_small() {
-        let t = 5;
-        let bits = 30;
-        let n = 1000;
-        let m = 5000;
-        let deb = false;
-        let variablelen = false;
-        let fixed_seed = true;
+    fn with_seed(seed: u64) -> Self {
+        TinyHash(RollingHash::from_parameters(2 + (seed as usize) % 2000, 2003, seed))
+    }
 
-        let mut ds1: Ds2 = Ds2::new();
-        let mut ds2: Ds3 = Ds3::new();
+    fn hash(&self, s: &Str) -> usize {
+        self.0.hash(s)
+    }
 
-        crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+    fn slow_prefix_hash(&self, s: BitSlice, ind: usize) -> usize {
+        self.0.slow_prefix_hash(s, ind)
     }
 
-    #[test]
-    fn test_z_fast_and_z_fast_sux_variable() {
-        let t = 5;
-        let bits = 200;
-        let n = 1000;
-        let m = 5000;
-        let deb = false;
-        let variablelen = true;
-        let fixed_seed = true;
+    fn compute_state(&self, s: BitSlice) -> RollingHashState {
+        self.0.compute_state(s)
+    }
 
-        let mut ds1: Ds3 = Ds3::new();
-        let mut ds2: Ds4 = Ds4::new();
+    fn fast_prefix_hash(&self, s: BitSlice, state: &RollingHashState, ind: usize) -> usize {
+        self.0.fast_prefix_hash(s, state, ind)
+    }
+}
 
-        crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+#[test]
+fn test_validate_small_sets() {
+    let sets = [vec![], vec![bits!("")], vec![bits!("0110"), bits!("1")], vec![bits!("0"), bits!("10"), bits!("11")]];
+    for v in &sets {
+        let mut ds2 = Ds2::new();
+        let mut ds3 = Ds3::with_seed(0);
+        let mut ds4 = Ds4::with_seed(0);
+        ds2.build(v);
+        ds3.build(v);
+        ds4.build(v);
+        if let Err(e) = ds2.validate() {
+            panic!("compact on {:?}: {}", v, e)
+        }
+        if let Err(e) = ds3.validate() {
+            panic!("z_fast on {:?}: {}", v, e)
+        }
+        if let Err(e) = ds4.validate() {
+            panic!("z_fast_sux on {:?}: {}", v, e)
+        }
     }
+}
 
-    #[test]
-    fn test_z_fast_and_z_fast_sux_fixed() {
-        let t = 5;
-        let bits = 200;
-        let n = 1000;
-        let m = 5000;
-        let deb = false;
-        let variablelen = false;
-        let fixed_seed = true;
+#[test]
+fn test_handle_verification() {
+    let mut ds1 = Ds2::new();
+    let mut ds2: ZFastTrie<TinyHash> = ZFastTrie::with_seed(0);
+    ds2.set_verify_handles(true);
+    Differential::new(0)
+        .set_kinds(&[KeySet::Random])
+        .set_sizes(5, 100, 100, 5000)
+        .add("compact", &mut ds1)
+        .add("z_fast_tiny_hash", &mut ds2)
+        .run();
+
+    // the build rehashed until no handle collided, so every collision was found by a query
+    assert!(ds2.build_collisions() == 0, "{} handles still collide after rehashing", ds2.build_collisions());
+    assert!(ds2.collisions() > 0, "the queries found no false hit of the tiny hash");
+    let v = KeySet::Random.generate(&mut SmallRng::seed_from_u64(0), 100, 100);
+    ds2.build(&v);
+    assert!(ds2.collisions() == 0, "the build kept {} false hits of the previous queries", ds2.collisions());
+
+    // the hashes tried after a collision are drawn from the seed, so the same keys give
+    // the same hash
+    let mut ds4: ZFastTrie<TinyHash> = ZFastTrie::with_seed(0);
+    ds4.set_verify_handles(true);
+    ds4.build(&v);
+    assert!(ds2.hash().0.seed() != 0, "the tiny hash of seed 0 had no colliding handle");
+    assert!(ds4.hash().0.seed() == ds2.hash().0.seed(), "the same seed and keys gave another hash");
+
+    // more handles than hashes: the build gives up rehashing and keeps the colliding handles
+    // aside, so that the queries still answer
+    let mut ds3: ZFastTrie<TinyHash> = ZFastTrie::with_seed(0);
+    ds3.set_verify_handles(true);
+    Differential::new(0)
+        .set_kinds(&[KeySet::Random])
+        .set_sizes(1, 3000, 100, 5000)
+        .add("compact", &mut ds1)
+        .add("z_fast_tiny_hash", &mut ds3)
+        .run();
+    assert!(ds3.build_collisions() > 0, "3000 keys with 2003 hashes gave no colliding handle");
+}
 
-        let mut ds1: Ds3 = Ds3::new();
-        let mut ds2: Ds4 = Ds4::new();
+#[test]
+fn test_query_context() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let v: Vec<Str> = (0..500).map(|_| gen_bin_str(&mut rng, 300)).collect();
+    let mut ds3 = Ds3::with_seed(0);
+    let mut ds4 = Ds4::with_seed(0);
+    ds3.build(&v);
+    ds4.build(&v);
+    if let Err(e) = ds3.validate() {
+        panic!("z_fast: {}", e)
+    }
+    if let Err(e) = ds4.validate() {
+        panic!("z_fast_sux: {}", e)
+    }
 
-        crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+    for _ in 0..500 {
+        let x = gen_bin_str(&mut rng, 300);
+        let y = gen_bin_str(&mut rng, 300);
+        let (x, y) = if cmp(&x, &y) == Greater { (y, x) } else { (x, y) };
+        let (cx3, cy3) = (ds3.query_context(&x), ds3.query_context(&y));
+        let (cx4, cy4) = (ds4.query_context(&x), ds4.query_context(&y));
+
+        assert!(ds3.pred_query_with(&cx3) == ds3.pred_query(&x));
+        assert!(ds3.succ_query_with(&cx3) == ds3.succ_query(&x));
+        assert!(ds3.ex_range_query_with(&cx3, &cy3) == ds3.ex_range_query(&x, &y));
+        assert!(ds4.pred_query_with(&cx4) == ds3.pred_query(&x));
+        assert!(ds4.succ_query_with(&cx4) == ds3.succ_query(&x));
+        assert!(ds4.ex_range_query_with(&cx4, &cy4) == ds3.ex_range_query(&x, &y));
+
+        let p = get_substr(&v[0], 0, 1 + x.len() % 299);
+        assert!(ds3.ex_pref_query_with(&ds3.query_context(&p)));
+        assert!(ds4.query_all(&p).ex_pref);
     }
+}
 
-    #[test]
-    fn test_z_fast_and_z_fast_sux_fixed_small() {
-        let t = 5;
-        let bits = 30;
-        let n = 1000;
-        let m = 5000;
-        let deb = false;
-        let variablelen = false;
-        let fixed_seed = true;
+fn check_sliced_queries<T: Trie>(ds: &T, buf: &Str, x: &Str, offset: usize) {
+    let sx = BitSlice::new(buf.as_ref(), offset, x.len());
+    assert!(sx == x.as_bit_slice() && sx.to_str() == *x);
+    assert!(ds.pred_query(sx) == ds.pred_query(x), "pred on a view at offset {} differs from pred on the key", offset);
+    assert!(ds.succ_query(sx) == ds.succ_query(x), "succ on a view at offset {} differs from succ on the key", offset);
+    assert!(ds.ex_pref_query(sx) == ds.ex_pref_query(x), "ex_pref on a view at offset {} differs from ex_pref on the key", offset);
+    assert!(ds.ex_range_query(x, sx) == ds.ex_range_query(x, x), "range query on a view at offset {} differs from the one on the key", offset);
+}
 
-        let mut ds1: Ds3 = Ds3::new();
-        let mut ds2: Ds4 = Ds4::new();
+#[test]
+fn test_bit_slice_queries() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let v: Vec<Str> = (0..300).map(|_| gen_bin_str(&mut rng, 200)).collect();
+    let mut ds1 = Ds1::new();
+    let mut ds2 = Ds2::new();
+    let mut ds3 = Ds3::with_seed(0);
+    let mut ds4 = Ds4::with_seed(0);
+    ds1.build(&v);
+    ds2.build(&v);
+    ds3.build(&v);
+    ds4.build(&v);
+
+    for (i, key) in v.iter().enumerate() {
+        // the key is embedded at a random offset of a larger buffer
+        let x = if i % 2 == 0 { key.clone() } else { gen_bin_str(&mut rng, 1 + (i as u32) % 250) };
+        let offset = (rng.next_u32() % 130) as usize;
+        let mut buf = gen_bin_str(&mut rng, offset as u32);
+        append(&mut buf, &x);
+        append(&mut buf, gen_bin_str(&mut rng, 70));
+
+        check_sliced_queries(&ds1, &buf, &x, offset);
+        check_sliced_queries(&ds2, &buf, &x, offset);
+        check_sliced_queries(&ds3, &buf, &x, offset);
+        check_sliced_queries(&ds4, &buf, &x, offset);
+
+        // the z-fast tries answer with views of their own keys
+        let sx = BitSlice::new(buf.as_ref(), offset, x.len());
+        assert!(matches!(ds3.succ_query(sx), Some(Key::Borrowed(_)) | None));
+        assert!(matches!(ds4.pred_query(sx), Some(Key::Borrowed(_)) | None));
+    }
+}
 
-        crosstest(t, bits, n, m, deb, variablelen, fixed_seed, &mut ds1, &mut ds2);
+fn check_batch<T: Trie>(ds: &T, xs: &[Str], ranges: &[(Str, Str)], name: &str) {
+    let pred: Vec<_> = xs.iter().map(|x| ds.pred_query(x)).collect();
+    let succ: Vec<_> = xs.iter().map(|x| ds.succ_query(x)).collect();
+    let ex_pref: Vec<_> = xs.iter().map(|x| ds.ex_pref_query(x)).collect();
+    let ex_range: Vec<_> = ranges.iter().map(|(x, y)| ds.ex_range_query(x, y)).collect();
+    assert!(ds.pred_batch(xs) == pred, "pred_batch of {} differs from pred_query", name);
+    assert!(ds.succ_batch(xs) == succ, "succ_batch of {} differs from succ_query", name);
+    assert!(ds.ex_pref_batch(xs) == ex_pref, "ex_pref_batch of {} differs from ex_pref_query", name);
+    assert!(ds.ex_range_batch(ranges) == ex_range, "ex_range_batch of {} differs from ex_range_query", name);
+}
+
+#[test]
+fn test_batch_queries() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let h = RollingHash::with_seed(0);
+    for kind in KeySet::ALL {
+        // few keys, so that the tiny hash finds a seed without colliding handles
+        let v = kind.generate(&mut rng, 60, 200);
+        let mut ds2 = Ds2::new();
+        let mut ds3 = Ds3::with_seed(0);
+        let mut ds4 = Ds4::with_seed(0);
+        let mut ds5: ZFastTrie<TinyHash> = ZFastTrie::with_seed(0);
+        ds5.set_verify_handles(true);
+        ds2.build(&v);
+        ds3.build(&v);
+        ds4.build(&v);
+        ds5.build(&v);
+
+        // queries around the keys, most sharing long prefixes with some other one, and repeated
+        let mut xs: Vec<Str> = (0..500).map(|_| gen_query_key(&mut rng, &v, 200)).collect();
+        xs.extend(xs[..50].to_vec());
+        let ranges: Vec<(Str, Str)> = xs
+            .chunks(2)
+            .map(|p| if cmp(&p[0], &p[1]) == Greater { (p[1].clone(), p[0].clone()) } else { (p[0].clone(), p[1].clone()) })
+            .collect();
+
+        check_batch(&ds2, &xs, &ranges, "compact");
+        check_batch(&ds3, &xs, &ranges, "z_fast");
+        check_batch(&ds4, &xs, &ranges, "z_fast_sux");
+        check_batch(&ds5, &xs, &ranges, "z_fast_tiny_hash");
+
+        // the state hashed from a neighbour gives the same prefix hashes
+        for w in xs.windows(2) {
+            let (x, y) = (w[0].as_bit_slice(), w[1].as_bit_slice());
+            let state = h.update_state(y, h.compute_state(x), lcp_len(x, y));
+            for ind in [0, y.len() / 2, y.len()] {
+                assert!(h.fast_prefix_hash(y, &state, ind) == h.slow_prefix_hash(y, ind), "updated state gives another hash of the prefix of length {}", ind);
+            }
+        }
     }
+
+    let ds4 = Ds4::with_seed(0);
+    assert!(ds4.pred_batch(&[bits!("0")]) == vec![None] && ds4.ex_range_batch(&[]).is_empty());
 }
 
-fn gen_bin_str(rng: &mut SmallRng, n: u32) -> Str {
-    let mut s = Str::new(0);
-    for _ in 0..n {
-        s.push(rng.next_u32() % 2 == 0);
+fn check_par_batch<T: Trie + Sync>(ds: &T, xs: &[Str], name: &str) {
+    let pred = ds.pred_batch(xs);
+    let ex_pref = ds.ex_pref_batch(xs);
+    // more threads than queries too, and the default of one per core
+    for threads in [0, 1, 2, 3, 8, xs.len() + 5] {
+        assert!(par_query_batch(ds, xs, threads, |ds, xs| ds.pred_batch(xs)) == pred, "par_query_batch of {} with {} threads differs from pred_batch", name, threads);
+        assert!(par_query_batch(ds, xs, threads, |ds, xs| ds.ex_pref_batch(xs)) == ex_pref, "par_query_batch of {} with {} threads differs from ex_pref_batch", name, threads);
+        assert!(par_query_batch(ds, &xs[..0], threads, |ds, xs| ds.pred_batch(xs)).is_empty());
     }
-    s
 }
 
-pub fn crosstest<T1: Trie, T2: Trie>(
-    t: u32,
-    bits: u32,
-    n: u32,
-    m: u32,
-    deb: bool,
-    variablelen: bool,
-    fixed_seed: bool,
-    ds1: &mut T1,
-    ds2: &mut T2
-) {
-    let mut rng = {
-        if fixed_seed { SmallRng::seed_from_u64(0) } else { SmallRng::from_rng(thread_rng()) }
-    };
-
-    for _ in 0..t {
-        let mut v: Vec<Str> = vec![];
-        for _ in 0..n {
-            let len = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
-            let s = gen_bin_str(&mut rng, len);
-            let mut flag = true;
-
-            if variablelen {
-                //da ottimizzare
-                for i in &v {
-                    if get_substr(i,0,min(i.len(), s.len())) == *i || get_substr(i,0,min(i.len(), s.len())) == s {
-                        flag = false;
-                        continue;
-                    }
-                }
-            }
-            if flag {
-                v.push(s);
-            }
+#[test]
+fn test_par_query_batch() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for kind in KeySet::ALL {
+        let v = kind.generate(&mut rng, 300, 200);
+        let mut ds2 = Ds2::new();
+        let mut ds3 = Ds3::with_seed(0);
+        let mut ds4 = Ds4::with_seed(0);
+        ds2.build(&v);
+        ds3.build(&v);
+        ds4.build(&v);
+
+        let xs: Vec<Str> = (0..1000).map(|_| gen_query_key(&mut rng, &v, 200)).collect();
+        check_par_batch(&ds2, &xs, "compact");
+        check_par_batch(&ds3, &xs, "z_fast");
+        check_par_batch(&ds4, &xs, "z_fast_sux");
+    }
+}
+
+// the dot of a trie lists every node and every link, so equal dots mean equal structures;
+// par_build is the threaded construction, which the z-fast tries have outside of Trie
+fn check_par_build<T: Trie>(
+    new: impl Fn() -> T,
+    par_build: impl Fn(&mut T, &[Str], usize),
+    dot: impl Fn(&T) -> Vec<u8>,
+    v: &[Str],
+    name: &str
+) -> Vec<T> {
+    let mut ds = new();
+    ds.build(v);
+    let mut res = vec![];
+    for threads in [0, 1, 2, 3, 8, v.len() + 5] {
+        let mut ds2 = new();
+        par_build(&mut ds2, v, threads);
+        if let Err(e) = ds2.validate() {
+            panic!("par_build of {} with {} threads: {}", name, threads, e)
         }
+        assert!(dot(&ds2) == dot(&ds) && ds2.stats() == ds.stats(), "par_build of {} with {} threads gave another trie", name, threads);
+        res.push(ds2);
+    }
+    res
+}
 
-        v.sort_by(cmp);
-        if deb {
-            print!("genero:\n");
-            for i in &v {
-                print!("{}\n", i);
+#[test]
+fn test_par_build() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for kind in KeySet::ALL {
+        for n in [0, 1, 2, 60, 400] {
+            let v = kind.generate(&mut rng, n, 200);
+            let xs: Vec<Str> = (0..200).map(|_| gen_query_key(&mut rng, &v, 200)).collect();
+            let dot2 = |ds: &Ds2| { let mut w = vec![]; ds.to_dot(&mut w).unwrap(); w };
+            let dot3 = |ds: &Ds3| { let mut w = vec![]; ds.to_dot(&mut w).unwrap(); w };
+            let dot4 = |ds: &Ds4| { let mut w = vec![]; ds.to_dot(&mut w).unwrap(); w };
+            check_par_build(Ds2::new, |ds, v, t| ds.par_build(v, t), dot2, &v, "compact");
+            check_par_build(|| Ds3::with_seed(0), Ds3::par_build, dot3, &v, "z_fast");
+
+            // the minimal perfect hash is only seen through its answers
+            let mut ds4 = Ds4::with_seed(0);
+            ds4.build(&v);
+            for ds in check_par_build(|| Ds4::with_seed(0), Ds4::par_build, dot4, &v, "z_fast_sux") {
+                assert!(ds.pred_batch(&xs) == ds4.pred_batch(&xs), "par_build of z_fast_sux answers pred unlike build");
             }
-            print!("testo:\n");
-        }
-        ds1.build(&v);
-        ds2.build(&v);
 
-        for _ in 0..m {
-            let len = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
-            let mut s1 = gen_bin_str(&mut rng, len);
-            let len2 = {
-                if variablelen { (rng.next_u32() % ((bits / 4) * 3)) + bits / 4 } else { bits }
-            };
-            let mut s2 = gen_bin_str(&mut rng, len2);
-            if cmp(&s1,&s2) == Greater {
-                swap(&mut s1, &mut s2);
+            // the tiny hash collides, and the collisions and the z-map follow the preorder
+            let dot5 = |ds: &ZFastTrie<TinyHash>| { let mut w = vec![]; ds.to_dot(&mut w).unwrap(); w };
+            let mut ds5: ZFastTrie<TinyHash> = ZFastTrie::with_seed(0);
+            ds5.build(&v);
+            for ds in check_par_build(|| ZFastTrie::<TinyHash>::with_seed(0), ZFastTrie::par_build, dot5, &v, "z_fast_tiny_hash") {
+                assert!(ds.build_collisions() == ds5.build_collisions(), "par_build of z_fast_tiny_hash counted {} collisions instead of {}", ds.build_collisions(), ds5.build_collisions());
+                assert!(ds.pred_batch(&xs) == ds5.pred_batch(&xs), "par_build of z_fast_tiny_hash answers pred unlike build");
             }
-            if deb {
-                print!("query: {} & {}\n", s1, s2);
+            if n <= 60 {
+                let new = || { let mut ds = ZFastTrie::<TinyHash>::with_seed(0); ds.set_verify_handles(true); ds };
+                check_par_build(new, ZFastTrie::par_build, dot5, &v, "z_fast_tiny_hash_verified");
             }
+        }
+    }
+
+    let v = KeySet::Random.generate(&mut rng, 1000, 64);
+    assert!(par_sort(&v, 4) == { let mut x = v.clone(); x.sort_by(cmp); x });
+}
 
-            let pred1 = ds1.pred_query(&s1);
-            let pred2 = ds2.pred_query(&s1);
-            if deb {
-                if let Some(ref t) = pred1 {
-                    print!("pred: {}\n", t);
-                } else {
-                    print!("non ha predecessori\n");
+fn dot_nodes(dot: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(dot).lines().filter(|l| l.contains("label=\"[")).map(|l| l.to_string()).collect()
+}
+
+#[test]
+fn test_to_dot() {
+    let mut rng = SmallRng::seed_from_u64(0);
+    for kind in [KeySet::Random, KeySet::VariableLength, KeySet::SharedPrefix, KeySet::Skewed] {
+        let v = kind.generate(&mut rng, 30, 40);
+        let mut ds2 = Ds2::new();
+        let mut ds3 = Ds3::with_seed(0);
+        let mut ds4 = Ds4::with_seed(0);
+        ds2.build(&v);
+        ds3.build(&v);
+        ds4.build(&v);
+
+        let queries = gen_queries(&mut rng, &v, 20, 40);
+        for q in queries.iter().filter_map(|q| if let Query::Succ(x) = q { Some(x) } else { None }) {
+            let (mut dot2, mut dot3, mut dot4) = (vec![], vec![], vec![]);
+            ds2.to_dot_with_query(&mut dot2, q).unwrap();
+            ds3.to_dot_with_query(&mut dot3, q).unwrap();
+            ds4.to_dot_with_query(&mut dot4, q).unwrap();
+
+            // the tries have the same shape, visited in the same order
+            let nodes = dot_nodes(&dot2);
+            assert!(nodes.len() == 2 * v.len() - 1, "{} nodes drawn for {} keys", nodes.len(), v.len());
+            assert!(nodes == dot_nodes(&dot3) && nodes == dot_nodes(&dot4), "the tries draw different nodes on {:?}", kind);
+            assert!(nodes.iter().filter(|l| l.contains("orange")).count() == 1, "the exit node is not highlighted once");
+        }
+
+        let mut dot = vec![];
+        ds4.to_dot(&mut dot).unwrap();
+        let text = String::from_utf8(dot).unwrap();
+        assert!(text.starts_with("digraph") && !text.contains("fillcolor"), "dot without a query is not a plain digraph");
+        let edges = |style: &str| text.lines().filter(|l| l.contains("->") && l.contains(style)).count();
+        assert!(edges("dashed") == 2 * (v.len() - 1) && edges("dotted") == 2 * (v.len() - 1));
+    }
+
+    let mut dot = vec![];
+    Ds2::new().to_dot(&mut dot).unwrap();
+    assert!(dot_nodes(&dot).is_empty(), "dot of the empty trie has nodes");
+}
+
+// internal nodes and skip bits of the trie on v, built as in the tries
+fn naive_shape(v: &[Str], ind: usize) -> (usize, usize) {
+    if v.len() == 1 {
+        return (0, v[0].len() - ind);
+    }
+    let mut rind = ind;
+    while v.iter().all(|x| x[rind] == v[0][rind]) {
+        rind += 1;
+    }
+    let mid = v.iter().position(|x| x[rind]).unwrap();
+    let (l_nodes, l_skip) = naive_shape(&v[..mid], rind + 1);
+    let (r_nodes, r_skip) = naive_shape(&v[mid..], rind + 1);
+    (l_nodes + r_nodes + 1, l_skip + r_skip + rind - ind)
+}
+
+#[test]
+fn test_stats() {
+    let keys = vec![bits!("000"), bits!("0010"), bits!("01"), bits!("1")];
+    let stats = TrieStats::of_keys(&keys);
+    // root [0, 0], then [1, 1] and [2, 2]; the leaves have 0, 1, 0 and 0 bits
+    assert!(stats.internal_nodes == 3 && stats.skips.total() == 1, "{} internal nodes and {} skip bits", stats.internal_nodes, stats.skips.total());
+    assert!(stats.depths.count(3) == 2 && stats.depths.max() == 3, "the two deepest leaves are not at depth 3");
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for kind in KeySet::ALL {
+        for n in [1, 2, 50] {
+            let keys = kind.generate(&mut rng, n, 40);
+            let mut expected: Option<TrieStats> = None;
+            for name in TRIE_IMPLS {
+                // elias_fano only takes fixed width keys
+                let Ok(index) = Index::new(name, Encoding::Bits, 0, keys.clone()) else {
+                    continue;
+                };
+                let stats = index.build().dyn_stats();
+                if !index.keys.is_empty() {
+                    let shape = naive_shape(&index.keys, 0);
+                    assert!(
+                        (stats.internal_nodes, stats.skips.total()) == shape,
+                        "{} on {:?} has another shape than the naive trie", name, kind
+                    );
                 }
-            }
-            assert!(pred1 == pred2, "answers (pred) don't match\n {:?}\n {:?}\n", pred1, pred2);
-
-            let succ1 = ds1.succ_query(&s1);
-            let succ2 = ds2.succ_query(&s1);
-            if deb {
-                if let Some(ref t) = succ1 {
-                    print!("succ: {}\n", t);
-                } else {
-                    print!("non ha successori\n");
+                assert!(stats.nodes() == (2 * stats.keys).saturating_sub(1), "{} has {} nodes for {} keys", name, stats.nodes(), stats.keys);
+
+                match name {
+                    "z_fast" | "z_fast_sux" => {
+                        assert!(stats.z_map_entries == stats.internal_nodes, "z-map of {} has {} entries for {} internal nodes", name, stats.z_map_entries, stats.internal_nodes)
+                    }
+                    "elias_fano" => assert!(stats.keys == 0 || stats.rank_directory_bits > 0, "elias_fano has no rank directory"),
+                    _ => {}
+                }
+                if let Some(mphf) = &stats.mphf {
+                    assert!(mphf.load_factor <= 1.0 && mphf.attempts >= 1, "mphf with load factor {} after {} attempts", mphf.load_factor, mphf.attempts);
                 }
-            }
-            assert!(succ1 == succ2, "answers (succ) don't match");
 
-            let flag1 = ds1.ex_range_query(&s1, &s2);
-            let flag2 = ds2.ex_range_query(&s1, &s2);
-            if deb {
-                print!("range query: {}\n", flag1);
-                print!("------------\n");
+                match &expected {
+                    Some(e) => assert!(
+                        e.depths == stats.depths && e.skips == stats.skips && e.extent_bits == stats.extent_bits,
+                        "{} on {:?} has other depths, skips or extents than the first trie", name, kind
+                    ),
+                    None => expected = Some(stats),
+                }
             }
-            assert!(flag1 == flag2, "answer (range query) don't match\n {}\n {}\n", flag1, flag2);
         }
     }
+    assert!(TrieStats::of_keys::<Str>(&[]).nodes() == 0, "the empty trie has nodes");
+}
+
+#[cfg(test)]
+mod cross_tests {
+    use crate::*;
+
+    #[test]
+    fn test_compact_and_oracle_fixed() {
+        let mut ds1: Ds2 = Ds2::new();
+
+        Differential::new(thread_rng().next_u64())
+            .set_kinds(&KeySet::FIXED_WIDTH)
+            .set_sizes(5, 100, 100, 5000)
+            .add("compact", &mut ds1)
+            .run();
+    }
+
+    #[test]
+    fn test_compact_and_oracle_variable() {
+        let mut ds1: Ds2 = Ds2::new();
+
+        Differential::new(0)
+            .set_sizes(5, 1000, 100, 5000)
+            .add("compact", &mut ds1)
+            .run();
+    }
+
+    #[test]
+    fn test_compact_and_z_fast_variable() {
+        let mut ds1: Ds2 = Ds2::new();
+        let mut ds2: Ds3 = Ds3::with_seed(0);
+
+        Differential::new(0)
+            .set_sizes(5, 1000, 100, 5000)
+            .add("compact", &mut ds1)
+            .add("z_fast", &mut ds2)
+            .run();
+    }
+
+    #[test]
+    fn test_compact_and_z_fast_fixed() {
+        let mut ds1: Ds2 = Ds2::new();
+        let mut ds2: Ds3 = Ds3::with_seed(0);
+
+        Differential::new(0)
+            .set_kinds(&KeySet::FIXED_WIDTH)
+            .set_sizes(5, 1000, 200, 5000)
+            .add("compact", &mut ds1)
+            .add("z_fast", &mut ds2)
+            .run();
+    }
+
+    #[test]
+    fn test_compact_and_z_fast_fixed_small() {
+        let mut ds1: Ds2 = Ds2::new();
+        let mut ds2: Ds3 = Ds3::with_seed(0);
+
+        Differential::new(0)
+            .set_kinds(&KeySet::FIXED_WIDTH)
+            .set_sizes(5, 1000, 30, 5000)
+            .add("compact", &mut ds1)
+            .add("z_fast", &mut ds2)
+            .run();
+    }
+
+    #[test]
+    fn test_z_fast_and_z_fast_sux_variable() {
+        let mut ds1: Ds3 = Ds3::with_seed(0);
+        let mut ds2: Ds4 = Ds4::with_seed(0);
+
+        Differential::new(0)
+            .set_sizes(5, 1000, 200, 5000)
+            .add("z_fast", &mut ds1)
+            .add("z_fast_sux", &mut ds2)
+            .run();
+    }
+
+    #[test]
+    fn test_z_fast_and_z_fast_sux_fixed() {
+        let mut ds1: Ds3 = Ds3::with_seed(0);
+        let mut ds2: Ds4 = Ds4::with_seed(0);
+
+        Differential::new(0)
+            .set_kinds(&KeySet::FIXED_WIDTH)
+            .set_sizes(5, 1000, 200, 5000)
+            .add("z_fast", &mut ds1)
+            .add("z_fast_sux", &mut ds2)
+            .run();
+    }
+
+    #[test]
+    fn test_z_fast_and_z_fast_sux_seeded() {
+        let mut ds1: Ds3 = Ds3::with_seed(42);
+        let mut ds2: Ds4 = Ds4::with_seed(42);
+        assert!(ds1.hash().seed() == 42 && ds2.seed() == 42);
+
+        Differential::new(0)
+            .set_sizes(5, 1000, 200, 5000)
+            .add("z_fast", &mut ds1)
+            .add("z_fast_sux", &mut ds2)
+            .run();
+    }
+
+    #[test]
+    fn test_z_fast_and_elias_fano_fixed_small() {
+        let mut ds1: Ds3 = Ds3::with_seed(0);
+        let mut ds2: Ds5 = Ds5::new();
+
+        Differential::new(0)
+            .set_kinds(&KeySet::FIXED_WIDTH)
+            .set_sizes(5, 1000, 30, 5000)
+            .add("z_fast", &mut ds1)
+            .add("elias_fano", &mut ds2)
+            .run();
+    }
+
+    #[test]
+    fn test_z_fast_sux_and_elias_fano_fixed_word() {
+        let mut ds1: Ds4 = Ds4::with_seed(0);
+        let mut ds2: Ds5 = Ds5::new();
+
+        Differential::new(0)
+            .set_kinds(&KeySet::FIXED_WIDTH)
+            .set_sizes(5, 1000, 64, 5000)
+            .add("z_fast_sux", &mut ds1)
+            .add("elias_fano", &mut ds2)
+            .run();
+    }
+
+    #[test]
+    fn test_oracle_and_elias_fano_fixed_tiny() {
+        let mut ds1: Ds5 = Ds5::new();
+
+        Differential::new(0)
+            .set_kinds(&KeySet::FIXED_WIDTH)
+            .set_sizes(20, 20, 6, 500)
+            .add("elias_fano", &mut ds1)
+            .run();
+    }
+
+    #[test]
+    fn test_z_fast_and_z_fast_sux_fixed_small() {
+        let mut ds1: Ds3 = Ds3::with_seed(0);
+        let mut ds2: Ds4 = Ds4::with_seed(0);
+
+        Differential::new(0)
+            .set_kinds(&KeySet::FIXED_WIDTH)
+            .set_sizes(5, 1000, 30, 5000)
+            .add("z_fast", &mut ds1)
+            .add("z_fast_sux", &mut ds2)
+            .run();
+    }
 }